@@ -1,5 +1,7 @@
+use jxpoolminer_core::{Algorithm, BenchmarkResults, DeviceSettings};
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, Context};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::fs;
 
@@ -14,12 +16,28 @@ pub struct Config {
 pub struct AppConfig {
     pub theme: String,
     pub language: String,
+    /// Start mining on every device as soon as the miner launches.
+    #[serde(default)]
+    pub auto_start: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MiningConfig {
     pub auto_detect_devices: bool,
     pub auto_assign_algorithms: bool,
+    /// Mining threads per CPU device; one per core when unset.
+    #[serde(default)]
+    pub cpu_threads: Option<usize>,
+    /// Resource limits per device id, e.g. `[mining.devices.cpu-0]`.
+    #[serde(default)]
+    pub devices: BTreeMap<String, DeviceSettings>,
+    /// Network ASICs to manage through their cgminer API, as `host` or
+    /// `host:port`.
+    #[serde(default)]
+    pub asics: Vec<String>,
+    /// Subnet searched for more ASICs at startup, e.g. `192.168.1.0/24`.
+    #[serde(default)]
+    pub asic_scan: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +47,26 @@ pub struct PoolConfig {
     pub wallet_address: String,
     pub worker_name: String,
     pub use_tls: bool,
+    /// PEM bundle of CAs to trust for TLS pools instead of the web roots.
+    #[serde(default)]
+    pub tls_ca_file: Option<PathBuf>,
+    /// SHA-256 fingerprint the pool's TLS certificate must match.
+    #[serde(default)]
+    pub tls_pinned_sha256: Option<String>,
+    /// Skip TLS certificate verification (self-signed local pools only).
+    #[serde(default)]
+    pub tls_insecure: bool,
+    /// Algorithm the pool's jobs are mined with.
+    #[serde(default = "default_pool_algorithm")]
+    pub algorithm: Algorithm,
+    /// Version of `algorithm` the pool expects, for versioned algorithms
+    /// such as GXHash; the original one when unset.
+    #[serde(default)]
+    pub algorithm_version: Option<u32>,
+}
+
+fn default_pool_algorithm() -> Algorithm {
+    Algorithm::SHA256
 }
 
 impl Default for Config {
@@ -37,10 +75,15 @@ impl Default for Config {
             app: AppConfig {
                 theme: "dark".to_string(),
                 language: "en".to_string(),
+                auto_start: false,
             },
             mining: MiningConfig {
                 auto_detect_devices: true,
                 auto_assign_algorithms: true,
+                cpu_threads: None,
+                devices: BTreeMap::new(),
+                asics: vec![],
+                asic_scan: None,
             },
             pool: PoolConfig {
                 primary: "stratum+tcp://localhost:3333".to_string(),
@@ -48,19 +91,32 @@ impl Default for Config {
                 wallet_address: "GXC_YOUR_WALLET_ADDRESS_HERE".to_string(),
                 worker_name: "worker1".to_string(),
                 use_tls: false,
+                tls_ca_file: None,
+                tls_pinned_sha256: None,
+                tls_insecure: false,
+                algorithm: Algorithm::SHA256,
+                algorithm_version: None,
             },
         }
     }
 }
 
-fn get_config_path() -> PathBuf {
+fn get_config_dir() -> PathBuf {
     let mut path = dirs::config_dir()
         .unwrap_or_else(|| PathBuf::from("."));
     path.push("jxpoolminer");
-    path.push("config.toml");
     path
 }
 
+fn get_config_path() -> PathBuf {
+    get_config_dir().join("config.toml")
+}
+
+/// Where measured device hashrates are kept, next to the config file.
+pub fn benchmarks_path() -> PathBuf {
+    get_config_dir().join("benchmarks.toml")
+}
+
 pub fn load_config() -> Result<Config> {
     let config_path = get_config_path();
     
@@ -92,3 +148,28 @@ pub fn save_config(config: &Config) -> Result<()> {
     
     Ok(())
 }
+
+/// Stored benchmark results; empty if nothing was benchmarked yet.
+pub fn load_benchmarks() -> Result<BenchmarkResults> {
+    let path = benchmarks_path();
+    if !path.exists() {
+        return Ok(BenchmarkResults::default());
+    }
+    let contents = fs::read_to_string(&path)
+        .context("Failed to read benchmark results")?;
+    toml::from_str(&contents)
+        .context("Failed to parse benchmark results")
+}
+
+pub fn save_benchmarks(results: &BenchmarkResults) -> Result<()> {
+    let path = benchmarks_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .context("Failed to create config directory")?;
+    }
+    let contents = toml::to_string_pretty(results)
+        .context("Failed to serialize benchmark results")?;
+    fs::write(&path, contents)
+        .context("Failed to write benchmark results")?;
+    Ok(())
+}
@@ -1,26 +1,62 @@
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
-pub enum Algorithm {
-    SHA256,
-    Ethash,
-    GXHash,
-}
+/// A proof-of-work algorithm, named by the id its implementation is
+/// registered under in the mining crate's registry, e.g. `"sha256"`. The
+/// built-ins have constants; an algorithm registered by another crate is
+/// named with `Algorithm::new`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Algorithm(Cow<'static, str>);
 
 impl Algorithm {
-    pub fn name(&self) -> &'static str {
-        match self {
-            Algorithm::SHA256 => "SHA-256",
-            Algorithm::Ethash => "Ethash",
-            Algorithm::GXHash => "GXHash",
-        }
+    pub const SHA256: Algorithm = Algorithm(Cow::Borrowed("sha256"));
+    pub const ETHASH: Algorithm = Algorithm(Cow::Borrowed("ethash"));
+    /// Ethash on Ethereum Classic's epoch schedule (ECIP-1099).
+    pub const ETCHASH: Algorithm = Algorithm(Cow::Borrowed("etchash"));
+    pub const GXHASH: Algorithm = Algorithm(Cow::Borrowed("gxhash"));
+
+    pub fn new(id: impl Into<Cow<'static, str>>) -> Self {
+        Self(id.into())
+    }
+
+    /// Key the algorithm's implementation is registered under.
+    pub fn id(&self) -> &str {
+        &self.0
     }
-    
+
     pub fn for_device(device_type: &super::DeviceType) -> Self {
         match device_type {
             super::DeviceType::ASIC => Algorithm::SHA256,
-            super::DeviceType::GPU { .. } => Algorithm::Ethash,
-            super::DeviceType::CPU { .. } => Algorithm::GXHash,
+            super::DeviceType::GPU { .. } => Algorithm::ETHASH,
+            super::DeviceType::CPU { .. } => Algorithm::GXHASH,
+        }
+    }
+}
+
+/// Also accepts the names configs and benchmark files used before
+/// algorithms were keyed by id.
+impl From<String> for Algorithm {
+    fn from(id: String) -> Self {
+        match id.as_str() {
+            "SHA256" => Algorithm::SHA256,
+            "Ethash" => Algorithm::ETHASH,
+            "Etchash" => Algorithm::ETCHASH,
+            "GXHash" => Algorithm::GXHASH,
+            _ => Algorithm(Cow::Owned(id)),
         }
     }
 }
+
+impl From<Algorithm> for String {
+    fn from(algorithm: Algorithm) -> Self {
+        algorithm.0.into_owned()
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
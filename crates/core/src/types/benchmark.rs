@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+
+/// One device measured on one algorithm.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub device_id: String,
+    pub device_name: String,
+    pub algorithm: crate::Algorithm,
+    /// Mean over the measured intervals, in hashes per second.
+    pub hashrate: f64,
+    /// Standard deviation of the per-interval hashrates.
+    pub stddev: f64,
+    /// Hashrate of every interval, in order.
+    pub interval_hashrates: Vec<f64>,
+    /// Average power draw while measuring, when the device reports it.
+    #[serde(default)]
+    pub power_watts: Option<f64>,
+    pub duration_secs: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl BenchmarkResult {
+    /// Hashes per joule, when power was readable.
+    pub fn hashes_per_watt(&self) -> Option<f64> {
+        self.power_watts.filter(|&watts| watts > 0.0).map(|watts| self.hashrate / watts)
+    }
+
+    /// Standard deviation relative to the mean; lower is steadier.
+    pub fn variation(&self) -> f64 {
+        if self.hashrate > 0.0 { self.stddev / self.hashrate } else { 0.0 }
+    }
+}
+
+/// Latest benchmark of every device and algorithm.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BenchmarkResults {
+    #[serde(default)]
+    pub results: Vec<BenchmarkResult>,
+}
+
+impl BenchmarkResults {
+    /// Adds `result`, replacing any earlier one for the same device and
+    /// algorithm.
+    pub fn record(&mut self, result: BenchmarkResult) {
+        self.results.retain(|r| r.device_id != result.device_id || r.algorithm != result.algorithm);
+        self.results.push(result);
+    }
+
+    pub fn get(&self, device_id: &str, algorithm: &crate::Algorithm) -> Option<&BenchmarkResult> {
+        self.results.iter().find(|r| r.device_id == device_id && r.algorithm == *algorithm)
+    }
+
+    /// Sets each device's `max_hashrate` from the benchmark of the first of
+    /// its algorithms that has one. Devices never benchmarked keep theirs.
+    pub fn apply(&self, devices: &mut [crate::Device]) {
+        for device in devices {
+            let measured = device.capabilities.supported_algorithms.iter()
+                .find_map(|algorithm| self.get(&device.id, algorithm));
+            if let Some(result) = measured {
+                device.capabilities.max_hashrate = result.hashrate;
+            }
+        }
+    }
+}
@@ -28,6 +28,139 @@ pub struct DeviceCapabilities {
     pub max_hashrate: f64,
     pub memory: u64,
     pub supported_algorithms: Vec<crate::Algorithm>,
+    /// SIMD and hashing extensions of a CPU device; empty for other kinds.
+    #[serde(default)]
+    pub cpu_features: Vec<CpuFeature>,
+}
+
+/// x86 instruction set extensions the SHA-256 kernels can use.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum CpuFeature {
+    Sse41,
+    Avx2,
+    Avx512F,
+    ShaNi,
+}
+
+impl CpuFeature {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CpuFeature::Sse41 => "SSE4.1",
+            CpuFeature::Avx2 => "AVX2",
+            CpuFeature::Avx512F => "AVX-512F",
+            CpuFeature::ShaNi => "SHA-NI",
+        }
+    }
+}
+
+/// Per-device limits on how much of the machine mining may use. Only CPU
+/// devices honour them so far.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DeviceSettings {
+    /// Mining threads; one per core when unset.
+    #[serde(default)]
+    pub threads: Option<usize>,
+    /// Share of the time spent hashing, from 0.01 to 1.
+    #[serde(default = "full_duty_cycle")]
+    pub duty_cycle: f64,
+    /// Scheduling niceness of the mining threads, 0 (normal) to 19 (lowest).
+    #[serde(default)]
+    pub nice: i32,
+    /// Cores the mining threads are pinned to, round robin; none pins
+    /// nothing.
+    #[serde(default)]
+    pub affinity: Vec<usize>,
+    /// Temperatures at which mining is slowed down and stopped.
+    #[serde(default)]
+    pub thermal: ThermalLimits,
+}
+
+fn full_duty_cycle() -> f64 {
+    1.0
+}
+
+impl Default for DeviceSettings {
+    fn default() -> Self {
+        Self {
+            threads: None,
+            duty_cycle: full_duty_cycle(),
+            nice: 0,
+            affinity: vec![],
+            thermal: ThermalLimits::default(),
+        }
+    }
+}
+
+impl DeviceSettings {
+    pub const MIN_DUTY_CYCLE: f64 = 0.01;
+    pub const MAX_NICE: i32 = 19;
+
+    /// The settings with every value brought into its valid range.
+    pub fn clamped(&self) -> Self {
+        Self {
+            threads: self.threads.map(|threads| threads.max(1)),
+            duty_cycle: if self.duty_cycle.is_nan() { 1.0 } else { self.duty_cycle.clamp(Self::MIN_DUTY_CYCLE, 1.0) },
+            nice: self.nice.clamp(0, Self::MAX_NICE),
+            affinity: self.affinity.clone(),
+            thermal: self.thermal.clamped(),
+        }
+    }
+}
+
+/// Temperature limits of a device, in °C. Above `target` mining is
+/// throttled, at `critical` it stops, and either is undone once the device
+/// has cooled to `hysteresis` degrees below `target`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ThermalLimits {
+    #[serde(default = "default_target_temp")]
+    pub target: f32,
+    #[serde(default = "default_critical_temp")]
+    pub critical: f32,
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: f32,
+}
+
+fn default_target_temp() -> f32 {
+    85.0
+}
+
+fn default_critical_temp() -> f32 {
+    95.0
+}
+
+fn default_hysteresis() -> f32 {
+    5.0
+}
+
+impl Default for ThermalLimits {
+    fn default() -> Self {
+        Self {
+            target: default_target_temp(),
+            critical: default_critical_temp(),
+            hysteresis: default_hysteresis(),
+        }
+    }
+}
+
+impl ThermalLimits {
+    /// The limits with `critical` at or above `target` and a non-negative
+    /// hysteresis.
+    pub fn clamped(&self) -> Self {
+        let defaults = Self::default();
+        let target = if self.target.is_nan() { defaults.target } else { self.target };
+        let critical = if self.critical.is_nan() { defaults.critical } else { self.critical };
+        let hysteresis = if self.hysteresis.is_nan() { defaults.hysteresis } else { self.hysteresis };
+        Self {
+            target,
+            critical: critical.max(target),
+            hysteresis: hysteresis.max(0.0),
+        }
+    }
+
+    /// Temperature a device has to cool to before it speeds up again.
+    pub fn resume_below(&self) -> f32 {
+        self.target - self.hysteresis
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -47,6 +180,7 @@ impl Device {
                 max_hashrate: 0.0,
                 memory: 0,
                 supported_algorithms: vec![],
+                cpu_features: vec![],
             },
             status: DeviceStatus::Idle,
         }
@@ -5,10 +5,49 @@ use chrono::{DateTime, Utc};
 pub struct MiningJob {
     pub id: String,
     pub algorithm: super::Algorithm,
+    /// First 76 bytes of the block header, to which the miner appends the
+    /// nonce; for Ethash, the 32-byte header hash.
     pub header: Vec<u8>,
+    /// Share target as a 32-byte big-endian integer. Left empty for the
+    /// engine to derive from `difficulty` with the algorithm's difficulty-1
+    /// target.
     pub target: Vec<u8>,
+    /// Pool difficulty `target` was derived from, or that it stands for
+    /// when the pool sent the target.
     pub difficulty: f64,
     pub timestamp: DateTime<Utc>,
+    /// Previous block hash in header byte order.
+    #[serde(default)]
+    pub prev_hash: Vec<u8>,
+    /// Coinbase transaction up to the extranonce.
+    #[serde(default)]
+    pub coinbase1: Vec<u8>,
+    /// Coinbase transaction after the extranonce.
+    #[serde(default)]
+    pub coinbase2: Vec<u8>,
+    /// Merkle branch hashes in the order they are folded into the root.
+    #[serde(default)]
+    pub merkle_branches: Vec<Vec<u8>>,
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub nbits: u32,
+    #[serde(default)]
+    pub ntime: u32,
+    /// Set when the pool wants all previous jobs abandoned.
+    #[serde(default)]
+    pub clean_jobs: bool,
+    #[serde(default)]
+    pub extranonce1: Vec<u8>,
+    #[serde(default)]
+    pub extranonce2_size: usize,
+    /// Ethash seed hash, which selects the epoch's cache and DAG.
+    #[serde(default)]
+    pub seed_hash: Vec<u8>,
+    /// Proof-of-work revision the pool expects, for algorithms that are
+    /// versioned (GXHash); `None` means the algorithm's original one.
+    #[serde(default)]
+    pub algorithm_version: Option<u32>,
 }
 
 impl MiningJob {
@@ -17,9 +56,26 @@ impl MiningJob {
             id,
             algorithm,
             header: vec![],
-            target: vec![0xFF; 32],
+            target: vec![],
             difficulty,
             timestamp: Utc::now(),
+            prev_hash: vec![0; 32],
+            coinbase1: vec![],
+            coinbase2: vec![],
+            merkle_branches: vec![],
+            version: 0,
+            nbits: 0,
+            ntime: 0,
+            clean_jobs: false,
+            extranonce1: vec![],
+            extranonce2_size: 0,
+            seed_hash: vec![],
+            algorithm_version: None,
         }
     }
+    
+    /// Whether the job carries a coinbase the miner must build a header from.
+    pub fn has_coinbase(&self) -> bool {
+        !self.coinbase1.is_empty() || !self.coinbase2.is_empty()
+    }
 }
@@ -2,8 +2,12 @@ pub mod device;
 pub mod algorithm;
 pub mod share;
 pub mod job;
+pub mod target;
+pub mod benchmark;
 
 pub use device::*;
 pub use algorithm::*;
 pub use share::*;
 pub use job::*;
+pub use benchmark::*;
+pub use target::{difficulty_to_target, target_to_difficulty, work_for_difficulty, meets_target, SHA256_DIFF1};
@@ -7,16 +7,36 @@ pub struct Share {
     pub nonce: u64,
     pub hash: Vec<u8>,
     pub device_id: String,
+    /// Pool difficulty of the job the share was found for.
+    #[serde(default)]
+    pub difficulty: f64,
+    /// Extranonce2 the share's coinbase was built with.
+    #[serde(default)]
+    pub extranonce2: Vec<u8>,
+    /// Header time the share was hashed with.
+    #[serde(default)]
+    pub ntime: u32,
+    /// Ethash mix digest, submitted alongside the nonce.
+    #[serde(default)]
+    pub mix_digest: Option<Vec<u8>>,
+    /// Ethash header hash the share was mined on, submitted with it.
+    #[serde(default)]
+    pub header_hash: Option<Vec<u8>>,
     pub timestamp: DateTime<Utc>,
 }
 
 impl Share {
-    pub fn new(job_id: String, nonce: u64, hash: Vec<u8>, device_id: String) -> Self {
+    pub fn new(job_id: String, nonce: u64, hash: Vec<u8>, device_id: String, difficulty: f64) -> Self {
         Self {
             job_id,
             nonce,
             hash,
             device_id,
+            difficulty,
+            extranonce2: vec![],
+            ntime: 0,
+            mix_digest: None,
+            header_hash: None,
             timestamp: Utc::now(),
         }
     }
@@ -0,0 +1,113 @@
+//! Conversions between pool difficulty and 256-bit share targets.
+//!
+//! Targets are 32-byte big-endian integers. A hash meets a target when,
+//! read as an integer in its algorithm's byte order, it is not above it.
+//! Each algorithm's difficulty-1 target comes from its implementation in
+//! the mining crate.
+
+/// Bitcoin's pool difficulty-1 target, `0x00000000ffff0000...`, shared by
+/// the algorithms that reuse its header and difficulty scale.
+pub const SHA256_DIFF1: [u8; 32] = {
+    let mut target = [0u8; 32];
+    target[4] = 0xff;
+    target[5] = 0xff;
+    target
+};
+
+/// `diff1 / difficulty`, saturating at the all-ones target for
+/// difficulties at or below zero.
+pub fn difficulty_to_target(diff1: &[u8; 32], difficulty: f64) -> [u8; 32] {
+    if difficulty <= 0.0 || !difficulty.is_finite() {
+        return [0xff; 32];
+    }
+    from_f64(to_f64(diff1) / difficulty)
+}
+
+/// Expected number of hashes to find one share at `difficulty`.
+pub fn work_for_difficulty(diff1: &[u8; 32], difficulty: f64) -> f64 {
+    difficulty * 2f64.powi(256) / (to_f64(diff1) + 1.0)
+}
+
+pub fn target_to_difficulty(diff1: &[u8; 32], target: &[u8]) -> f64 {
+    let target = to_f64(target);
+    if target == 0.0 {
+        return f64::INFINITY;
+    }
+    to_f64(diff1) / target
+}
+
+/// Big-endian comparison of a hash against a target of the same width.
+pub fn meets_target(hash: &[u8], target: &[u8]) -> bool {
+    hash.iter().cmp(target.iter()) != std::cmp::Ordering::Greater
+}
+
+fn to_f64(value: &[u8]) -> f64 {
+    value.iter().fold(0.0, |acc, &byte| acc * 256.0 + byte as f64)
+}
+
+fn from_f64(value: f64) -> [u8; 32] {
+    if value >= 2f64.powi(256) {
+        return [0xff; 32];
+    }
+    
+    let mut out = [0u8; 32];
+    let mut remainder = value.floor();
+    for (i, byte) in out.iter_mut().enumerate() {
+        let scale = 2f64.powi(8 * (31 - i as i32));
+        let digit = (remainder / scale).floor().min(255.0);
+        *byte = digit as u8;
+        remainder -= digit * scale;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex32(s: &str) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        out
+    }
+
+    #[test]
+    fn test_sha256_targets() {
+        assert_eq!(
+            difficulty_to_target(&SHA256_DIFF1, 1.0),
+            hex32("00000000ffff0000000000000000000000000000000000000000000000000000")
+        );
+        assert_eq!(
+            difficulty_to_target(&SHA256_DIFF1, 16.0),
+            hex32("000000000ffff000000000000000000000000000000000000000000000000000")
+        );
+        assert_eq!(
+            difficulty_to_target(&SHA256_DIFF1, 0.5),
+            hex32("00000001fffe0000000000000000000000000000000000000000000000000000")
+        );
+        let target = difficulty_to_target(&SHA256_DIFF1, 1024.0);
+        assert_eq!(target_to_difficulty(&SHA256_DIFF1, &target), 1024.0);
+    }
+
+    #[test]
+    fn test_ethash_boundary() {
+        assert_eq!(difficulty_to_target(&[0xff; 32], 1.0), [0xff; 32]);
+        assert_eq!(
+            difficulty_to_target(&[0xff; 32], 4_000_000_000.0)[..5],
+            hex32("0000000112e0be826d694b2e62d01511f12a6061fbaec8bc02357593e70e52ba")[..5]
+        );
+    }
+
+    #[test]
+    fn test_work_and_comparison() {
+        assert_eq!(work_for_difficulty(&SHA256_DIFF1, 1.0).round(), 4_295_032_833.0);
+        assert_eq!(work_for_difficulty(&[0xff; 32], 2.0), 2.0);
+        
+        let target = difficulty_to_target(&SHA256_DIFF1, 1.0);
+        assert!(meets_target(&target, &target));
+        assert!(meets_target(&[0u8; 32], &target));
+        assert!(!meets_target(&[0xff; 32], &target));
+    }
+}
@@ -1,5 +1,13 @@
-use jxpoolminer_core::{Device, DeviceType, DeviceCapabilities, Algorithm};
+use crate::cgminer::CgminerClient;
+use jxpoolminer_core::{Device, DeviceType, DeviceCapabilities, DeviceStatus, Algorithm};
+use jxpoolminer_stats::Collector;
 use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// How often network ASICs are asked for their status.
+const MONITOR_INTERVAL: Duration = Duration::from_secs(5);
 
 pub async fn detect() -> Result<Vec<Device>> {
     let mut devices = Vec::new();
@@ -42,6 +50,7 @@ async fn detect_linux() -> Result<Vec<Device>> {
                         max_hashrate: hashrate,
                         memory: 0,
                         supported_algorithms: vec![Algorithm::SHA256],
+                        cpu_features: vec![],
                     },
                     status: jxpoolminer_core::DeviceStatus::Idle,
                 });
@@ -49,38 +58,9 @@ async fn detect_linux() -> Result<Vec<Device>> {
         }
     }
     
-    if let Ok(entries) = std::fs::read_dir("/dev") {
-        for entry in entries.flatten() {
-            let name = entry.file_name();
-            let name_str = name.to_string_lossy();
-            
-            if name_str.starts_with("ttyUSB") || name_str.starts_with("ttyACM") {
-                if let Ok(device) = detect_cgminer_device(&entry.path()).await {
-                    devices.push(device);
-                }
-            }
-        }
-    }
-    
     Ok(devices)
 }
 
-async fn detect_cgminer_device(path: &std::path::Path) -> Result<Device> {
-    let name = format!("ASIC Miner ({})", path.display());
-    
-    Ok(Device {
-        id: format!("asic-{}", path.display()),
-        name,
-        device_type: DeviceType::ASIC,
-        capabilities: DeviceCapabilities {
-            max_hashrate: 100_000_000_000_000.0,
-            memory: 0,
-            supported_algorithms: vec![Algorithm::SHA256],
-        },
-        status: jxpoolminer_core::DeviceStatus::Idle,
-    })
-}
-
 fn extract_asic_name(line: &str) -> String {
     if let Some(pos) = line.find("ID") {
         if let Some(desc_start) = line[pos..].find(char::is_alphabetic) {
@@ -101,9 +81,137 @@ fn estimate_asic_hashrate(name: &str) -> f64 {
         14_000_000_000_000.0
     } else if name_lower.contains("whatsminer") {
         100_000_000_000_000.0
-    } else if name_lower.contains("avalon") {
-        50_000_000_000_000.0
     } else {
+        // Avalon and unknown models
         50_000_000_000_000.0
     }
 }
+
+/// An ASIC on the network that mines on its own, managed and watched
+/// through its cgminer API. Its device ID is `asic-<host>`, stable for as
+/// long as the miner keeps its address.
+pub struct NetworkAsic {
+    pub device: Device,
+    client: CgminerClient,
+}
+
+impl NetworkAsic {
+    /// Reaches the miner at `address` (`host` or `host:port`) and describes
+    /// it from its `summary` and `stats`.
+    pub async fn connect(address: &str) -> Result<Self> {
+        Self::from_client(CgminerClient::new(address)).await
+    }
+
+    pub async fn from_client(client: CgminerClient) -> Result<Self> {
+        let summary = client.summary().await?;
+        let stats = client.stats().await?;
+        let model = stats.model.unwrap_or_else(|| "ASIC Miner".to_string());
+        let host = client.address().rsplit_once(':').map_or(client.address(), |(host, _)| host);
+
+        let device = Device {
+            id: format!("asic-{}", host),
+            name: format!("{} ({})", model, host),
+            device_type: DeviceType::ASIC,
+            capabilities: DeviceCapabilities {
+                max_hashrate: summary.average_hashrate.max(summary.hashrate),
+                memory: 0,
+                supported_algorithms: vec![Algorithm::SHA256],
+                cpu_features: vec![],
+            },
+            status: if summary.hashrate > 0.0 { DeviceStatus::Mining } else { DeviceStatus::Idle },
+        };
+        Ok(Self { device, client })
+    }
+
+    pub fn client(&self) -> &CgminerClient {
+        &self.client
+    }
+
+    /// Copies hashrate, hottest temperature, fastest fan and hardware
+    /// errors into `collector`.
+    pub async fn report(&self, collector: &Collector) -> Result<()> {
+        let summary = self.client.summary().await?;
+        let stats = self.client.stats().await?;
+        let id = &self.device.id;
+        collector.record_hashrate(id, summary.hashrate).await;
+        collector.update_hardware_errors(id, summary.hardware_errors).await;
+        if let Some(temperature) = stats.max_temperature() {
+            collector.update_temperature(id, temperature).await;
+        }
+        if let Some(&rpm) = stats.fan_speeds.iter().max() {
+            collector.update_fan_speed(id, rpm).await;
+        }
+        Ok(())
+    }
+}
+
+/// Connects to every address, logging and leaving out the ones that do
+/// not answer.
+pub async fn detect_network(addresses: &[String]) -> Vec<NetworkAsic> {
+    let mut asics = Vec::new();
+    for address in addresses {
+        match NetworkAsic::connect(address).await {
+            Ok(asic) => {
+                tracing::info!("Found {} at {}", asic.device.name, asic.client.address());
+                asics.push(asic);
+            }
+            Err(e) => tracing::warn!("No cgminer API at {}: {}", address, e),
+        }
+    }
+    asics
+}
+
+/// Reports every ASIC to `collector` periodically until aborted.
+pub fn monitor(asics: Vec<NetworkAsic>, collector: Arc<Collector>) -> JoinHandle<()> {
+    monitor_every(asics, collector, MONITOR_INTERVAL)
+}
+
+fn monitor_every(asics: Vec<NetworkAsic>, collector: Arc<Collector>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            for asic in &asics {
+                if let Err(e) = asic.report(&collector).await {
+                    tracing::warn!("Cannot read {}: {}", asic.device.id, e);
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgminer::tests::FakeMiner;
+
+    #[tokio::test]
+    async fn test_network_asic_is_described_and_monitored() {
+        let miner = FakeMiner::start().await;
+        let asics = detect_network(&[miner.address.clone(), "127.0.0.1:1".to_string()]).await;
+        assert_eq!(asics.len(), 1);
+        let device = asics[0].device.clone();
+        assert_eq!(device.id, "asic-127.0.0.1");
+        assert_eq!(device.name, "Antminer S9 (127.0.0.1)");
+        assert_eq!(device.capabilities.max_hashrate, 13612.45e9);
+        assert_eq!(device.status, DeviceStatus::Mining);
+
+        let collector = Arc::new(Collector::new());
+        let task = monitor_every(asics, collector.clone(), Duration::from_millis(10));
+        let stats = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(stats) = collector.device_stats(&device.id).await {
+                    if stats.fan_speed > 0 {
+                        return stats;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("no ASIC stats");
+        task.abort();
+        assert_eq!(stats.hashrate, 13612.45e9);
+        assert_eq!(stats.hardware_errors, 42);
+        assert_eq!(stats.temperature, 78.0);
+        assert_eq!(stats.fan_speed, 5880);
+    }
+}
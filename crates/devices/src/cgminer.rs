@@ -0,0 +1,409 @@
+//! Client for the cgminer JSON API on TCP port 4028, which bmminer,
+//! btminer and most other ASIC firmware serve too. Each command is one
+//! connection: a JSON request out, a JSON reply back until the miner closes
+//! the socket.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const DEFAULT_PORT: u16 = 4028;
+
+/// How long a command may take, connect included.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Hashrate fields as firmwares name them, with their unit in H/s. The
+/// first present wins.
+const CURRENT_HASHRATE: &[(&str, f64)] = &[("GHS 5s", 1e9), ("MHS 5s", 1e6), ("KHS 5s", 1e3)];
+const AVERAGE_HASHRATE: &[(&str, f64)] = &[("GHS av", 1e9), ("MHS av", 1e6), ("KHS av", 1e3)];
+
+/// Totals from `summary`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// Over the last few seconds, in H/s.
+    pub hashrate: f64,
+    /// Since the miner started, in H/s.
+    pub average_hashrate: f64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub hardware_errors: u64,
+    /// Seconds since the miner started.
+    pub elapsed: u64,
+}
+
+/// One hashboard or chain from `devs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dev {
+    pub id: u64,
+    pub name: String,
+    /// e.g. `Alive` or `Dead`.
+    pub status: String,
+    pub temperature: Option<f32>,
+    pub hashrate: f64,
+    pub hardware_errors: u64,
+}
+
+/// Hardware details from `stats`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Model, e.g. `Antminer S19`.
+    pub model: Option<String>,
+    /// Board and PCB sensors, in °C.
+    pub temperatures: Vec<f32>,
+    pub chip_temperatures: Vec<f32>,
+    /// In RPM, for fans that are spinning.
+    pub fan_speeds: Vec<u32>,
+}
+
+impl Stats {
+    /// Hottest sensor of any kind.
+    pub fn max_temperature(&self) -> Option<f32> {
+        self.temperatures.iter().chain(&self.chip_temperatures).copied().reduce(f32::max)
+    }
+}
+
+/// A configured pool from `pools`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolEntry {
+    pub id: usize,
+    pub url: String,
+    pub user: String,
+    /// e.g. `Alive` or `Dead`.
+    pub status: String,
+    pub priority: i64,
+    pub stratum_active: bool,
+}
+
+pub struct CgminerClient {
+    address: String,
+    timeout: Duration,
+}
+
+impl CgminerClient {
+    /// `address` is `host:port`; a bare host gets `DEFAULT_PORT`.
+    pub fn new(address: &str) -> Self {
+        let address = if address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        };
+        Self {
+            address,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub async fn summary(&self) -> Result<Summary> {
+        let reply = self.command("summary", None).await?;
+        let summary = reply["SUMMARY"].get(0).context("summary reply has no SUMMARY")?;
+        Ok(Summary {
+            hashrate: hashrate(summary, CURRENT_HASHRATE).or_else(|| hashrate(summary, AVERAGE_HASHRATE)).unwrap_or(0.0),
+            average_hashrate: hashrate(summary, AVERAGE_HASHRATE).unwrap_or(0.0),
+            accepted: integer(&summary["Accepted"]),
+            rejected: integer(&summary["Rejected"]),
+            hardware_errors: integer(&summary["Hardware Errors"]),
+            elapsed: integer(&summary["Elapsed"]),
+        })
+    }
+
+    pub async fn devs(&self) -> Result<Vec<Dev>> {
+        let reply = self.command("devs", None).await?;
+        let devs = reply["DEVS"].as_array().map(Vec::as_slice).unwrap_or_default();
+        Ok(devs.iter().map(|dev| Dev {
+            id: number(&dev["ID"]).or_else(|| number(&dev["ASC"])).unwrap_or(0.0) as u64,
+            name: dev["Name"].as_str().unwrap_or("ASC").to_string(),
+            status: dev["Status"].as_str().unwrap_or_default().to_string(),
+            temperature: number(&dev["Temperature"]).map(|t| t as f32).filter(|&t| t > 0.0),
+            hashrate: hashrate(dev, CURRENT_HASHRATE).or_else(|| hashrate(dev, AVERAGE_HASHRATE)).unwrap_or(0.0),
+            hardware_errors: integer(&dev["Hardware Errors"]),
+        }).collect())
+    }
+
+    pub async fn stats(&self) -> Result<Stats> {
+        let reply = self.command("stats", None).await?;
+        let mut stats = Stats::default();
+        for entry in reply["STATS"].as_array().into_iter().flatten() {
+            let Some(fields) = entry.as_object() else {
+                continue;
+            };
+            for (key, value) in fields {
+                if key == "Type" {
+                    stats.model = value.as_str().map(str::to_string);
+                } else if key.starts_with("temp_chip") {
+                    stats.chip_temperatures.extend(temperatures(value));
+                } else if key.starts_with("temp") && key != "temp_num" && key != "temp_max" {
+                    stats.temperatures.extend(temperatures(value));
+                } else if key.strip_prefix("fan").is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())) {
+                    stats.fan_speeds.extend(number(value).filter(|&rpm| rpm > 0.0).map(|rpm| rpm as u32));
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    pub async fn pools(&self) -> Result<Vec<PoolEntry>> {
+        let reply = self.command("pools", None).await?;
+        let pools = reply["POOLS"].as_array().map(Vec::as_slice).unwrap_or_default();
+        Ok(pools.iter().map(|pool| PoolEntry {
+            id: integer(&pool["POOL"]) as usize,
+            url: pool["URL"].as_str().unwrap_or_default().to_string(),
+            user: pool["User"].as_str().unwrap_or_default().to_string(),
+            status: pool["Status"].as_str().unwrap_or_default().to_string(),
+            priority: number(&pool["Priority"]).unwrap_or(0.0) as i64,
+            stratum_active: pool["Stratum Active"].as_bool().unwrap_or(false),
+        }).collect())
+    }
+
+    /// Needs write access (`api-allow W:`) on the miner, as do
+    /// `switch_pool` and `restart`.
+    pub async fn add_pool(&self, url: &str, user: &str, password: &str) -> Result<()> {
+        // The parameter is comma separated, so commas must be escaped.
+        let parameter = [url, user, password].map(|p| p.replace('\\', "\\\\").replace(',', "\\,")).join(",");
+        self.command("addpool", Some(&parameter)).await.map(|_| ())
+    }
+
+    pub async fn switch_pool(&self, id: usize) -> Result<()> {
+        self.command("switchpool", Some(&id.to_string())).await.map(|_| ())
+    }
+
+    pub async fn restart(&self) -> Result<()> {
+        self.command("restart", None).await.map(|_| ())
+    }
+
+    /// Makes `url` with `user` the miner's active pool, adding it unless it
+    /// is already configured. Returns the pool's id.
+    pub async fn use_pool(&self, url: &str, user: &str, password: &str) -> Result<usize> {
+        let find = |pools: Vec<PoolEntry>| pools.into_iter().find(|p| p.url == url && p.user == user);
+        let pool = match find(self.pools().await?) {
+            Some(pool) => pool,
+            None => {
+                self.add_pool(url, user, password).await?;
+                find(self.pools().await?)
+                    .with_context(|| format!("{} did not list {} after adding it", self.address, url))?
+            }
+        };
+        if pool.priority != 0 || !pool.stratum_active {
+            self.switch_pool(pool.id).await?;
+        }
+        Ok(pool.id)
+    }
+
+    async fn command(&self, command: &str, parameter: Option<&str>) -> Result<Value> {
+        let request = match parameter {
+            Some(parameter) => json!({ "command": command, "parameter": parameter }),
+            None => json!({ "command": command }),
+        };
+        let exchange = async {
+            let mut stream = TcpStream::connect(&self.address).await?;
+            stream.write_all(request.to_string().as_bytes()).await?;
+            let mut reply = Vec::new();
+            stream.read_to_end(&mut reply).await?;
+            Ok::<_, anyhow::Error>(reply)
+        };
+        let reply = tokio::time::timeout(self.timeout, exchange).await
+            .map_err(|_| anyhow::anyhow!("{} did not answer {} within {:?}", self.address, command, self.timeout))?
+            .with_context(|| format!("{} to {} failed", command, self.address))?;
+        parse_reply(&self.address, command, &reply)
+    }
+}
+
+fn parse_reply(address: &str, command: &str, reply: &[u8]) -> Result<Value> {
+    let text = String::from_utf8_lossy(reply);
+    // Replies end in a NUL byte.
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    // Some miners close the socket without a reply once they restart.
+    if text.is_empty() && command == "restart" {
+        return Ok(Value::Null);
+    }
+    // bmminer leaves out the commas between STATS objects.
+    let text = text.replace("}{", "},{");
+    let reply: Value = serde_json::from_str(&text)
+        .with_context(|| format!("{} sent an invalid {} reply", address, command))?;
+
+    let status = reply["STATUS"].get(0).unwrap_or(&reply);
+    if matches!(status["STATUS"].as_str(), Some("E" | "F")) {
+        anyhow::bail!("{} refused {}: {}", address, command, status["Msg"].as_str().unwrap_or("no reason given"));
+    }
+    Ok(reply)
+}
+
+/// A number, which firmwares send as either JSON numbers or strings.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn integer(value: &Value) -> u64 {
+    number(value).map_or(0, |n| n.max(0.0) as u64)
+}
+
+fn hashrate(fields: &Value, names: &[(&str, f64)]) -> Option<f64> {
+    names.iter().find_map(|(name, unit)| number(&fields[*name]).map(|rate| rate * unit))
+}
+
+/// Sensor readings in a stats field: a number, or several joined by dashes
+/// such as `"56-62-60-58"`. Zero marks an empty slot.
+fn temperatures(value: &Value) -> Vec<f32> {
+    let readings: Vec<f64> = match value {
+        Value::String(s) => s.split('-').filter_map(|t| t.trim().parse().ok()).collect(),
+        other => number(other).into_iter().collect(),
+    };
+    readings.into_iter().filter(|&t| t > 0.0).map(|t| t as f32).collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    /// A cgminer API with one pool configured, answering like an
+    /// Antminer S9.
+    pub(crate) struct FakeMiner {
+        pub address: String,
+        pub pools: Arc<Mutex<Vec<(String, String)>>>,
+        pub active: Arc<Mutex<usize>>,
+    }
+
+    impl FakeMiner {
+        pub(crate) async fn start() -> Self {
+            Self::start_on("127.0.0.1:0").await
+        }
+
+        pub(crate) async fn start_on(address: &str) -> Self {
+            let listener = TcpListener::bind(address).await.unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let pools = Arc::new(Mutex::new(vec![("stratum+tcp://old.pool:3333".to_string(), "old.worker".to_string())]));
+            let active = Arc::new(Mutex::new(0));
+            let (state, current) = (pools.clone(), active.clone());
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    let request: Value = loop {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                        if let Ok(request) = serde_json::from_slice(&request) {
+                            break request;
+                        }
+                    };
+                    let reply = answer(&request, &state, &current);
+                    stream.write_all(reply.as_bytes()).await.unwrap();
+                    stream.write_all(b"\0").await.unwrap();
+                }
+            });
+            Self { address, pools, active }
+        }
+    }
+
+    fn answer(request: &Value, pools: &Mutex<Vec<(String, String)>>, active: &Mutex<usize>) -> String {
+        let ok = |msg: &str| format!(r#"{{"STATUS":[{{"STATUS":"S","Msg":"{}"}}],"id":1}}"#, msg);
+        let parameter = request["parameter"].as_str().unwrap_or_default();
+        match request["command"].as_str().unwrap() {
+            "summary" => r#"{"STATUS":[{"STATUS":"S","Msg":"Summary"}],"SUMMARY":[{"Elapsed":3600,"GHS 5s":"13612.45","GHS av":13500.5,"Accepted":1200,"Rejected":3,"Hardware Errors":42}],"id":1}"#.to_string(),
+            "devs" => r#"{"STATUS":[{"STATUS":"S","Msg":"1 ASC(s)"}],"DEVS":[{"ASC":0,"Name":"BM1387","ID":0,"Status":"Alive","Temperature":71,"MHS 5s":4537483.2,"Hardware Errors":14}],"id":1}"#.to_string(),
+            // bmminer's missing comma between the two STATS objects.
+            "stats" => r#"{"STATUS":[{"STATUS":"S","Msg":"CGMiner stats"}],"STATS":[{"CGMiner":"4.9.0","Miner":"16.8.1.3","Type":"Antminer S9"}{"STATS":0,"fan_num":2,"fan1":0,"fan3":5760,"fan6":5880,"temp_num":3,"temp1":0,"temp6":58,"temp7":61,"temp2_6":73,"temp2_7":76,"temp_max":61,"temp_chip1":"70-75-72-78"}],"id":1}"#.to_string(),
+            "pools" => {
+                let pools = pools.lock().unwrap();
+                let active = *active.lock().unwrap();
+                let entries: Vec<_> = pools.iter().enumerate().map(|(id, (url, user))| json!({
+                    "POOL": id,
+                    "URL": url,
+                    "User": user,
+                    "Status": "Alive",
+                    "Priority": if id == active { 0 } else { id as i64 + 1 },
+                    "Stratum Active": id == active,
+                })).collect();
+                json!({ "STATUS": [{ "STATUS": "S", "Msg": "pools" }], "POOLS": entries }).to_string()
+            }
+            "addpool" => {
+                let fields: Vec<_> = parameter.split(',').collect();
+                pools.lock().unwrap().push((fields[0].to_string(), fields[1].to_string()));
+                ok("Added pool")
+            }
+            "switchpool" => match parameter.parse::<usize>() {
+                Ok(id) if id < pools.lock().unwrap().len() => {
+                    *active.lock().unwrap() = id;
+                    ok("Switching to pool")
+                }
+                _ => r#"{"STATUS":[{"STATUS":"E","Msg":"Invalid pool id"}],"id":1}"#.to_string(),
+            },
+            "restart" => ok("Restart"),
+            _ => r#"{"STATUS":[{"STATUS":"E","Msg":"Invalid command"}],"id":1}"#.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_summary_devs_and_stats() {
+        let miner = FakeMiner::start().await;
+        let client = CgminerClient::new(&miner.address);
+
+        let summary = client.summary().await.unwrap();
+        assert_eq!(summary, Summary {
+            hashrate: 13612.45e9,
+            average_hashrate: 13500.5e9,
+            accepted: 1200,
+            rejected: 3,
+            hardware_errors: 42,
+            elapsed: 3600,
+        });
+
+        let devs = client.devs().await.unwrap();
+        assert_eq!(devs.len(), 1);
+        assert_eq!(devs[0].temperature, Some(71.0));
+        assert_eq!(devs[0].hashrate, 4537483.2e6);
+
+        let mut stats = client.stats().await.unwrap();
+        assert_eq!(stats.model.as_deref(), Some("Antminer S9"));
+        stats.temperatures.sort_by(f32::total_cmp);
+        assert_eq!(stats.temperatures, vec![58.0, 61.0, 73.0, 76.0]);
+        assert_eq!(stats.chip_temperatures, vec![70.0, 75.0, 72.0, 78.0]);
+        assert_eq!(stats.fan_speeds, vec![5760, 5880]);
+        assert_eq!(stats.max_temperature(), Some(78.0));
+    }
+
+    #[tokio::test]
+    async fn test_points_miner_at_pool() {
+        let miner = FakeMiner::start().await;
+        let client = CgminerClient::new(&miner.address);
+
+        let id = client.use_pool("stratum+tcp://our.pool:3333", "wallet:rig-1", "x").await.unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(*miner.active.lock().unwrap(), 1);
+        let pools = client.pools().await.unwrap();
+        assert!(pools[1].stratum_active);
+        assert_eq!(pools[1].user, "wallet:rig-1");
+
+        // Already there: nothing is added again.
+        assert_eq!(client.use_pool("stratum+tcp://our.pool:3333", "wallet:rig-1", "x").await.unwrap(), 1);
+        assert_eq!(miner.pools.lock().unwrap().len(), 2);
+
+        let refused = client.switch_pool(7).await.unwrap_err();
+        assert!(refused.to_string().contains("Invalid pool id"), "{}", refused);
+        client.restart().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_miner_fails() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        assert!(CgminerClient::new(&address).summary().await.is_err());
+        assert_eq!(CgminerClient::new("10.0.0.5").address(), "10.0.0.5:4028");
+    }
+}
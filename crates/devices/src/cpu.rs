@@ -1,4 +1,4 @@
-use jxpoolminer_core::{Device, DeviceType, DeviceCapabilities, Algorithm};
+use jxpoolminer_core::{Device, DeviceType, DeviceCapabilities, Algorithm, CpuFeature};
 use anyhow::Result;
 use sysinfo::System;
 
@@ -18,10 +18,30 @@ pub async fn detect() -> Result<Vec<Device>> {
         capabilities: DeviceCapabilities {
             max_hashrate: (cpu_count as f64) * 1000.0, // Estimate
             memory: sys.total_memory(),
-            supported_algorithms: vec![Algorithm::GXHash],
+            supported_algorithms: vec![Algorithm::GXHASH],
+            cpu_features: features(),
         },
         status: jxpoolminer_core::DeviceStatus::Idle,
     };
     
+    tracing::info!("CPU features: {:?}", device.capabilities.cpu_features);
     Ok(vec![device])
 }
+
+/// Hashing-relevant instruction set extensions of the host CPU.
+pub fn features() -> Vec<CpuFeature> {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let detected = [
+            (CpuFeature::Sse41, is_x86_feature_detected!("sse4.1")),
+            (CpuFeature::Avx2, is_x86_feature_detected!("avx2")),
+            (CpuFeature::Avx512F, is_x86_feature_detected!("avx512f")),
+            (CpuFeature::ShaNi, is_x86_feature_detected!("sha")),
+        ];
+        detected.into_iter().filter(|(_, present)| *present).map(|(feature, _)| feature).collect()
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        Vec::new()
+    }
+}
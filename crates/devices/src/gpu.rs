@@ -1,6 +1,6 @@
 use jxpoolminer_core::{Device, DeviceType, DeviceCapabilities, Algorithm, GPUVendor};
 use anyhow::Result;
-use sysinfo::System;
+use std::path::{Path, PathBuf};
 
 pub async fn detect() -> Result<Vec<Device>> {
     let mut devices = Vec::new();
@@ -25,47 +25,197 @@ pub async fn detect() -> Result<Vec<Device>> {
 
 #[cfg(target_os = "linux")]
 async fn detect_linux() -> Result<Vec<Device>> {
-    let mut devices = Vec::new();
-    
-    if let Ok(output) = tokio::process::Command::new("lspci")
-        .output()
-        .await
-    {
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        
-        for (idx, line) in output_str.lines().enumerate() {
-            let line_lower = line.to_lowercase();
-            
-            if line_lower.contains("vga") || line_lower.contains("3d") || line_lower.contains("display") {
-                let (vendor, name) = if line_lower.contains("nvidia") {
-                    (GPUVendor::NVIDIA, extract_gpu_name(line, "NVIDIA"))
-                } else if line_lower.contains("amd") || line_lower.contains("radeon") {
-                    (GPUVendor::AMD, extract_gpu_name(line, "AMD"))
-                } else if line_lower.contains("intel") {
-                    (GPUVendor::Intel, extract_gpu_name(line, "Intel"))
-                } else {
-                    continue;
+    detect_sysfs(Path::new(SYSFS_ROOT))
+}
+
+/// Where Linux mounts sysfs.
+pub const SYSFS_ROOT: &str = "/sys";
+
+/// A GPU found under `class/drm` in sysfs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PciGpu {
+    /// PCI bus address, e.g. `0000:03:00.0`.
+    pub address: String,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    /// Kernel driver bound to the device, e.g. `amdgpu`.
+    pub driver: Option<String>,
+    /// VRAM in bytes, when the driver reports it.
+    pub vram: Option<u64>,
+    /// The PCI device directory, `class/drm/cardN/device`.
+    pub path: PathBuf,
+}
+
+impl PciGpu {
+    pub fn vendor(&self) -> Option<GPUVendor> {
+        match self.vendor_id {
+            PCI_VENDOR_NVIDIA => Some(GPUVendor::NVIDIA),
+            PCI_VENDOR_AMD => Some(GPUVendor::AMD),
+            PCI_VENDOR_INTEL => Some(GPUVendor::Intel),
+            _ => None,
+        }
+    }
+
+    /// Keyed by bus address, so it survives reboots and other cards
+    /// being added or removed.
+    pub fn stable_id(&self) -> String {
+        format!("gpu-{}", self.address)
+    }
+
+    pub fn model(&self) -> Option<&'static GpuModel> {
+        GPU_MODELS.iter().find(|m| m.vendor_id == self.vendor_id && m.device_id == self.device_id)
+    }
+
+    pub fn name(&self) -> String {
+        match self.model() {
+            Some(model) => model.name.to_string(),
+            None => {
+                let vendor = match self.vendor() {
+                    Some(GPUVendor::NVIDIA) => "NVIDIA",
+                    Some(GPUVendor::AMD) => "AMD",
+                    Some(GPUVendor::Intel) => "Intel",
+                    None => "Unknown",
                 };
-                
-                let memory = estimate_gpu_memory(&vendor);
-                let hashrate = estimate_gpu_hashrate(&vendor);
-                
-                devices.push(Device {
-                    id: format!("gpu-{}", idx),
-                    name,
-                    device_type: DeviceType::GPU { vendor: vendor.clone() },
-                    capabilities: DeviceCapabilities {
-                        max_hashrate: hashrate,
-                        memory,
-                        supported_algorithms: vec![Algorithm::Ethash],
-                    },
-                    status: jxpoolminer_core::DeviceStatus::Idle,
-                });
+                format!("{} GPU [{:04x}:{:04x}]", vendor, self.vendor_id, self.device_id)
             }
         }
     }
-    
-    Ok(devices)
+
+    /// VRAM as reported by the driver, else the model's usual size.
+    pub fn memory(&self) -> Option<u64> {
+        self.vram.or_else(|| self.model().and_then(|m| m.vram_gib).map(|gib| gib << 30))
+    }
+
+    /// None for display devices of vendors that cannot mine, such as a
+    /// server's BMC. `max_hashrate` is left for a benchmark to fill in.
+    pub fn to_device(&self) -> Option<Device> {
+        let vendor = self.vendor()?;
+        Some(Device {
+            id: self.stable_id(),
+            name: self.name(),
+            device_type: DeviceType::GPU { vendor },
+            capabilities: DeviceCapabilities {
+                max_hashrate: 0.0,
+                memory: self.memory().unwrap_or(0),
+                supported_algorithms: vec![Algorithm::ETHASH],
+                cpu_features: vec![],
+            },
+            status: jxpoolminer_core::DeviceStatus::Idle,
+        })
+    }
+}
+
+const PCI_VENDOR_NVIDIA: u16 = 0x10de;
+const PCI_VENDOR_AMD: u16 = 0x1002;
+const PCI_VENDOR_INTEL: u16 = 0x8086;
+
+/// A known PCI device ID.
+#[derive(Debug)]
+pub struct GpuModel {
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub name: &'static str,
+    /// None where the same ID ships with different amounts.
+    pub vram_gib: Option<u64>,
+}
+
+const fn model(vendor_id: u16, device_id: u16, name: &'static str, vram_gib: Option<u64>) -> GpuModel {
+    GpuModel { vendor_id, device_id, name, vram_gib }
+}
+
+/// Common mining cards. Several retail models often share one ID, in
+/// which case the name lists them all.
+pub static GPU_MODELS: &[GpuModel] = &[
+    model(PCI_VENDOR_NVIDIA, 0x1b06, "NVIDIA GeForce GTX 1080 Ti", Some(11)),
+    model(PCI_VENDOR_NVIDIA, 0x1b80, "NVIDIA GeForce GTX 1080", Some(8)),
+    model(PCI_VENDOR_NVIDIA, 0x1c03, "NVIDIA GeForce GTX 1060 6GB", Some(6)),
+    model(PCI_VENDOR_NVIDIA, 0x2204, "NVIDIA GeForce RTX 3090", Some(24)),
+    model(PCI_VENDOR_NVIDIA, 0x2206, "NVIDIA GeForce RTX 3080", Some(10)),
+    model(PCI_VENDOR_NVIDIA, 0x2484, "NVIDIA GeForce RTX 3070", Some(8)),
+    model(PCI_VENDOR_NVIDIA, 0x2503, "NVIDIA GeForce RTX 3060", Some(12)),
+    model(PCI_VENDOR_NVIDIA, 0x2684, "NVIDIA GeForce RTX 4090", Some(24)),
+    model(PCI_VENDOR_NVIDIA, 0x2704, "NVIDIA GeForce RTX 4080", Some(16)),
+    model(PCI_VENDOR_AMD, 0x67df, "AMD Radeon RX 470/480/570/580", None),
+    model(PCI_VENDOR_AMD, 0x687f, "AMD Radeon RX Vega 56/64", Some(8)),
+    model(PCI_VENDOR_AMD, 0x731f, "AMD Radeon RX 5700/5700 XT", Some(8)),
+    model(PCI_VENDOR_AMD, 0x73bf, "AMD Radeon RX 6800/6800 XT/6900 XT", Some(16)),
+    model(PCI_VENDOR_AMD, 0x73df, "AMD Radeon RX 6700/6700 XT", None),
+    model(PCI_VENDOR_AMD, 0x744c, "AMD Radeon RX 7900 XT/XTX", None),
+    model(PCI_VENDOR_INTEL, 0x56a0, "Intel Arc A770", None),
+    model(PCI_VENDOR_INTEL, 0x56a1, "Intel Arc A750", Some(8)),
+];
+
+/// Finds GPUs by walking `class/drm/card*/device` under `root`, normally
+/// `SYSFS_ROOT`.
+pub fn detect_sysfs(root: &Path) -> Result<Vec<Device>> {
+    Ok(scan_sysfs(root)?.iter().filter_map(PciGpu::to_device).collect())
+}
+
+/// Every display-class PCI device under `root`, once each, in bus order.
+pub fn scan_sysfs(root: &Path) -> Result<Vec<PciGpu>> {
+    let drm = root.join("class/drm");
+    let entries = match std::fs::read_dir(&drm) {
+        Ok(entries) => entries,
+        // No DRM drivers loaded at all.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", drm.display(), e)),
+    };
+
+    let mut gpus: Vec<PciGpu> = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // Skips connectors (card0-DP-1) and render nodes (renderD128).
+        let is_card = name.strip_prefix("card")
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+        if !is_card {
+            continue;
+        }
+        match read_pci_gpu(&entry.path().join("device")) {
+            Some(gpu) if !gpus.iter().any(|g| g.address == gpu.address) => gpus.push(gpu),
+            Some(_) => {}
+            None => tracing::debug!("Skipping {}: not a PCI display device", name),
+        }
+    }
+    gpus.sort_by(|a, b| a.address.cmp(&b.address));
+    Ok(gpus)
+}
+
+fn read_pci_gpu(path: &Path) -> Option<PciGpu> {
+    let class = read_hex(&path.join("class"))?;
+    // PCI base class 0x03: display controller.
+    if class >> 16 != 0x03 {
+        return None;
+    }
+    let vendor_id = u16::try_from(read_hex(&path.join("vendor"))?).ok()?;
+    let device_id = u16::try_from(read_hex(&path.join("device"))?).ok()?;
+
+    let uevent = std::fs::read_to_string(path.join("uevent")).unwrap_or_default();
+    let uevent_value = |key: &str| uevent.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+        .map(str::to_string);
+    let address = uevent_value("PCI_SLOT_NAME")
+        .or_else(|| link_name(path))?;
+    let driver = uevent_value("DRIVER")
+        .or_else(|| link_name(&path.join("driver")));
+    let vram = std::fs::read_to_string(path.join("mem_info_vram_total")).ok()
+        .and_then(|s| s.trim().parse().ok());
+
+    Some(PciGpu { address, vendor_id, device_id, driver, vram, path: path.to_path_buf() })
+}
+
+/// Reads a sysfs attribute such as `0x10de`.
+fn read_hex(path: &Path) -> Option<u32> {
+    let value = std::fs::read_to_string(path).ok()?;
+    let value = value.trim();
+    u32::from_str_radix(value.strip_prefix("0x").unwrap_or(value), 16).ok()
+}
+
+/// Name of the directory a sysfs link points at.
+fn link_name(path: &Path) -> Option<String> {
+    let target = std::fs::canonicalize(path).ok()?;
+    Some(target.file_name()?.to_string_lossy().into_owned())
 }
 
 #[cfg(target_os = "windows")]
@@ -106,7 +256,8 @@ async fn detect_windows() -> Result<Vec<Device>> {
                 capabilities: DeviceCapabilities {
                     max_hashrate: hashrate,
                     memory,
-                    supported_algorithms: vec![Algorithm::Ethash],
+                    supported_algorithms: vec![Algorithm::ETHASH],
+                    cpu_features: vec![],
                 },
                 status: jxpoolminer_core::DeviceStatus::Idle,
             });
@@ -153,7 +304,8 @@ async fn detect_macos() -> Result<Vec<Device>> {
                     capabilities: DeviceCapabilities {
                         max_hashrate: hashrate,
                         memory,
-                        supported_algorithms: vec![Algorithm::Ethash],
+                        supported_algorithms: vec![Algorithm::ETHASH],
+                        cpu_features: vec![],
                     },
                     status: jxpoolminer_core::DeviceStatus::Idle,
                 });
@@ -164,17 +316,7 @@ async fn detect_macos() -> Result<Vec<Device>> {
     Ok(devices)
 }
 
-fn extract_gpu_name(line: &str, vendor: &str) -> String {
-    if let Some(pos) = line.find(vendor) {
-        line[pos..].split('[').next()
-            .unwrap_or(vendor)
-            .trim()
-            .to_string()
-    } else {
-        format!("{} GPU", vendor)
-    }
-}
-
+#[cfg(any(target_os = "windows", target_os = "macos"))]
 fn estimate_gpu_memory(vendor: &GPUVendor) -> u64 {
     match vendor {
         GPUVendor::NVIDIA => 8 * 1024 * 1024 * 1024,
@@ -183,6 +325,7 @@ fn estimate_gpu_memory(vendor: &GPUVendor) -> u64 {
     }
 }
 
+#[cfg(any(target_os = "windows", target_os = "macos"))]
 fn estimate_gpu_hashrate(vendor: &GPUVendor) -> f64 {
     match vendor {
         GPUVendor::NVIDIA => 100_000_000.0,
@@ -190,3 +333,91 @@ fn estimate_gpu_hashrate(vendor: &GPUVendor) -> f64 {
         GPUVendor::Intel => 20_000_000.0,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeSysfs(PathBuf);
+
+    impl FakeSysfs {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("jxpoolminer-test-sysfs-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(root.join("class/drm")).unwrap();
+            Self(root)
+        }
+
+        fn card(&self, card: &str, attributes: &[(&str, &str)]) {
+            let device = self.0.join("class/drm").join(card).join("device");
+            std::fs::create_dir_all(&device).unwrap();
+            for (name, value) in attributes {
+                std::fs::write(device.join(name), format!("{}\n", value)).unwrap();
+            }
+        }
+    }
+
+    impl Drop for FakeSysfs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_detects_gpus_from_sysfs() {
+        let sysfs = FakeSysfs::new("detect");
+        sysfs.card("card1", &[
+            ("class", "0x030000"),
+            ("vendor", "0x1002"),
+            ("device", "0x73bf"),
+            ("uevent", "DRIVER=amdgpu\nPCI_ID=1002:73BF\nPCI_SLOT_NAME=0000:03:00.0"),
+            ("mem_info_vram_total", "17163091968"),
+        ]);
+        sysfs.card("card0", &[
+            ("class", "0x030200"),
+            ("vendor", "0x10de"),
+            ("device", "0x2684"),
+            ("uevent", "DRIVER=nvidia\nPCI_SLOT_NAME=0000:01:00.0"),
+        ]);
+        // Connectors and render nodes are not cards.
+        sysfs.card("card1-DP-1", &[("class", "0x030000")]);
+        sysfs.card("renderD128", &[("class", "0x030000")]);
+        // A BMC's VGA controller is a display device but cannot mine.
+        sysfs.card("card2", &[
+            ("class", "0x030000"),
+            ("vendor", "0x1a03"),
+            ("device", "0x2000"),
+            ("uevent", "DRIVER=ast\nPCI_SLOT_NAME=0000:05:00.0"),
+        ]);
+
+        let gpus = scan_sysfs(&sysfs.0).unwrap();
+        assert_eq!(gpus.iter().map(|g| g.address.as_str()).collect::<Vec<_>>(),
+            vec!["0000:01:00.0", "0000:03:00.0", "0000:05:00.0"]);
+        assert_eq!(gpus[1].driver.as_deref(), Some("amdgpu"));
+
+        let devices = detect_sysfs(&sysfs.0).unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].id, "gpu-0000:01:00.0");
+        assert_eq!(devices[0].name, "NVIDIA GeForce RTX 4090");
+        assert_eq!(devices[0].device_type, DeviceType::GPU { vendor: GPUVendor::NVIDIA });
+        assert_eq!(devices[0].capabilities.memory, 24 << 30);
+        assert_eq!(devices[1].id, "gpu-0000:03:00.0");
+        assert_eq!(devices[1].capabilities.memory, 17163091968);
+    }
+
+    #[test]
+    fn test_unknown_models_and_missing_drm() {
+        let sysfs = FakeSysfs::new("unknown");
+        sysfs.card("card0", &[
+            ("class", "0x038000"),
+            ("vendor", "0x8086"),
+            ("device", "0xabcd"),
+            ("uevent", "PCI_SLOT_NAME=0000:00:02.0"),
+        ]);
+        let devices = detect_sysfs(&sysfs.0).unwrap();
+        assert_eq!(devices[0].name, "Intel GPU [8086:abcd]");
+        assert_eq!(devices[0].capabilities.memory, 0);
+
+        assert!(detect_sysfs(&sysfs.0.join("missing")).unwrap().is_empty());
+    }
+}
@@ -2,10 +2,14 @@ pub mod detector;
 pub mod cpu;
 pub mod gpu;
 pub mod asic;
+pub mod cgminer;
+pub mod scan;
+pub mod telemetry;
 
 pub use detector::*;
+pub use telemetry::{Reading, Telemetry};
 
-use jxpoolminer_core::{Device, DeviceType, Algorithm};
+use jxpoolminer_core::Device;
 use anyhow::Result;
 
 pub async fn detect_all() -> Result<Vec<Device>> {
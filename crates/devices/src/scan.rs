@@ -0,0 +1,349 @@
+//! Finds ASIC miners on the LAN by probing every address of a subnet for
+//! the cgminer API and, failing that, a miner's web interface.
+
+use crate::asic::NetworkAsic;
+use crate::cgminer::{CgminerClient, DEFAULT_PORT};
+use jxpoolminer_core::{Algorithm, Device, DeviceCapabilities, DeviceStatus, DeviceType};
+use anyhow::{Context, Result};
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::task::{JoinHandle, JoinSet};
+
+/// Largest range scanned, a /16.
+const MIN_PREFIX: u8 = 16;
+/// Bytes of a web page looked at for a fingerprint.
+const HTTP_PEEK: usize = 8 * 1024;
+
+/// Text in a miner's web interface, and the maker it gives away.
+const HTTP_FINGERPRINTS: &[(&str, &str)] = &[
+    ("antminer", "Antminer"),
+    ("whatsminer", "Whatsminer"),
+    ("avalon", "Avalon"),
+    ("innosilicon", "Innosilicon"),
+    ("braiins", "Braiins OS"),
+];
+
+/// An IPv4 range such as `192.168.1.0/24`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    network: Ipv4Addr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    /// A bare address is a /32.
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, prefix) = s.trim().split_once('/').unwrap_or((s.trim(), "32"));
+        let address: Ipv4Addr = address.parse().with_context(|| format!("Invalid address in {}", s))?;
+        let prefix: u8 = prefix.parse().ok().filter(|&p| p <= 32)
+            .with_context(|| format!("Invalid prefix length in {}", s))?;
+        if prefix < MIN_PREFIX {
+            anyhow::bail!("{} is too large to scan; use a /{} or smaller", s, MIN_PREFIX);
+        }
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(address) & mask),
+            prefix,
+        })
+    }
+}
+
+impl Cidr {
+    /// Addresses of the range that hosts can have: all but the network
+    /// and broadcast address, except in /31 and /32.
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let first = u32::from(self.network);
+        let size = 1u64 << (32 - self.prefix);
+        let (start, end) = if size <= 2 {
+            (first as u64, first as u64 + size)
+        } else {
+            (first as u64 + 1, first as u64 + size - 1)
+        };
+        (start..end).map(|address| Ipv4Addr::from(address as u32))
+    }
+}
+
+/// How far a scan has got.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScanProgress {
+    pub scanned: usize,
+    pub total: usize,
+    pub found: usize,
+}
+
+/// A miner found by a scan.
+#[derive(Debug, Clone)]
+pub struct Discovered {
+    pub device: Device,
+    /// Where its cgminer API answered; None when only its web interface did.
+    pub api_address: Option<String>,
+}
+
+pub struct Scanner {
+    api_port: u16,
+    http_port: Option<u16>,
+    concurrency: usize,
+    timeout: Duration,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            api_port: DEFAULT_PORT,
+            http_port: Some(80),
+            concurrency: 64,
+            timeout: Duration::from_millis(800),
+        }
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_api_port(mut self, port: u16) -> Self {
+        self.api_port = port;
+        self
+    }
+
+    /// Port of miners' web interfaces; None skips it.
+    pub fn with_http_port(mut self, port: Option<u16>) -> Self {
+        self.http_port = port;
+        self
+    }
+
+    /// Hosts probed at once.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// How long each probe of a host may take.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Starts scanning `range` in the background.
+    pub fn start(&self, range: Cidr) -> Scan {
+        let hosts: Vec<_> = range.hosts().collect();
+        let (progress_tx, progress) = watch::channel(ScanProgress { total: hosts.len(), ..ScanProgress::default() });
+        let (cancel, cancel_rx) = watch::channel(false);
+        let probe = Probe {
+            api_port: self.api_port,
+            http_port: self.http_port,
+            timeout: self.timeout,
+        };
+        let handle = tokio::spawn(run(hosts, probe, self.concurrency, progress_tx, cancel_rx));
+        Scan { progress, cancel, handle }
+    }
+}
+
+/// A scan in progress. Dropping it cancels the scan.
+pub struct Scan {
+    progress: watch::Receiver<ScanProgress>,
+    cancel: watch::Sender<bool>,
+    handle: JoinHandle<Vec<Discovered>>,
+}
+
+impl Scan {
+    pub fn progress(&self) -> watch::Receiver<ScanProgress> {
+        self.progress.clone()
+    }
+
+    /// Stops probing; `finish` then returns what was found so far.
+    pub fn cancel(&self) {
+        self.cancel.send_replace(true);
+    }
+
+    /// Miners found, in address order.
+    pub async fn finish(self) -> Result<Vec<Discovered>> {
+        let Scan { handle, cancel: _cancel, .. } = self;
+        Ok(handle.await?)
+    }
+}
+
+async fn run(
+    hosts: Vec<Ipv4Addr>,
+    probe: Probe,
+    concurrency: usize,
+    progress: watch::Sender<ScanProgress>,
+    mut cancel: watch::Receiver<bool>,
+) -> Vec<Discovered> {
+    let mut found = Vec::new();
+    let mut probes = JoinSet::new();
+    let mut hosts = hosts.into_iter();
+
+    loop {
+        while probes.len() < concurrency {
+            let Some(host) = hosts.next() else {
+                break;
+            };
+            let probe = probe.clone();
+            probes.spawn(async move { probe.run(host).await });
+        }
+        tokio::select! {
+            biased;
+            _ = cancel.wait_for(|&cancelled| cancelled) => {
+                probes.abort_all();
+                tracing::info!("Network scan cancelled");
+                break;
+            }
+            next = probes.join_next() => {
+                let Some(result) = next else {
+                    break;
+                };
+                let discovered = result.ok().flatten();
+                progress.send_modify(|p| {
+                    p.scanned += 1;
+                    p.found += discovered.is_some() as usize;
+                });
+                found.extend(discovered);
+            }
+        }
+    }
+
+    found.sort_by_key(|d: &Discovered| d.device.id.strip_prefix("asic-").and_then(|ip| ip.parse::<Ipv4Addr>().ok()));
+    found
+}
+
+#[derive(Clone)]
+struct Probe {
+    api_port: u16,
+    http_port: Option<u16>,
+    timeout: Duration,
+}
+
+impl Probe {
+    async fn run(&self, host: Ipv4Addr) -> Option<Discovered> {
+        let address = format!("{}:{}", host, self.api_port);
+        let client = CgminerClient::new(&address).with_timeout(self.timeout);
+        if let Ok(asic) = NetworkAsic::from_client(client).await {
+            tracing::info!("Found {} (cgminer API)", asic.device.name);
+            return Some(Discovered { device: asic.device, api_address: Some(address) });
+        }
+
+        let model = self.http_fingerprint(host).await?;
+        tracing::info!("Found {} at {} (web interface only)", model, host);
+        let device = Device {
+            id: format!("asic-{}", host),
+            name: format!("{} ({})", model, host),
+            device_type: DeviceType::ASIC,
+            capabilities: DeviceCapabilities {
+                max_hashrate: 0.0,
+                memory: 0,
+                supported_algorithms: vec![Algorithm::SHA256],
+                cpu_features: vec![],
+            },
+            status: DeviceStatus::Idle,
+        };
+        Some(Discovered { device, api_address: None })
+    }
+
+    /// The maker named by the host's web interface, if it is a miner's.
+    async fn http_fingerprint(&self, host: Ipv4Addr) -> Option<&'static str> {
+        let port = self.http_port?;
+        let fetch = async {
+            let mut stream = TcpStream::connect((host, port)).await.ok()?;
+            let request = format!("GET / HTTP/1.0\r\nHost: {}\r\n\r\n", host);
+            stream.write_all(request.as_bytes()).await.ok()?;
+            let mut page = Vec::new();
+            stream.take(HTTP_PEEK as u64).read_to_end(&mut page).await.ok()?;
+            Some(page)
+        };
+        let page = tokio::time::timeout(self.timeout, fetch).await.ok()??;
+        // Headers count too: Antminers answer 401 with a digest realm of
+        // "antMiner Configuration".
+        let page = String::from_utf8_lossy(&page).to_lowercase();
+        HTTP_FINGERPRINTS.iter().find(|(text, _)| page.contains(text)).map(|(_, maker)| *maker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgminer::tests::FakeMiner;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_cidr_hosts() {
+        let hosts = |range: &str| range.parse::<Cidr>().unwrap().hosts().map(|h| h.to_string()).collect::<Vec<_>>();
+        assert_eq!(hosts("192.168.1.7/30"), vec!["192.168.1.5", "192.168.1.6"]);
+        assert_eq!(hosts("10.0.0.8/31"), vec!["10.0.0.8", "10.0.0.9"]);
+        assert_eq!(hosts("10.0.0.8"), vec!["10.0.0.8"]);
+        assert_eq!("172.16.0.0/16".parse::<Cidr>().unwrap().hosts().count(), 65534);
+        for invalid in ["10.0.0.0/8", "10.0.0/24", "10.0.0.0/33", "host/24"] {
+            assert!(invalid.parse::<Cidr>().is_err(), "{}", invalid);
+        }
+    }
+
+    /// Serves `page` to every connection on `address`.
+    async fn web_server(address: &str, page: &'static str) -> u16 {
+        let listener = TcpListener::bind(address).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let _ = stream.write_all(page.as_bytes()).await;
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_scan_finds_miners_on_loopback() {
+        let miner = FakeMiner::start_on("127.0.0.2:0").await;
+        let api_port: u16 = miner.address.rsplit_once(':').unwrap().1.parse().unwrap();
+        let http_port = web_server("127.0.0.3:0", "HTTP/1.0 401 Unauthorized\r\nWWW-Authenticate: Digest realm=\"antMiner Configuration\"\r\n\r\n").await;
+        web_server(&format!("127.0.0.4:{}", http_port), "HTTP/1.0 200 OK\r\n\r\n<h1>Welcome to nginx!</h1>").await;
+
+        let scan = Scanner::new()
+            .with_api_port(api_port)
+            .with_http_port(Some(http_port))
+            .with_concurrency(2)
+            .with_timeout(Duration::from_secs(2))
+            .start("127.0.0.0/29".parse().unwrap());
+        let progress = scan.progress();
+        let found = scan.finish().await.unwrap();
+
+        assert_eq!(found.iter().map(|d| d.device.id.as_str()).collect::<Vec<_>>(), vec!["asic-127.0.0.2", "asic-127.0.0.3"]);
+        assert_eq!(found[0].device.name, "Antminer S9 (127.0.0.2)");
+        assert_eq!(found[0].device.device_type, DeviceType::ASIC);
+        assert_eq!(found[0].api_address.as_deref(), Some(miner.address.as_str()));
+        assert_eq!(found[1].device.name, "Antminer (127.0.0.3)");
+        assert_eq!(found[1].api_address, None);
+        assert_eq!(*progress.borrow(), ScanProgress { scanned: 6, total: 6, found: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_scan_can_be_cancelled() {
+        // Accepts connections and never answers.
+        let listener = TcpListener::bind("127.0.0.5:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let scan = Scanner::new()
+            .with_api_port(port)
+            .with_http_port(None)
+            .with_timeout(Duration::from_secs(60))
+            .start("127.0.0.5".parse().unwrap());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        scan.cancel();
+        let found = tokio::time::timeout(Duration::from_secs(5), scan.finish()).await
+            .expect("cancelled scan did not stop").unwrap();
+        assert!(found.is_empty());
+    }
+}
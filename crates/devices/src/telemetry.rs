@@ -0,0 +1,375 @@
+//! Temperature, fan, power and clock readings from sysfs. GPUs are matched
+//! to their hwmon sensors by PCI address; the CPU gets the package sensors
+//! of `coretemp`/`k10temp`, falling back to thermal zones, and its power
+//! from RAPL energy counters.
+
+use jxpoolminer_core::{Device, DeviceType};
+use jxpoolminer_stats::Collector;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// How often sensors are read by default.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// hwmon drivers that report the CPU package.
+const CPU_HWMON: &[&str] = &["coretemp", "k10temp", "zenpower", "cpu_thermal"];
+
+/// One device's sensors at one moment. Missing sensors are None.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Reading {
+    /// Hottest sensor of the device, in °C.
+    pub temperature: Option<f32>,
+    pub fan_rpm: Option<u32>,
+    /// Power draw in watts.
+    pub power: Option<f32>,
+    /// Clocks in MHz.
+    pub core_clock: Option<f32>,
+    pub memory_clock: Option<f32>,
+}
+
+impl Reading {
+    fn merge(&mut self, other: Reading) {
+        self.temperature = match (self.temperature, other.temperature) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        self.fan_rpm = self.fan_rpm.or(other.fan_rpm);
+        self.power = self.power.or(other.power);
+        self.core_clock = self.core_clock.or(other.core_clock);
+        self.memory_clock = self.memory_clock.or(other.memory_clock);
+    }
+}
+
+/// Polls sysfs sensors for a set of devices.
+pub struct Telemetry {
+    root: PathBuf,
+    interval: Duration,
+    devices: Vec<Device>,
+    /// Last energy counter reading of each RAPL zone, in µJ.
+    energy: HashMap<PathBuf, (u64, Instant)>,
+    latest: watch::Sender<HashMap<String, Reading>>,
+}
+
+impl Telemetry {
+    pub fn new(devices: &[Device]) -> Self {
+        Self {
+            root: PathBuf::from(crate::gpu::SYSFS_ROOT),
+            interval: POLL_INTERVAL,
+            devices: devices.to_vec(),
+            energy: HashMap::new(),
+            latest: watch::channel(HashMap::new()).0,
+        }
+    }
+
+    /// Reads sensors under `root` instead of `/sys`.
+    pub fn with_sysfs_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Current readings, keyed by device ID. CPU power needs two readings
+    /// of the energy counters, so it first shows up on the second call.
+    pub fn read(&mut self) -> HashMap<String, Reading> {
+        self.read_at(Instant::now())
+    }
+
+    /// The readings `spawn` takes, as they are taken.
+    pub fn subscribe(&self) -> watch::Receiver<HashMap<String, Reading>> {
+        self.latest.subscribe()
+    }
+
+    /// Streams readings into `collector` and to subscribers every interval
+    /// until aborted.
+    pub fn spawn(mut self, collector: Arc<Collector>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            loop {
+                interval.tick().await;
+                let readings = self.read();
+                for (device_id, reading) in &readings {
+                    publish(&collector, device_id, *reading).await;
+                }
+                self.latest.send_replace(readings);
+            }
+        })
+    }
+
+    fn read_at(&mut self, now: Instant) -> HashMap<String, Reading> {
+        let mut readings: HashMap<String, Reading> = HashMap::new();
+        let cpu = self.devices.iter()
+            .find(|d| matches!(d.device_type, DeviceType::CPU { .. }))
+            .map(|d| d.id.clone());
+
+        for hwmon in list_dir(&self.root.join("class/hwmon")) {
+            let name = read_string(&hwmon.join("name")).unwrap_or_default();
+            // k10temp and zenpower are PCI drivers too, so the name is
+            // checked before the PCI address.
+            let device_id = if CPU_HWMON.contains(&name.as_str()) {
+                cpu.clone()
+            } else {
+                pci_slot(&hwmon.join("device")).map(|address| format!("gpu-{}", address))
+            };
+            let Some(device_id) = device_id.filter(|id| self.devices.iter().any(|d| &d.id == id)) else {
+                continue;
+            };
+            readings.entry(device_id).or_default().merge(read_hwmon(&hwmon));
+        }
+
+        if let Some(cpu) = cpu {
+            let mut reading = readings.remove(&cpu).unwrap_or_default();
+            if reading.temperature.is_none() {
+                reading.temperature = self.thermal_zone_temperature();
+            }
+            if reading.power.is_none() {
+                reading.power = self.rapl_power(now);
+            }
+            if reading != Reading::default() {
+                readings.insert(cpu, reading);
+            }
+        }
+        readings
+    }
+
+    /// Hottest CPU thermal zone, for systems without a CPU hwmon driver.
+    fn thermal_zone_temperature(&self) -> Option<f32> {
+        list_dir(&self.root.join("class/thermal"))
+            .filter(|zone| {
+                let kind = read_string(&zone.join("type")).unwrap_or_default();
+                kind.contains("pkg") || kind.contains("cpu")
+            })
+            .filter_map(|zone| read_number(&zone.join("temp")))
+            .map(|millidegrees| millidegrees as f32 / 1000.0)
+            .reduce(f32::max)
+    }
+
+    /// Package power summed over sockets, from the change in each RAPL
+    /// zone's energy counter since the previous call.
+    fn rapl_power(&mut self, now: Instant) -> Option<f32> {
+        let mut total = None;
+        // Top-level zones only: intel-rapl:0, not its sub-zone intel-rapl:0:0.
+        let zones = list_dir(&self.root.join("class/powercap"))
+            .filter(|zone| zone.file_name().is_some_and(|n| n.to_string_lossy().matches(':').count() == 1));
+        for zone in zones {
+            let Some(energy) = read_number(&zone.join("energy_uj")) else {
+                continue;
+            };
+            let Some((previous, then)) = self.energy.insert(zone.clone(), (energy, now)) else {
+                continue;
+            };
+            let elapsed = now.saturating_duration_since(then).as_secs_f64();
+            if elapsed <= 0.0 {
+                continue;
+            }
+            let delta = if energy >= previous {
+                energy - previous
+            } else {
+                // The counter wrapped around.
+                let range = read_number(&zone.join("max_energy_range_uj")).unwrap_or(0);
+                (range + energy).saturating_sub(previous)
+            };
+            *total.get_or_insert(0.0) += (delta as f64 / 1e6 / elapsed) as f32;
+        }
+        total
+    }
+}
+
+async fn publish(collector: &Collector, device_id: &str, reading: Reading) {
+    if let Some(temperature) = reading.temperature {
+        collector.update_temperature(device_id, temperature).await;
+    }
+    if let Some(power) = reading.power {
+        collector.update_power(device_id, power).await;
+    }
+    if let Some(rpm) = reading.fan_rpm {
+        collector.update_fan_speed(device_id, rpm).await;
+    }
+    if reading.core_clock.is_some() || reading.memory_clock.is_some() {
+        collector.update_clocks(device_id, reading.core_clock.unwrap_or(0.0), reading.memory_clock.unwrap_or(0.0)).await;
+    }
+}
+
+/// Reads one hwmon directory. Units follow the hwmon sysfs ABI:
+/// millidegrees, RPM, microwatts and hertz.
+fn read_hwmon(hwmon: &Path) -> Reading {
+    let mut reading = Reading::default();
+    for path in list_dir(hwmon) {
+        let Some(file) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        let Some(sensor) = file.strip_suffix("_input").or_else(|| file.strip_suffix("_average")) else {
+            continue;
+        };
+        let Some(value) = read_number(&path) else {
+            continue;
+        };
+        let label = read_string(&hwmon.join(format!("{}_label", sensor)));
+        if sensor.starts_with("temp") {
+            let celsius = value as f32 / 1000.0;
+            reading.temperature = Some(reading.temperature.map_or(celsius, |t| t.max(celsius)));
+        } else if sensor == "fan1" {
+            reading.fan_rpm = Some(value as u32);
+        } else if sensor == "power1" {
+            reading.power = Some(value as f32 / 1e6);
+        } else if sensor.starts_with("freq") {
+            let mhz = Some(value as f32 / 1e6);
+            // amdgpu labels them sclk and mclk.
+            match label.as_deref() {
+                Some("mclk") => reading.memory_clock = mhz,
+                Some("sclk") => reading.core_clock = mhz,
+                _ if sensor == "freq1" => reading.core_clock = mhz,
+                _ if sensor == "freq2" => reading.memory_clock = mhz,
+                _ => {}
+            }
+        }
+    }
+    reading
+}
+
+/// PCI address of the device a hwmon belongs to, if it is a PCI device.
+fn pci_slot(device: &Path) -> Option<String> {
+    std::fs::read_to_string(device.join("uevent")).ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("PCI_SLOT_NAME="))
+        .map(str::to_string)
+}
+
+fn list_dir(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir).into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    paths.sort();
+    paths.into_iter()
+}
+
+fn read_string(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    read_string(path)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("jxpoolminer-test-telemetry-{}-{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&root);
+            Self(root)
+        }
+
+        fn write(&self, path: &str, value: &str) {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, format!("{}\n", value)).unwrap();
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn devices() -> Vec<Device> {
+        let gpu = Device::new(
+            "gpu-0000:03:00.0".to_string(),
+            "GPU".to_string(),
+            DeviceType::GPU { vendor: jxpoolminer_core::GPUVendor::AMD },
+        );
+        let cpu = Device::new("cpu-0".to_string(), "CPU".to_string(), DeviceType::CPU { cores: 8 });
+        vec![gpu, cpu]
+    }
+
+    #[test]
+    fn test_maps_hwmon_sensors_to_devices() {
+        let sysfs = Fixture::new("hwmon");
+        sysfs.write("class/hwmon/hwmon0/name", "coretemp");
+        sysfs.write("class/hwmon/hwmon0/temp1_input", "61000");
+        sysfs.write("class/hwmon/hwmon0/temp2_input", "58000");
+        sysfs.write("class/hwmon/hwmon1/name", "amdgpu");
+        sysfs.write("class/hwmon/hwmon1/device/uevent", "DRIVER=amdgpu\nPCI_SLOT_NAME=0000:03:00.0");
+        sysfs.write("class/hwmon/hwmon1/temp1_input", "70000");
+        sysfs.write("class/hwmon/hwmon1/temp2_input", "85000");
+        sysfs.write("class/hwmon/hwmon1/fan1_input", "1850");
+        sysfs.write("class/hwmon/hwmon1/power1_average", "187000000");
+        sysfs.write("class/hwmon/hwmon1/freq1_input", "2100000000");
+        sysfs.write("class/hwmon/hwmon1/freq1_label", "sclk");
+        sysfs.write("class/hwmon/hwmon1/freq2_input", "1000000000");
+        sysfs.write("class/hwmon/hwmon1/freq2_label", "mclk");
+        // Sensors of devices we do not mine on are ignored.
+        sysfs.write("class/hwmon/hwmon2/name", "nvme");
+        sysfs.write("class/hwmon/hwmon2/device/uevent", "PCI_SLOT_NAME=0000:04:00.0");
+        sysfs.write("class/hwmon/hwmon2/temp1_input", "40000");
+
+        let readings = Telemetry::new(&devices()).with_sysfs_root(&sysfs.0).read();
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings["gpu-0000:03:00.0"], Reading {
+            temperature: Some(85.0),
+            fan_rpm: Some(1850),
+            power: Some(187.0),
+            core_clock: Some(2100.0),
+            memory_clock: Some(1000.0),
+        });
+        assert_eq!(readings["cpu-0"], Reading { temperature: Some(61.0), ..Reading::default() });
+    }
+
+    #[test]
+    fn test_cpu_falls_back_to_thermal_zones_and_rapl() {
+        let sysfs = Fixture::new("cpu");
+        sysfs.write("class/thermal/thermal_zone0/type", "acpitz");
+        sysfs.write("class/thermal/thermal_zone0/temp", "90000");
+        sysfs.write("class/thermal/thermal_zone1/type", "x86_pkg_temp");
+        sysfs.write("class/thermal/thermal_zone1/temp", "55000");
+        sysfs.write("class/powercap/intel-rapl:0/energy_uj", "1000000");
+        sysfs.write("class/powercap/intel-rapl:0/max_energy_range_uj", "200000000");
+        sysfs.write("class/powercap/intel-rapl:0:0/energy_uj", "0");
+
+        let mut telemetry = Telemetry::new(&devices()).with_sysfs_root(&sysfs.0);
+        let start = Instant::now();
+        let first = telemetry.read_at(start);
+        assert_eq!(first["cpu-0"], Reading { temperature: Some(55.0), ..Reading::default() });
+
+        sysfs.write("class/powercap/intel-rapl:0/energy_uj", "131000000");
+        let second = telemetry.read_at(start + Duration::from_secs(2));
+        assert_eq!(second["cpu-0"].power, Some(65.0));
+
+        // Wraps around past max_energy_range_uj.
+        sysfs.write("class/powercap/intel-rapl:0/energy_uj", "71000000");
+        let third = telemetry.read_at(start + Duration::from_secs(4));
+        assert_eq!(third["cpu-0"].power, Some(70.0));
+    }
+
+    #[tokio::test]
+    async fn test_streams_readings_into_collector() {
+        let sysfs = Fixture::new("stream");
+        sysfs.write("class/hwmon/hwmon0/name", "k10temp");
+        sysfs.write("class/hwmon/hwmon0/device/uevent", "DRIVER=k10temp\nPCI_SLOT_NAME=0000:00:18.3");
+        sysfs.write("class/hwmon/hwmon0/temp1_input", "48500");
+
+        let collector = Arc::new(Collector::new());
+        let telemetry = Telemetry::new(&devices())
+            .with_sysfs_root(&sysfs.0)
+            .with_interval(Duration::from_millis(10));
+        let mut readings = telemetry.subscribe();
+        let task = telemetry.spawn(collector.clone());
+        readings.changed().await.unwrap();
+        assert_eq!(readings.borrow()["cpu-0"].temperature, Some(48.5));
+        task.abort();
+        assert_eq!(collector.device_stats("cpu-0").await.unwrap().temperature, 48.5);
+    }
+}
@@ -1,21 +1,32 @@
 use jxpoolminer_config::Config;
-use jxpoolminer_core::Device;
-use jxpoolminer_mining::Engine;
-use jxpoolminer_pool::Client;
+use jxpoolminer_core::{BenchmarkResults, Device, DeviceSettings};
+use jxpoolminer_mining::{Benchmark, Coordinator};
+use jxpoolminer_mining::algorithms::{self, ethash::DagStage};
+use jxpoolminer_pool::{Client, ConnectionState};
 use jxpoolminer_stats::Collector;
 use anyhow::Result;
 use eframe::egui;
 use egui_plot::{Line, Plot, PlotPoints};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
+/// How long the Devices tab benchmarks each algorithm of a device.
+const BENCHMARK_DURATION: Duration = Duration::from_secs(30);
+
 pub struct MinerApp {
     config: Config,
-    engine: Arc<Engine>,
+    coordinator: Arc<Coordinator>,
     pool_client: Arc<Client>,
     stats_collector: Arc<Collector>,
     current_tab: Tab,
     devices: Arc<RwLock<Vec<Device>>>,
+    benchmarks: Arc<RwLock<BenchmarkResults>>,
+    /// Devices with a benchmark under way.
+    benchmarking: Arc<RwLock<HashSet<String>>>,
+    /// Core lists being typed into the CPU limit editors, per device.
+    affinity_inputs: HashMap<String, String>,
     runtime: tokio::runtime::Handle,
 }
 
@@ -33,19 +44,26 @@ impl MinerApp {
     pub fn new(
         config: Config,
         devices: Vec<Device>,
-        engine: Engine,
-        pool_client: Client,
-        stats_collector: Collector,
+        coordinator: Arc<Coordinator>,
+        pool_client: Arc<Client>,
+        stats_collector: Arc<Collector>,
     ) -> Self {
         let runtime = tokio::runtime::Handle::current();
+        let benchmarks = jxpoolminer_config::load_benchmarks().unwrap_or_else(|e| {
+            tracing::warn!("Ignoring stored benchmark results: {}", e);
+            BenchmarkResults::default()
+        });
         
         Self {
             config,
-            engine: Arc::new(engine),
-            pool_client: Arc::new(pool_client),
-            stats_collector: Arc::new(stats_collector),
+            coordinator,
+            pool_client,
+            stats_collector,
             current_tab: Tab::Dashboard,
             devices: Arc::new(RwLock::new(devices)),
+            benchmarks: Arc::new(RwLock::new(benchmarks)),
+            benchmarking: Arc::new(RwLock::new(HashSet::new())),
+            affinity_inputs: HashMap::new(),
             runtime,
         }
     }
@@ -95,6 +113,18 @@ impl eframe::App for MinerApp {
 }
 
 impl MinerApp {
+    /// Detected devices with their status as the engine currently sees it.
+    async fn current_devices(&self) -> Vec<Device> {
+        let engine_devices = self.coordinator.engine().devices().await;
+        let mut devices = self.devices.read().await.clone();
+        for device in &mut devices {
+            if let Some(engine_device) = engine_devices.iter().find(|d| d.id == device.id) {
+                device.status = engine_device.status.clone();
+            }
+        }
+        devices
+    }
+    
     fn show_dashboard(&mut self, ui: &mut egui::Ui) {
         ui.heading("Dashboard");
         ui.separator();
@@ -111,9 +141,7 @@ impl MinerApp {
             stats_collector.global_stats().await
         });
         
-        let devices = runtime.block_on(async {
-            self.devices.read().await.clone()
-        });
+        let devices = runtime.block_on(self.current_devices());
         
         let active_devices = devices.iter()
             .filter(|d| matches!(d.status, jxpoolminer_core::DeviceStatus::Mining))
@@ -152,7 +180,14 @@ impl MinerApp {
                 });
             });
         });
-        
+
+        let dag_progress = self.coordinator.engine().dag_progress().borrow().clone();
+        if let Some(progress) = dag_progress.filter(|p| p.stage != DagStage::Ready) {
+            ui.add_space(10.0);
+            ui.label(format!("Generating {} DAG for epoch {}", algorithms::registry().name(&progress.algorithm), progress.epoch));
+            ui.add(egui::ProgressBar::new(progress.fraction() as f32).show_percentage());
+        }
+
         ui.add_space(20.0);
         ui.label("Hashrate History (Last 24h)");
         ui.separator();
@@ -196,9 +231,7 @@ impl MinerApp {
         ui.separator();
         
         let runtime = self.runtime.clone();
-        let devices = runtime.block_on(async {
-            self.devices.read().await.clone()
-        });
+        let devices = runtime.block_on(self.current_devices());
         
         if devices.is_empty() {
             ui.label("No devices detected. Please check your hardware.");
@@ -220,6 +253,8 @@ impl MinerApp {
                     ui.separator();
                     ui.label("Hashrate");
                     ui.separator();
+                    ui.label("Benchmark");
+                    ui.separator();
                     ui.label("Status");
                     ui.separator();
                     ui.label("Action");
@@ -245,31 +280,38 @@ impl MinerApp {
                     ui.separator();
                     
                     // Algorithm
-                    let algorithm = if !device.capabilities.supported_algorithms.is_empty() {
-                        format!("{:?}", device.capabilities.supported_algorithms[0])
-                    } else {
-                        "None".to_string()
+                    let algorithm = match device.capabilities.supported_algorithms.first() {
+                        Some(algorithm) => algorithms::registry().name(algorithm),
+                        None => "None".to_string(),
                     };
                     ui.label(algorithm);
                     ui.separator();
                     
-                    // Hashrate
-                    let hashrate = device.capabilities.max_hashrate;
-                    let hashrate_str = if hashrate > 1_000_000_000_000.0 {
-                        format!("{:.2} TH/s", hashrate / 1_000_000_000_000.0)
-                    } else if hashrate > 1_000_000.0 {
-                        format!("{:.2} MH/s", hashrate / 1_000_000.0)
-                    } else {
-                        format!("{:.2} H/s", hashrate)
+                    // Measured hashrate
+                    let hashrate = runtime.block_on(self.coordinator.engine().hashrate(&device.id));
+                    ui.label(format!("{} (avg {})", format_hashrate(hashrate.instant), format_hashrate(hashrate.smoothed)));
+                    ui.separator();
+                    
+                    // Last benchmark of the device's main algorithm
+                    let is_benchmarking = runtime.block_on(self.benchmarking.read()).contains(&device.id);
+                    let benchmark = device.capabilities.supported_algorithms.first()
+                        .and_then(|algorithm| runtime.block_on(self.benchmarks.read()).get(&device.id, algorithm).cloned());
+                    let benchmark_str = match (is_benchmarking, benchmark) {
+                        (true, _) => "Running...".to_string(),
+                        (false, Some(result)) => {
+                            let per_watt = result.hashes_per_watt().map_or(String::new(), |h| format!(", {:.0} H/W", h));
+                            format!("{} ±{:.1}%{}", format_hashrate(result.hashrate), result.variation() * 100.0, per_watt)
+                        }
+                        (false, None) => "-".to_string(),
                     };
-                    ui.label(hashrate_str);
+                    ui.label(benchmark_str);
                     ui.separator();
                     
                     // Status
                     let (status_text, status_color) = match &device.status {
                         jxpoolminer_core::DeviceStatus::Idle => ("Idle", egui::Color32::GRAY),
                         jxpoolminer_core::DeviceStatus::Mining => ("Mining", egui::Color32::GREEN),
-                        jxpoolminer_core::DeviceStatus::Error(_) => ("Error", egui::Color32::RED),
+                        jxpoolminer_core::DeviceStatus::Error(reason) => (reason.as_str(), egui::Color32::RED),
                     };
                     ui.colored_label(status_color, status_text);
                     ui.separator();
@@ -277,33 +319,28 @@ impl MinerApp {
                     // Action button
                     let is_mining = matches!(device.status, jxpoolminer_core::DeviceStatus::Mining);
                     let device_id = device.id.clone();
-                    let engine = self.engine.clone();
+                    let coordinator = self.coordinator.clone();
                     let runtime = self.runtime.clone();
                     
                     if is_mining {
                         if ui.button("Stop").clicked() {
-                            runtime.spawn(async move {
-                                if let Err(e) = engine.stop_mining(&device_id).await {
-                                    tracing::error!("Failed to stop mining on {}: {}", device_id, e);
-                                }
-                            });
+                            if let Err(e) = coordinator.stop_device(&device_id) {
+                                tracing::error!("Failed to stop mining on {}: {}", device_id, e);
+                            }
                         }
-                    } else {
+                    } else if !is_benchmarking {
                         if ui.button("Start").clicked() {
-                            let pool_client = self.pool_client.clone();
                             runtime.spawn(async move {
-                                match pool_client.receive_job().await {
-                                    Ok(job) => {
-                                        if let Err(e) = engine.start_mining(&device_id, job).await {
-                                            tracing::error!("Failed to start mining on {}: {}", device_id, e);
-                                        }
-                                    }
-                                    Err(e) => {
-                                        tracing::error!("Failed to get job from pool: {}", e);
-                                    }
+                                if let Err(e) = coordinator.start_device(&device_id).await {
+                                    tracing::error!("Failed to start mining on {}: {}", device_id, e);
                                 }
                             });
                         }
+                        let benchmark = ui.add_enabled(Benchmark::supports(device), egui::Button::new("Benchmark"))
+                            .on_disabled_hover_text("Only CPUs can be benchmarked");
+                        if benchmark.clicked() {
+                            self.start_benchmark(device.clone());
+                        }
                     }
                 });
                 ui.add_space(3.0);
@@ -311,6 +348,31 @@ impl MinerApp {
         });
     }
     
+    /// Benchmarks an idle device in the background, then stores the
+    /// results and shows the measured hashrate as the device's maximum.
+    fn start_benchmark(&self, device: Device) {
+        let benchmarks = self.benchmarks.clone();
+        let benchmarking = self.benchmarking.clone();
+        let devices = self.devices.clone();
+        self.runtime.spawn(async move {
+            if !benchmarking.write().await.insert(device.id.clone()) {
+                return;
+            }
+            let results = Benchmark::new(BENCHMARK_DURATION).run_all(std::slice::from_ref(&device)).await;
+            
+            let mut stored = benchmarks.write().await;
+            for result in results {
+                stored.record(result);
+            }
+            stored.apply(&mut devices.write().await);
+            if let Err(e) = jxpoolminer_config::save_benchmarks(&stored) {
+                tracing::error!("Failed to save benchmark results: {}", e);
+            }
+            drop(stored);
+            benchmarking.write().await.remove(&device.id);
+        });
+    }
+    
     fn show_pool(&mut self, ui: &mut egui::Ui) {
         ui.heading("Pool Connection");
         ui.separator();
@@ -318,18 +380,25 @@ impl MinerApp {
         let runtime = self.runtime.clone();
         let pool_client = self.pool_client.clone();
         
-        let is_connected = runtime.block_on(async {
-            pool_client.is_connected().await
+        let state = pool_client.state();
+        let is_connected = state.is_connected();
+        let (active_pool, difficulty) = runtime.block_on(async {
+            (pool_client.active_pool().await, pool_client.difficulty().await)
         });
         
         ui.horizontal(|ui| {
             ui.label("Status:");
-            if is_connected {
-                ui.colored_label(egui::Color32::GREEN, "● Connected");
-            } else {
-                ui.colored_label(egui::Color32::RED, "● Disconnected");
-            }
+            let color = match state {
+                ConnectionState::Connected => egui::Color32::GREEN,
+                ConnectionState::FailedOver | ConnectionState::Connecting => egui::Color32::YELLOW,
+                ConnectionState::Disconnected => egui::Color32::RED,
+            };
+            ui.colored_label(color, format!("● {}", state.label()));
         });
+        if let Some(ref url) = active_pool {
+            ui.label(format!("Active pool: {}", url));
+            ui.label(format!("Share difficulty: {}", difficulty));
+        }
         
         ui.add_space(10.0);
         
@@ -341,6 +410,12 @@ impl MinerApp {
             ui.label(format!("Wallet: {}", self.config.pool.wallet_address));
             ui.label(format!("Worker: {}", self.config.pool.worker_name));
             ui.label(format!("TLS: {}", if self.config.pool.use_tls { "Enabled" } else { "Disabled" }));
+            if let Some(ref pin) = self.config.pool.tls_pinned_sha256 {
+                ui.label(format!("TLS pin: {}", pin));
+            }
+            if self.config.pool.tls_insecure {
+                ui.colored_label(egui::Color32::YELLOW, "⚠ TLS certificate verification disabled");
+            }
         });
         
         ui.add_space(20.0);
@@ -360,7 +435,7 @@ impl MinerApp {
             
             if ui.button("Reconnect").clicked() {
                 tracing::info!("Reconnecting to pool...");
-                // Pool client will auto-reconnect on next operation
+                self.pool_client.reconnect();
             }
         });
         
@@ -370,8 +445,13 @@ impl MinerApp {
         egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
             if is_connected {
                 ui.label("✅ Connected to pool successfully");
+                if state == ConnectionState::FailedOver {
+                    ui.label("⚠️  Primary pool unreachable, mining on fallback");
+                }
                 ui.label("✅ Worker authenticated");
                 ui.label("✅ Receiving jobs");
+            } else if state == ConnectionState::Connecting {
+                ui.label("⏳ Connecting to pool...");
             } else {
                 ui.label("❌ Not connected to pool");
                 ui.label("ℹ️  Check pool URL and internet connection");
@@ -441,14 +521,42 @@ impl MinerApp {
                             ui.separator();
                             ui.label(format!("Hashrate: {:.2} MH/s", stats.hashrate / 1_000_000.0));
                             ui.separator();
+                            ui.label(format!("Effective: {:.2} MH/s", stats.effective_hashrate / 1_000_000.0));
+                            ui.separator();
                             ui.label(format!("Accepted: {}", stats.shares_accepted));
                             ui.separator();
                             ui.label(format!("Rejected: {}", stats.shares_rejected));
+                            ui.separator();
+                            ui.label(format!("HW errors: {}", stats.hardware_errors));
+                            ui.separator();
+                            ui.label(format!("Temp: {:.0}°C", stats.temperature));
+                            ui.separator();
+                            ui.label(format!("Power: {:.0} W", stats.power_usage));
+                            ui.separator();
+                            ui.label(format!("Fan: {} RPM", stats.fan_speed));
+                            if stats.core_clock > 0.0 {
+                                ui.separator();
+                                ui.label(format!("Clocks: {:.0}/{:.0} MHz", stats.core_clock, stats.memory_clock));
+                            }
                         });
                     });
                 }
             });
             
+            let events = runtime.block_on(async {
+                stats_collector.events().await
+            });
+            if !events.is_empty() {
+                ui.add_space(10.0);
+                ui.label("Device Events");
+                ui.separator();
+                egui::ScrollArea::vertical().id_source("device_events").max_height(100.0).show(ui, |ui| {
+                    for event in events.iter().rev() {
+                        ui.label(format!("{} {}: {}", event.timestamp.format("%H:%M:%S"), event.device_id, event.message));
+                    }
+                });
+            }
+            
             ui.add_space(10.0);
             ui.label("Share Acceptance Over Time");
             ui.separator();
@@ -510,6 +618,13 @@ impl MinerApp {
         ui.checkbox(&mut self.config.mining.auto_detect_devices, "Auto-detect devices");
         ui.checkbox(&mut self.config.mining.auto_assign_algorithms, "Auto-assign algorithms");
         
+        ui.add_space(10.0);
+        ui.label("CPU Limits (applied immediately)");
+        let devices = self.runtime.block_on(self.devices.read()).clone();
+        for device in devices.iter().filter(|d| matches!(d.device_type, jxpoolminer_core::DeviceType::CPU { .. })) {
+            self.show_cpu_limits(ui, device);
+        }
+        
         ui.add_space(10.0);
         ui.label("Appearance");
         ui.horizontal(|ui| {
@@ -520,7 +635,63 @@ impl MinerApp {
         
         ui.add_space(20.0);
         if ui.button("Save Settings").clicked() {
-            // Save settings
+            if let Err(e) = jxpoolminer_config::save_config(&self.config) {
+                tracing::error!("Failed to save settings: {}", e);
+            }
+        }
+    }
+    
+    /// Editors for a CPU device's threads, duty cycle, priority and core
+    /// pinning. Changes go straight to the engine; Save Settings keeps them.
+    fn show_cpu_limits(&mut self, ui: &mut egui::Ui, device: &Device) {
+        let jxpoolminer_core::DeviceType::CPU { cores } = device.device_type else {
+            return;
+        };
+        let mut settings = self.config.mining.devices.get(&device.id).cloned().unwrap_or_default();
+        let mut changed = false;
+        
+        ui.group(|ui| {
+            ui.label(&device.name);
+            ui.horizontal(|ui| {
+                let mut all_cores = settings.threads.is_none();
+                changed |= ui.checkbox(&mut all_cores, "One thread per core").changed();
+                let mut threads = settings.threads.unwrap_or(cores);
+                if !all_cores {
+                    changed |= ui.add(egui::DragValue::new(&mut threads).clamp_range(1..=cores.max(1) * 2).prefix("Threads: ")).changed();
+                }
+                settings.threads = (!all_cores).then_some(threads);
+            });
+            
+            let mut duty = settings.duty_cycle * 100.0;
+            changed |= ui.add(egui::Slider::new(&mut duty, DeviceSettings::MIN_DUTY_CYCLE * 100.0..=100.0).suffix("%").text("Duty cycle")).changed();
+            settings.duty_cycle = duty / 100.0;
+            
+            changed |= ui.add(egui::Slider::new(&mut settings.nice, 0..=DeviceSettings::MAX_NICE).text("Niceness")).changed();
+            
+            ui.horizontal(|ui| {
+                ui.label("Pin to cores:");
+                let input = self.affinity_inputs.entry(device.id.clone()).or_insert_with(|| {
+                    settings.affinity.iter().map(|core| core.to_string()).collect::<Vec<_>>().join(",")
+                });
+                if ui.text_edit_singleline(input).lost_focus() {
+                    let affinity: Vec<usize> = input.split(',').filter_map(|core| core.trim().parse().ok()).collect();
+                    if affinity != settings.affinity {
+                        settings.affinity = affinity;
+                        changed = true;
+                    }
+                }
+            });
+        });
+        
+        if changed {
+            self.config.mining.devices.insert(device.id.clone(), settings.clone());
+            let engine = self.coordinator.engine().clone();
+            let device_id = device.id.clone();
+            self.runtime.spawn(async move {
+                if let Err(e) = engine.set_device_settings(&device_id, settings).await {
+                    tracing::error!("Failed to apply CPU limits to {}: {}", device_id, e);
+                }
+            });
         }
     }
     
@@ -530,9 +701,8 @@ impl MinerApp {
         
         let runtime = self.runtime.clone();
         let pool_client = self.pool_client.clone();
-        let devices = runtime.block_on(async {
-            self.devices.read().await.clone()
-        });
+        let engine = self.coordinator.engine().clone();
+        let devices = runtime.block_on(self.current_devices());
         
         ui.label("System Information:");
         ui.add_space(5.0);
@@ -555,7 +725,15 @@ impl MinerApp {
                     ui.label(format!("Type: {:?}", device.device_type));
                     ui.label(format!("Max Hashrate: {:.2} MH/s", device.capabilities.max_hashrate / 1_000_000.0));
                     ui.label(format!("Memory: {} MB", device.capabilities.memory / 1_024 / 1_024));
-                    ui.label(format!("Algorithms: {:?}", device.capabilities.supported_algorithms));
+                    let names: Vec<_> = device.capabilities.supported_algorithms.iter().map(|a| algorithms::registry().name(a)).collect();
+                    ui.label(format!("Algorithms: {}", names.join(", ")));
+                    if let jxpoolminer_core::DeviceType::CPU { .. } = device.device_type {
+                        let features: Vec<_> = device.capabilities.cpu_features.iter().map(|f| f.name()).collect();
+                        ui.label(format!("CPU Features: {}", if features.is_empty() { "none".to_string() } else { features.join(", ") }));
+                        if let Some(kernel) = runtime.block_on(engine.sha256_kernel(&device.id)) {
+                            ui.label(format!("SHA-256 Kernel: {} ({} lanes)", kernel.name(), kernel.lanes()));
+                        }
+                    }
                     ui.label(format!("Status: {:?}", device.status));
                 });
                 ui.add_space(5.0);
@@ -566,15 +744,19 @@ impl MinerApp {
         ui.label("Connection Status:");
         ui.separator();
         
-        let is_connected = runtime.block_on(async {
-            pool_client.is_connected().await
+        let (is_connected, protocol) = runtime.block_on(async {
+            (pool_client.is_connected().await, pool_client.protocol_version().await)
         });
         
         egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
             if is_connected {
                 ui.colored_label(egui::Color32::GREEN, "✅ Pool connection active");
-                ui.label("ℹ️  Stratum protocol: V1");
-                ui.label("ℹ️  Connection type: TCP");
+                ui.label(format!("ℹ️  Stratum protocol: V{}", protocol.unwrap_or(1)));
+                ui.label(if protocol == Some(2) {
+                    "ℹ️  Connection type: TCP (Noise encrypted)"
+                } else {
+                    "ℹ️  Connection type: TCP"
+                });
             } else {
                 ui.colored_label(egui::Color32::RED, "❌ Pool connection inactive");
                 ui.label("⚠️  Check pool URL and network connection");
@@ -590,7 +772,7 @@ impl MinerApp {
                 
                 // Export debug information
                 let mut debug_info = String::new();
-                debug_info.push_str(&format!("JxPoolMiner Debug Info\n"));
+                debug_info.push_str("JxPoolMiner Debug Info\n");
                 debug_info.push_str(&format!("Version: {}\n", env!("CARGO_PKG_VERSION")));
                 debug_info.push_str(&format!("Timestamp: {}\n\n", chrono::Utc::now()));
                 debug_info.push_str(&format!("Devices: {}\n", devices.len()));
@@ -627,9 +809,9 @@ impl MinerApp {
 pub async fn run(
     config: Config,
     devices: Vec<Device>,
-    mining_engine: Engine,
-    pool_client: Client,
-    stats_collector: Collector,
+    coordinator: Arc<Coordinator>,
+    pool_client: Arc<Client>,
+    stats_collector: Arc<Collector>,
 ) -> Result<()> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -638,7 +820,7 @@ pub async fn run(
         ..Default::default()
     };
     
-    let app = MinerApp::new(config, devices, mining_engine, pool_client, stats_collector);
+    let app = MinerApp::new(config, devices, coordinator, pool_client, stats_collector);
     
     eframe::run_native(
         "JxPoolMiner",
@@ -648,3 +830,13 @@ pub async fn run(
     
     Ok(())
 }
+
+fn format_hashrate(hashrate: f64) -> String {
+    if hashrate > 1_000_000_000_000.0 {
+        format!("{:.2} TH/s", hashrate / 1_000_000_000_000.0)
+    } else if hashrate > 1_000_000.0 {
+        format!("{:.2} MH/s", hashrate / 1_000_000.0)
+    } else {
+        format!("{:.2} H/s", hashrate)
+    }
+}
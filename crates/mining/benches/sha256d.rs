@@ -0,0 +1,46 @@
+//! SHA-256d throughput: a plain `sha256d` per nonce against the midstate
+//! on every kernel this CPU supports.
+//!
+//! Run with `cargo bench -p jxpoolminer-mining --bench sha256d`.
+
+use jxpoolminer_mining::algorithms::sha256::{Kernel, Midstate};
+use jxpoolminer_mining::header::{sha256d, NONCE_OFFSET};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const HASHES: u32 = 2_000_000;
+const BATCH: usize = 256;
+
+fn report(name: &str, elapsed: Duration) -> f64 {
+    let elapsed = elapsed.max(Duration::from_nanos(1));
+    let rate = HASHES as f64 / elapsed.as_secs_f64();
+    println!("{:<10} {:>8.2} MH/s ({:?} for {} hashes)", name, rate / 1e6, elapsed, HASHES);
+    rate
+}
+
+fn main() {
+    let prefix: Vec<u8> = (0..NONCE_OFFSET as u8).collect();
+
+    let started = Instant::now();
+    for nonce in 0..HASHES {
+        let mut header = prefix.clone();
+        header.extend_from_slice(&black_box(nonce).to_le_bytes());
+        black_box(sha256d(&header));
+    }
+    let naive = report("naive", started.elapsed());
+
+    let midstate = Midstate::new(&prefix);
+    let nonces: Vec<u32> = (0..HASHES).collect();
+    let mut out = Vec::with_capacity(BATCH);
+    for kernel in Kernel::available() {
+        let started = Instant::now();
+        for batch in nonces.chunks(BATCH) {
+            out.clear();
+            kernel.hash(&midstate, black_box(batch), &mut out);
+            black_box(&out);
+        }
+        let rate = report(kernel.name(), started.elapsed());
+        println!("{:<10} {:>8.2}x naive", "", rate / naive);
+    }
+    println!("active     {}", Kernel::active().name());
+}
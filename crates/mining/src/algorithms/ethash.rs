@@ -1,78 +1,636 @@
-use jxpoolminer_core::{Device, MiningJob, Share};
+//! Ethash proof of work, plus Ethereum Classic's Etchash epoch schedule
+//! (ECIP-1099: 60 000-block epochs from block 11 700 000).
+//!
+//! A light cache is derived from the epoch's seed hash and expanded into
+//! the full dataset (the DAG). `hashimoto_light` recomputes the dataset
+//! items it needs from the cache, `hashimoto_full` reads them from the DAG;
+//! both give the same mix digest and result.
+
+use super::MiningAlgorithm;
+use jxpoolminer_core::{Algorithm, MiningJob};
 use anyhow::Result;
-use sha3::{Keccak256, Digest};
-use tokio::sync::mpsc;
-
-pub async fn mine(
-    device: &Device,
-    job: MiningJob,
-    cancel_rx: &mut mpsc::Receiver<()>,
-) -> Result<Share> {
-    let mut nonce = 0u64;
-    let target = job.target.clone();
-    
-    loop {
-        tokio::select! {
-            _ = cancel_rx.recv() => {
-                tracing::info!("Mining cancelled for device: {}", device.id);
-                anyhow::bail!("Mining cancelled");
+use rayon::prelude::*;
+use sha3::{Digest, Keccak256, Keccak512};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::watch;
+
+pub const EPOCH_LENGTH: u64 = 30_000;
+pub const ECIP1099_EPOCH_LENGTH: u64 = 60_000;
+pub const ECIP1099_BLOCK: u64 = 11_700_000;
+
+const DATASET_BYTES_INIT: u64 = 1 << 30;
+const DATASET_BYTES_GROWTH: u64 = 1 << 23;
+const CACHE_BYTES_INIT: u64 = 1 << 24;
+const CACHE_BYTES_GROWTH: u64 = 1 << 17;
+const HASH_BYTES: usize = 64;
+const MIX_BYTES: usize = 128;
+const DATASET_PARENTS: u32 = 256;
+const CACHE_ROUNDS: usize = 3;
+const ACCESSES: u32 = 64;
+const NODE_WORDS: usize = HASH_BYTES / 4;
+const MIX_WORDS: usize = MIX_BYTES / 4;
+/// Furthest epoch a seed hash is searched for.
+const MAX_SEED_ROUNDS: u64 = 4096;
+/// Dataset items generated between progress reports.
+const PROGRESS_CHUNK: usize = 4096;
+/// Ethash boundaries are 2^256 / difficulty.
+const DIFF1_TARGET: [u8; 32] = [0xff; 32];
+
+/// 64 bytes as sixteen little-endian words.
+type Node = [u32; NODE_WORDS];
+
+fn fnv(a: u32, b: u32) -> u32 {
+    a.wrapping_mul(0x0100_0193) ^ b
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+fn keccak512(data: &[u8]) -> Node {
+    node_from_bytes(&Keccak512::digest(data))
+}
+
+fn node_from_bytes(bytes: &[u8]) -> Node {
+    let mut node = [0u32; NODE_WORDS];
+    for (word, chunk) in node.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    node
+}
+
+fn node_to_bytes(node: &Node) -> [u8; HASH_BYTES] {
+    let mut bytes = [0u8; HASH_BYTES];
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(node) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    bytes
+}
+
+fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    (2..).take_while(|d| d * d <= n).all(|d| !n.is_multiple_of(d))
+}
+
+/// Light cache size in bytes for `epoch`.
+pub fn cache_size(epoch: u64) -> usize {
+    let mut size = CACHE_BYTES_INIT + CACHE_BYTES_GROWTH * epoch - HASH_BYTES as u64;
+    while !is_prime(size / HASH_BYTES as u64) {
+        size -= 2 * HASH_BYTES as u64;
+    }
+    size as usize
+}
+
+/// Full dataset size in bytes for `epoch`.
+pub fn full_size(epoch: u64) -> usize {
+    let mut size = DATASET_BYTES_INIT + DATASET_BYTES_GROWTH * epoch - MIX_BYTES as u64;
+    while !is_prime(size / MIX_BYTES as u64) {
+        size -= 2 * MIX_BYTES as u64;
+    }
+    size as usize
+}
+
+/// Keccak-256 applied `rounds` times to 32 zero bytes.
+fn seed_after(rounds: u64) -> [u8; 32] {
+    (0..rounds).fold([0u8; 32], |seed, _| keccak256(&seed))
+}
+
+/// Which chain's epoch schedule to follow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Ethash,
+    Etchash,
+}
+
+/// An epoch: its number sizes the cache and DAG, its seed fills them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Epoch {
+    pub number: u64,
+    pub seed: [u8; 32],
+}
+
+impl Epoch {
+    pub fn cache_size(&self) -> usize {
+        cache_size(self.number)
+    }
+
+    pub fn full_size(&self) -> usize {
+        full_size(self.number)
+    }
+}
+
+impl Variant {
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            Variant::Ethash => Algorithm::ETHASH,
+            Variant::Etchash => Algorithm::ETCHASH,
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Variant::Ethash => "ethash",
+            Variant::Etchash => "etchash",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Ethash => "Ethash",
+            Variant::Etchash => "Etchash",
+        }
+    }
+
+    fn activated(&self, block: u64) -> bool {
+        *self == Variant::Etchash && block >= ECIP1099_BLOCK
+    }
+
+    pub fn epoch_length(&self, block: u64) -> u64 {
+        if self.activated(block) {
+            ECIP1099_EPOCH_LENGTH
+        } else {
+            EPOCH_LENGTH
+        }
+    }
+
+    pub fn epoch(&self, block: u64) -> Epoch {
+        let number = block / self.epoch_length(block);
+        // Seeds keep advancing once per 30 000 blocks, so a doubled epoch
+        // takes two rounds.
+        let rounds = if self.activated(block) { number * 2 } else { number };
+        Epoch { number, seed: seed_after(rounds) }
+    }
+
+    /// The epoch a pool's seed hash refers to, found by walking the seed
+    /// chain. An empty seed means epoch 0.
+    pub fn epoch_for_seed(&self, seed: &[u8]) -> Option<Epoch> {
+        if seed.is_empty() {
+            return Some(self.epoch(0));
+        }
+        let mut candidate = [0u8; 32];
+        for rounds in 0..MAX_SEED_ROUNDS {
+            if candidate[..] == *seed {
+                return Some(self.epoch(rounds * EPOCH_LENGTH));
             }
-            result = async {
-                for _ in 0..5000 {
-                    let hash = ethash_hash(&job.header, nonce);
-                    
-                    if check_target(&hash, &target) {
-                        return Some(Share {
-                            job_id: job.id.clone(),
-                            nonce,
-                            hash: hash.to_vec(),
-                            device_id: device.id.clone(),
-                            timestamp: chrono::Utc::now(),
-                        });
-                    }
-                    
-                    nonce = nonce.wrapping_add(1);
-                }
-                None::<Share>
-            } => {
-                if let Some(share) = result {
-                    return Ok(share);
+            candidate = keccak256(&candidate);
+        }
+        None
+    }
+}
+
+pub struct LightCache {
+    nodes: Vec<Node>,
+}
+
+impl LightCache {
+    pub fn generate(seed: &[u8; 32], size: usize) -> Self {
+        let n = size / HASH_BYTES;
+        let mut nodes = Vec::with_capacity(n);
+        nodes.push(keccak512(seed));
+        for i in 1..n {
+            let previous = node_to_bytes(&nodes[i - 1]);
+            nodes.push(keccak512(&previous));
+        }
+
+        for _ in 0..CACHE_ROUNDS {
+            for i in 0..n {
+                let other = nodes[i][0] as usize % n;
+                let mut mixed = nodes[(i + n - 1) % n];
+                for (word, o) in mixed.iter_mut().zip(&nodes[other]) {
+                    *word ^= o;
                 }
+                nodes[i] = keccak512(&node_to_bytes(&mixed));
             }
         }
-        
-        tokio::task::yield_now().await;
+        Self { nodes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Keccak-256 of the serialised cache, as published with test vectors.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        for node in &self.nodes {
+            hasher.update(node_to_bytes(node));
+        }
+        hasher.finalize().into()
+    }
+
+    /// Dataset item `index`, derived from 256 pseudo-random cache nodes.
+    pub fn dataset_item(&self, index: u32) -> Node {
+        let n = self.nodes.len() as u32;
+        let mut mix = self.nodes[(index % n) as usize];
+        mix[0] ^= index;
+        mix = keccak512(&node_to_bytes(&mix));
+
+        for parent in 0..DATASET_PARENTS {
+            let cache_index = fnv(index ^ parent, mix[parent as usize % NODE_WORDS]) % n;
+            for (word, p) in mix.iter_mut().zip(&self.nodes[cache_index as usize]) {
+                *word = fnv(*word, *p);
+            }
+        }
+        keccak512(&node_to_bytes(&mix))
+    }
+}
+
+pub struct Dataset {
+    nodes: Vec<Node>,
+}
+
+impl Dataset {
+    /// Expands `cache` into a dataset of `size` bytes on the rayon pool,
+    /// calling `progress(items_done, items_total)` as chunks complete.
+    pub fn generate(cache: &LightCache, size: usize, progress: impl Fn(usize, usize) + Sync) -> Self {
+        let total = size / HASH_BYTES;
+        let mut nodes = vec![[0u32; NODE_WORDS]; total];
+        let done = AtomicUsize::new(0);
+
+        nodes.par_chunks_mut(PROGRESS_CHUNK).enumerate().for_each(|(chunk, items)| {
+            let first = chunk * PROGRESS_CHUNK;
+            for (offset, item) in items.iter_mut().enumerate() {
+                *item = cache.dataset_item((first + offset) as u32);
+            }
+            let finished = done.fetch_add(items.len(), Ordering::Relaxed) + items.len();
+            progress(finished, total);
+        });
+        Self { nodes }
+    }
+
+    pub fn size(&self) -> usize {
+        self.nodes.len() * HASH_BYTES
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowResult {
+    pub mix_digest: [u8; 32],
+    /// Compared against the share boundary as a big-endian integer.
+    pub result: [u8; 32],
+}
+
+fn hashimoto(header: &[u8], nonce: u64, full_size: usize, lookup: impl Fn(u32) -> Node) -> PowResult {
+    let mut seed_input = Vec::with_capacity(header.len() + 8);
+    seed_input.extend_from_slice(header);
+    seed_input.extend_from_slice(&nonce.to_le_bytes());
+    let seed = keccak512(&seed_input);
+
+    let mut mix = [0u32; MIX_WORDS];
+    mix[..NODE_WORDS].copy_from_slice(&seed);
+    mix[NODE_WORDS..].copy_from_slice(&seed);
+
+    let rows = (full_size / MIX_BYTES) as u32;
+    for i in 0..ACCESSES {
+        let row = fnv(i ^ seed[0], mix[i as usize % MIX_WORDS]) % rows;
+        for half in 0..2 {
+            let item = lookup(row * 2 + half);
+            let words = &mut mix[half as usize * NODE_WORDS..][..NODE_WORDS];
+            for (word, value) in words.iter_mut().zip(&item) {
+                *word = fnv(*word, *value);
+            }
+        }
+    }
+
+    let mut mix_digest = [0u8; 32];
+    for (chunk, words) in mix_digest.chunks_exact_mut(4).zip(mix.chunks_exact(4)) {
+        let compressed = fnv(fnv(fnv(words[0], words[1]), words[2]), words[3]);
+        chunk.copy_from_slice(&compressed.to_le_bytes());
+    }
+
+    let mut final_input = node_to_bytes(&seed).to_vec();
+    final_input.extend_from_slice(&mix_digest);
+    PowResult { mix_digest, result: keccak256(&final_input) }
+}
+
+/// Hashimoto with dataset items recomputed from the light cache.
+pub fn hashimoto_light(cache: &LightCache, full_size: usize, header: &[u8], nonce: u64) -> PowResult {
+    hashimoto(header, nonce, full_size, |index| cache.dataset_item(index))
+}
+
+/// Hashimoto reading dataset items from the DAG.
+pub fn hashimoto_full(dataset: &Dataset, header: &[u8], nonce: u64) -> PowResult {
+    hashimoto(header, nonce, dataset.size(), |index| dataset.nodes[index as usize])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DagStage {
+    Cache,
+    Dataset,
+    Ready,
+}
+
+/// How far the DAG for an epoch has been generated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DagProgress {
+    pub algorithm: Algorithm,
+    pub epoch: u64,
+    pub stage: DagStage,
+    pub items_done: usize,
+    pub items_total: usize,
+}
+
+impl DagProgress {
+    pub fn fraction(&self) -> f64 {
+        match self.stage {
+            DagStage::Cache => 0.0,
+            DagStage::Ready => 1.0,
+            DagStage::Dataset => self.items_done as f64 / self.items_total.max(1) as f64,
+        }
     }
 }
 
-fn ethash_hash(header: &[u8], nonce: u64) -> [u8; 32] {
-    let mut hasher = Keccak256::new();
-    hasher.update(header);
-    hasher.update(&nonce.to_le_bytes());
-    
-    let result = hasher.finalize();
-    let mut hash = [0u8; 32];
-    hash.copy_from_slice(&result);
-    
-    let mut hasher2 = Keccak256::new();
-    hasher2.update(&hash);
-    let result2 = hasher2.finalize();
-    hash.copy_from_slice(&result2);
-    
-    hash
+fn progress_sender() -> &'static watch::Sender<Option<DagProgress>> {
+    static PROGRESS: OnceLock<watch::Sender<Option<DagProgress>>> = OnceLock::new();
+    PROGRESS.get_or_init(|| watch::channel(None).0)
+}
+
+/// Latest DAG generation progress across all Ethash variants.
+pub fn dag_progress() -> watch::Receiver<Option<DagProgress>> {
+    progress_sender().subscribe()
 }
 
-fn check_target(hash: &[u8; 32], target: &[u8]) -> bool {
-    if target.is_empty() {
-        return hash[0] == 0 && hash[1] == 0 && hash[2] == 0;
+/// An epoch's cache, plus its DAG once generated.
+pub struct EpochData {
+    variant: Variant,
+    epoch: Epoch,
+    full_size: usize,
+    cache: LightCache,
+    dataset: OnceLock<Dataset>,
+    generating: AtomicBool,
+}
+
+impl EpochData {
+    pub fn new(variant: Variant, epoch: Epoch) -> Self {
+        Self::with_sizes(variant, epoch, epoch.cache_size(), epoch.full_size())
+    }
+
+    /// Non-standard sizes, for tests that cannot afford a real epoch.
+    pub fn with_sizes(variant: Variant, epoch: Epoch, cache_size: usize, full_size: usize) -> Self {
+        Self {
+            variant,
+            epoch,
+            full_size,
+            cache: LightCache::generate(&epoch.seed, cache_size),
+            dataset: OnceLock::new(),
+            generating: AtomicBool::new(false),
+        }
+    }
+
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
+    pub fn has_dataset(&self) -> bool {
+        self.dataset.get().is_some()
     }
-    
-    for i in 0..hash.len().min(target.len()) {
-        if hash[i] > target[i] {
-            return false;
-        } else if hash[i] < target[i] {
-            return true;
+
+    /// Starts building the DAG on the rayon pool unless already under way.
+    pub fn spawn_dataset(self: &Arc<Self>) {
+        if !self.generating.swap(true, Ordering::AcqRel) {
+            let data = self.clone();
+            rayon::spawn(move || data.generate_dataset());
+        }
+    }
+
+    /// Builds the DAG, reporting through `dag_progress`. Hashing falls back
+    /// to the light cache until it completes.
+    pub fn generate_dataset(&self) {
+        self.generating.store(true, Ordering::Release);
+        if self.has_dataset() {
+            return;
+        }
+        let report = |stage, items_done, items_total| {
+            progress_sender().send_replace(Some(DagProgress {
+                algorithm: self.variant.algorithm(),
+                epoch: self.epoch.number,
+                stage,
+                items_done,
+                items_total,
+            }));
+        };
+
+        tracing::info!("Generating {} DAG for epoch {} ({} MiB)", self.variant.name(), self.epoch.number, self.full_size >> 20);
+        let dataset = Dataset::generate(&self.cache, self.full_size, |done, total| report(DagStage::Dataset, done, total));
+        let total = dataset.nodes.len();
+        let _ = self.dataset.set(dataset);
+        report(DagStage::Ready, total, total);
+        tracing::info!("DAG for epoch {} ready", self.epoch.number);
+    }
+
+    pub fn compute(&self, header: &[u8], nonce: u64) -> PowResult {
+        match self.dataset.get() {
+            Some(dataset) => hashimoto_full(dataset, header, nonce),
+            None => hashimoto_light(&self.cache, self.full_size, header, nonce),
         }
     }
-    true
+}
+
+impl MiningAlgorithm for EpochData {
+    fn id(&self) -> &str {
+        self.variant.id()
+    }
+
+    fn name(&self) -> &str {
+        self.variant.name()
+    }
+
+    fn hash(&self, header: &[u8], nonce: u64) -> [u8; 32] {
+        self.compute(header, nonce).result
+    }
+
+    fn mix_digest(&self, header: &[u8], nonce: u64) -> Option<[u8; 32]> {
+        Some(self.compute(header, nonce).mix_digest)
+    }
+
+    /// Always from the light cache, so a corrupt DAG cannot vouch for itself.
+    fn reference_hash(&self, header: &[u8], nonce: u64) -> ([u8; 32], Option<[u8; 32]>) {
+        let pow = hashimoto_light(&self.cache, self.full_size, header, nonce);
+        (pow.result, Some(pow.mix_digest))
+    }
+
+    fn diff1_target(&self) -> [u8; 32] {
+        DIFF1_TARGET
+    }
+
+    fn wide_nonce(&self) -> bool {
+        true
+    }
+
+    fn batch_size(&self) -> usize {
+        if self.has_dataset() { 2048 } else { 64 }
+    }
+
+    fn is_warming_up(&self) -> bool {
+        self.generating.load(Ordering::Acquire) && !self.has_dataset()
+    }
+}
+
+/// Registry entry for one variant. Jobs are mined through the `EpochData`
+/// their seed hash selects; epochs are kept once built.
+pub struct Ethash {
+    variant: Variant,
+    full_dag: bool,
+    epochs: Mutex<HashMap<u64, Arc<EpochData>>>,
+}
+
+impl Ethash {
+    pub fn new(variant: Variant) -> Self {
+        Self {
+            variant,
+            full_dag: true,
+            epochs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Mine from the light cache only, never allocating the DAG.
+    pub fn light_only(mut self) -> Self {
+        self.full_dag = false;
+        self
+    }
+
+    /// Cache for `epoch`, generating it on first use.
+    pub fn epoch_data(&self, epoch: Epoch) -> Arc<EpochData> {
+        let mut epochs = self.epochs.lock().unwrap();
+        if let Some(data) = epochs.get(&epoch.number) {
+            return data.clone();
+        }
+
+        progress_sender().send_replace(Some(DagProgress {
+            algorithm: self.variant.algorithm(),
+            epoch: epoch.number,
+            stage: DagStage::Cache,
+            items_done: 0,
+            items_total: epoch.full_size() / HASH_BYTES,
+        }));
+        let data = Arc::new(EpochData::new(self.variant, epoch));
+        // Older epochs are dropped, but the previous one stays around for
+        // jobs still arriving from it around the epoch switch.
+        epochs.retain(|number, _| *number + 1 >= epoch.number);
+        epochs.insert(epoch.number, data.clone());
+        data
+    }
+}
+
+impl MiningAlgorithm for Ethash {
+    fn id(&self) -> &str {
+        self.variant.id()
+    }
+
+    fn name(&self) -> &str {
+        self.variant.name()
+    }
+
+    /// Hashes against epoch 0 in light mode; jobs are mined through
+    /// `prepare`.
+    fn hash(&self, header: &[u8], nonce: u64) -> [u8; 32] {
+        self.epoch_data(self.variant.epoch(0)).hash(header, nonce)
+    }
+
+    fn mix_digest(&self, header: &[u8], nonce: u64) -> Option<[u8; 32]> {
+        self.epoch_data(self.variant.epoch(0)).mix_digest(header, nonce)
+    }
+
+    fn diff1_target(&self) -> [u8; 32] {
+        DIFF1_TARGET
+    }
+
+    fn wide_nonce(&self) -> bool {
+        true
+    }
+
+    fn prepare(&self, job: &MiningJob) -> Result<Option<Arc<dyn MiningAlgorithm>>> {
+        let epoch = self.variant.epoch_for_seed(&job.seed_hash)
+            .ok_or_else(|| anyhow::anyhow!("Unknown {} seed hash {}", self.name(), hex::encode(&job.seed_hash)))?;
+        let data = self.epoch_data(epoch);
+        if self.full_dag {
+            data.spawn_dataset();
+        }
+        Ok(Some(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex32(s: &str) -> [u8; 32] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_sizes_match_published_tables() {
+        assert_eq!(cache_size(0), 16_776_896);
+        assert_eq!(cache_size(1), 16_907_456);
+        assert_eq!(cache_size(2), 17_039_296);
+        assert_eq!(full_size(0), 1_073_739_904);
+        assert_eq!(full_size(1), 1_082_130_304);
+        assert_eq!(full_size(2), 1_090_514_816);
+    }
+
+    #[test]
+    fn test_epochs_and_seeds() {
+        assert_eq!(Variant::Ethash.epoch(29_999).seed, [0u8; 32]);
+        let epoch1 = Variant::Ethash.epoch(30_000);
+        assert_eq!(epoch1.number, 1);
+        assert_eq!(epoch1.seed, hex32("290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563"));
+        assert_eq!(Variant::Ethash.epoch_for_seed(&epoch1.seed), Some(epoch1));
+        assert_eq!(Variant::Ethash.epoch_for_seed(&[]).unwrap().number, 0);
+        assert_eq!(Variant::Ethash.epoch_for_seed(&[0xab; 32]), None);
+
+        // Before ECIP-1099 the schedules agree.
+        assert_eq!(Variant::Etchash.epoch(ECIP1099_BLOCK - 1), Variant::Ethash.epoch(ECIP1099_BLOCK - 1));
+        // After it, epochs halve but seeds keep their 30 000-block pace.
+        let etc = Variant::Etchash.epoch(ECIP1099_BLOCK);
+        assert_eq!(etc.number, 195);
+        assert_eq!(etc.seed, Variant::Ethash.epoch(ECIP1099_BLOCK).seed);
+        assert_eq!(Variant::Etchash.epoch_for_seed(&etc.seed), Some(etc));
+        assert_eq!(Variant::Etchash.epoch(ECIP1099_BLOCK + ECIP1099_EPOCH_LENGTH).number, 196);
+    }
+
+    #[test]
+    fn test_light_and_full_agree() {
+        let epoch = Variant::Ethash.epoch(0);
+        let data = EpochData::with_sizes(Variant::Ethash, epoch, 1024, 32 * 1024);
+        let header = hex32("c9149cc0386e689d789a1c2f3d5d169a61a6218ed30e74414dc736e442ef3d1f");
+
+        let light = data.compute(&header, 0);
+        let reports = AtomicUsize::new(0);
+        let dataset = Dataset::generate(&data.cache, 32 * 1024, |done, total| {
+            assert!(done <= total);
+            reports.fetch_add(1, Ordering::Relaxed);
+        });
+        assert!(reports.load(Ordering::Relaxed) > 0);
+        assert_eq!(hashimoto_full(&dataset, &header, 0), light);
+
+        let progress = dag_progress();
+        assert!(!data.is_warming_up());
+        data.generate_dataset();
+        assert!(data.has_dataset());
+        assert!(!data.is_warming_up());
+        assert!(progress.has_changed().unwrap());
+        assert_eq!(data.compute(&header, 0), light);
+        assert_ne!(data.compute(&header, 1), light);
+    }
+
+    /// Block "first" from the ethereum/tests Ethash vectors, checked in
+    /// light mode against the real epoch 0 cache.
+    #[test]
+    fn test_published_vector() {
+        let epoch = Variant::Ethash.epoch(0);
+        let cache = LightCache::generate(&epoch.seed, epoch.cache_size());
+        let header = hex32("2a8de2adf89af77358250bf908bf04ba94a6e8c3ba87775564a41d269a05e4ce");
+        let result = hashimoto_light(&cache, epoch.full_size(), &header, 0x4242_4242_4242_4242);
+
+        assert_eq!(hex::encode(result.mix_digest), "58f759ede17a706c93f13030328bcea40c1d1341fb26f2facd21ceb0dae57017");
+        assert_eq!(hex::encode(result.result), "dd47fd2d98db51078356852d7c4014e6a5d6c387c35f40e2875b74a256ed7906");
+    }
 }
@@ -1,81 +1,267 @@
-use jxpoolminer_core::{Device, MiningJob, Share};
+//! GXHash, the GXC proof of work.
+//!
+//! # Specification
+//!
+//! - **Header**: 80 bytes laid out as for SHA-256d (version, previous
+//!   hash, merkle root, ntime, nbits; see `crate::header`), with the nonce
+//!   a 32-bit little-endian integer at bytes 76..80. Miners scan nonces
+//!   over the 76-byte prefix.
+//! - **Hash, version 0**: with `prefix` the first 76 bytes and `nonce`
+//!   widened to 64 bits, `h1 = BLAKE3(prefix || le64(nonce) ||
+//!   le64(nonce ^ 0xDEADBEEF))`, then `pow = BLAKE3(h1 || be64(nonce))`.
+//! - **Hash, version 1**: `seed = BLAKE3-derive_key(CONTEXT_V1, header)`,
+//!   then `pow = BLAKE3(seed || header)`.
+//! - **Target**: `pow` is read as a big-endian 256-bit integer (first
+//!   byte most significant) and meets a target when it is less than or
+//!   equal to it. Difficulty 1 is `0x00000000ffff << 208`, as for SHA-256d.
+//!
+//! Jobs name the version they expect in `MiningJob::algorithm_version`,
+//! which pools that predate versioning leave unset; those mine version 0.
+//! A miner refuses versions it does not implement instead of mining
+//! shares the pool would reject, and any change to the hash gets a new
+//! version with its own vectors in `TEST_VECTORS`.
+
+use super::MiningAlgorithm;
+use crate::header::HEADER_SIZE;
+use jxpoolminer_core::{meets_target, MiningJob, SHA256_DIFF1};
 use anyhow::Result;
 use blake3::Hasher;
-use tokio::sync::mpsc;
-
-pub async fn mine(
-    device: &Device,
-    job: MiningJob,
-    cancel_rx: &mut mpsc::Receiver<()>,
-) -> Result<Share> {
-    let mut nonce = 0u64;
-    let target = job.target.clone();
-    let cores = match &device.device_type {
-        jxpoolminer_core::DeviceType::CPU { cores } => *cores,
-        _ => 1,
-    };
-    
-    loop {
-        tokio::select! {
-            _ = cancel_rx.recv() => {
-                tracing::info!("Mining cancelled for device: {}", device.id);
-                anyhow::bail!("Mining cancelled");
-            }
-            result = async {
-                for _ in 0..(cores * 1000) {
-                    let hash = gxhash_compute(&job.header, nonce);
-                    
-                    if check_target(&hash, &target) {
-                        return Some(Share {
-                            job_id: job.id.clone(),
-                            nonce,
-                            hash: hash.to_vec(),
-                            device_id: device.id.clone(),
-                            timestamp: chrono::Utc::now(),
-                        });
-                    }
-                    
-                    nonce = nonce.wrapping_add(1);
-                }
-                None::<Share>
-            } => {
-                if let Some(share) = result {
-                    return Ok(share);
-                }
-            }
+use std::sync::Arc;
+
+/// BLAKE3 key-derivation context of version 1.
+pub const CONTEXT_V1: &str = "GXC GXHash v1 proof of work";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Version {
+    /// The original hash, frozen for the pools that still mine it.
+    V0,
+    V1,
+}
+
+impl Version {
+    /// The version mined when a job does not name one.
+    pub const DEFAULT: Version = Version::V0;
+    /// The newest version this miner implements.
+    pub const LATEST: Version = Version::V1;
+
+    pub fn number(&self) -> u32 {
+        match self {
+            Version::V0 => 0,
+            Version::V1 => 1,
+        }
+    }
+
+    pub fn from_number(number: u32) -> Result<Self> {
+        match number {
+            0 => Ok(Version::V0),
+            1 => Ok(Version::V1),
+            _ => anyhow::bail!("Unsupported GXHash version {} (this miner implements up to v{})", number, Version::LATEST.number()),
+        }
+    }
+
+    /// The version `job` asks for.
+    pub fn for_job(job: &MiningJob) -> Result<Self> {
+        job.algorithm_version.map_or(Ok(Version::DEFAULT), Self::from_number)
+    }
+}
+
+/// Known-answer vectors: `(version, 76-byte header prefix, nonce, pow)`,
+/// all hex. Implementations elsewhere (pools, other miners) should
+/// reproduce these exactly.
+pub const TEST_VECTORS: &[(u32, &str, u32, &str)] = &[
+    (
+        0,
+        "00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+        0,
+        "dbffa048e5c611adb4237d9c5fc38d5990b11e62fda2e36455ca30f4ee35f1de",
+    ),
+    (
+        0,
+        "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d",
+        2083236893,
+        "c4af3e3042a3ff8a71f2160ba856d25954dbca052298b878ac7e2880d71e0ef6",
+    ),
+    (
+        0,
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f404142434445464748494a4b",
+        0xffff_ffff,
+        "36716b7ca75021c84547374aef2c0bb9b63488afdf3e0e81ddb17f5307e2a538",
+    ),
+    (
+        1,
+        "00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+        0,
+        "c448c36fd9ef8ee64cba73a112a286efbeec8489b2c680e95cf5e4dc37da7810",
+    ),
+    (
+        1,
+        "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d",
+        2083236893,
+        "83d543e63b5fa45de3e8e42f2f7b542a97d53ce03e2951e8ba8a9b31d97ec1fa",
+    ),
+    (
+        1,
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f404142434445464748494a4b",
+        0xffff_ffff,
+        "7306d94a26fc83f7ee0f78a3f611205133af241d98a9ab6c891fad7371890f43",
+    ),
+];
+
+/// The full header: `prefix` followed by the little-endian nonce.
+pub fn header_with_nonce(prefix: &[u8], nonce: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(prefix.len() + 4);
+    header.extend_from_slice(prefix);
+    header.extend_from_slice(&nonce.to_le_bytes());
+    header
+}
+
+/// The proof-of-work hash of a full header.
+pub fn pow_hash(version: Version, header: &[u8]) -> [u8; 32] {
+    match version {
+        Version::V0 => {
+            let (prefix, nonce) = header.split_at(header.len().saturating_sub(4));
+            let nonce = nonce.try_into().map_or(0, u32::from_le_bytes) as u64;
+            let mut hasher = Hasher::new();
+            hasher.update(prefix);
+            hasher.update(&nonce.to_le_bytes());
+            hasher.update(&(nonce ^ 0xDEADBEEF).to_le_bytes());
+            let h1 = hasher.finalize();
+
+            let mut hasher = Hasher::new();
+            hasher.update(h1.as_bytes());
+            hasher.update(&nonce.to_be_bytes());
+            *hasher.finalize().as_bytes()
+        }
+        Version::V1 => {
+            let seed = blake3::derive_key(CONTEXT_V1, header);
+            let mut hasher = Hasher::new();
+            hasher.update(&seed);
+            hasher.update(header);
+            *hasher.finalize().as_bytes()
         }
-        
-        tokio::task::yield_now().await;
     }
 }
 
-fn gxhash_compute(header: &[u8], nonce: u64) -> [u8; 32] {
-    let mut hasher = Hasher::new();
-    hasher.update(header);
-    hasher.update(&nonce.to_le_bytes());
-    hasher.update(&(nonce ^ 0xDEADBEEF).to_le_bytes());
-    
-    let hash1 = hasher.finalize();
-    
-    let mut hasher2 = Hasher::new();
-    hasher2.update(hash1.as_bytes());
-    hasher2.update(&nonce.to_be_bytes());
-    
-    let hash2 = hasher2.finalize();
-    *hash2.as_bytes()
+/// Checks a submitted share: `header` is the 80-byte header with the
+/// nonce filled in, `target` the big-endian share target.
+pub fn verify(version: Version, header: &[u8], target: &[u8; 32]) -> Result<bool> {
+    if header.len() != HEADER_SIZE {
+        anyhow::bail!("GXHash headers are {} bytes, got {}", HEADER_SIZE, header.len());
+    }
+    Ok(meets_target(&pow_hash(version, header), target))
+}
+
+pub struct GxHash {
+    version: Version,
+}
+
+impl GxHash {
+    pub fn new() -> Self {
+        Self::with_version(Version::DEFAULT)
+    }
+
+    pub fn with_version(version: Version) -> Self {
+        Self { version }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+}
+
+impl Default for GxHash {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-fn check_target(hash: &[u8; 32], target: &[u8]) -> bool {
-    if target.is_empty() {
-        return hash[0] == 0 && hash[1] == 0;
+impl MiningAlgorithm for GxHash {
+    fn id(&self) -> &str {
+        "gxhash"
+    }
+
+    fn name(&self) -> &str {
+        "GXHash"
+    }
+
+    fn hash(&self, header: &[u8], nonce: u64) -> [u8; 32] {
+        pow_hash(self.version, &header_with_nonce(header, nonce as u32))
+    }
+
+    fn diff1_target(&self) -> [u8; 32] {
+        SHA256_DIFF1
+    }
+
+    fn batch_size(&self) -> usize {
+        1000
+    }
+
+    /// Mines the version the job asks for, failing if it is unknown.
+    fn prepare(&self, job: &MiningJob) -> Result<Option<Arc<dyn MiningAlgorithm>>> {
+        let version = Version::for_job(job)?;
+        if version == self.version {
+            return Ok(None);
+        }
+        Ok(Some(Arc::new(GxHash::with_version(version))))
     }
-    
-    for i in 0..hash.len().min(target.len()) {
-        if hash[i] > target[i] {
-            return false;
-        } else if hash[i] < target[i] {
-            return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::NONCE_OFFSET;
+    use jxpoolminer_core::Algorithm;
+
+    #[test]
+    fn test_known_answer_vectors() {
+        for &(version, prefix, nonce, expected) in TEST_VECTORS {
+            let version = Version::from_number(version).unwrap();
+            let prefix = hex::decode(prefix).unwrap();
+            assert_eq!(prefix.len(), NONCE_OFFSET);
+            let header = header_with_nonce(&prefix, nonce);
+
+            assert_eq!(hex::encode(pow_hash(version, &header)), expected, "nonce {}", nonce);
+            assert_eq!(hex::encode(GxHash::with_version(version).hash(&prefix, nonce as u64)), expected);
         }
     }
-    true
+
+    #[test]
+    fn test_nonce_is_little_endian_at_offset_76() {
+        let prefix = [0u8; NONCE_OFFSET];
+        let header = header_with_nonce(&prefix, 0x0403_0201);
+        assert_eq!(&header[NONCE_OFFSET..], &[1, 2, 3, 4]);
+        assert_ne!(GxHash::new().hash(&prefix, 1), GxHash::new().hash(&prefix, 1 << 24));
+    }
+
+    #[test]
+    fn test_verify_compares_big_endian() {
+        let header = header_with_nonce(&[0u8; NONCE_OFFSET], 0);
+        let pow = pow_hash(Version::V1, &header);
+        assert!(verify(Version::V1, &header, &pow).unwrap());
+
+        // One below the hash as a big-endian integer fails.
+        let mut below = pow;
+        let last = below.iter().rposition(|&b| b != 0).unwrap();
+        below[last] -= 1;
+        assert!(!verify(Version::V1, &header, &below).unwrap());
+        assert!(verify(Version::V1, &header, &[0xff; 32]).unwrap());
+        assert!(verify(Version::V1, &header[..NONCE_OFFSET], &[0xff; 32]).is_err());
+    }
+
+    #[test]
+    fn test_jobs_select_the_version() {
+        let mut job = MiningJob::new("1".to_string(), Algorithm::GXHASH, 1.0);
+        assert_eq!(Version::for_job(&job).unwrap(), Version::V0);
+        assert!(GxHash::new().prepare(&job).unwrap().is_none());
+
+        job.algorithm_version = Some(0);
+        assert!(GxHash::new().prepare(&job).unwrap().is_none());
+        job.algorithm_version = Some(1);
+        let v1 = GxHash::new().prepare(&job).unwrap().unwrap();
+        let prefix = [0u8; NONCE_OFFSET];
+        assert_eq!(v1.hash(&prefix, 7), GxHash::with_version(Version::V1).hash(&prefix, 7));
+        assert_ne!(v1.hash(&prefix, 7), GxHash::new().hash(&prefix, 7));
+        job.algorithm_version = Some(2);
+        assert!(GxHash::new().prepare(&job).is_err());
+    }
 }
@@ -2,14 +2,430 @@ pub mod gxhash;
 pub mod sha256;
 pub mod ethash;
 
-use jxpoolminer_core::{Device, MiningJob, Share, Algorithm};
+use crate::work::WorkCursor;
+use jxpoolminer_core::{difficulty_to_target, meets_target, target_to_difficulty, work_for_difficulty, Algorithm, Device, MiningJob, Share};
 use anyhow::Result;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock, RwLock};
 use tokio::sync::mpsc;
 
-pub async fn mine(device: &Device, job: MiningJob, cancel_rx: &mut mpsc::Receiver<()>) -> Result<Share> {
-    match job.algorithm {
-        Algorithm::SHA256 => sha256::mine(device, job, cancel_rx).await,
-        Algorithm::Ethash => ethash::mine(device, job, cancel_rx).await,
-        Algorithm::GXHash => gxhash::mine(device, job, cancel_rx).await,
+/// A proof-of-work hash the engine can mine with. Implementations are
+/// looked up by `id` in the registry, so the engine and its callers never
+/// need to know the concrete type.
+pub trait MiningAlgorithm: Send + Sync {
+    /// Registry key, e.g. `"sha256"`.
+    fn id(&self) -> &str;
+
+    fn name(&self) -> &str {
+        self.id()
+    }
+
+    /// Hash of a header prefix with `nonce`. Algorithms whose nonce is the
+    /// 32-bit field of a Bitcoin-style header only see nonces below 2^32.
+    fn hash(&self, header: &[u8], nonce: u64) -> [u8; 32];
+
+    /// Hashes `nonces` in order into `out`. Override when hashing several
+    /// nonces together is cheaper than one at a time.
+    fn hash_batch(&self, header: &[u8], nonces: &[u64], out: &mut Vec<[u8; 32]>) {
+        out.extend(nonces.iter().map(|&nonce| self.hash(header, nonce)));
+    }
+
+    /// Hasher for scanning nonces over one header. Override to precompute
+    /// whatever the nonce does not affect.
+    fn header_hasher<'a>(&'a self, header: &[u8]) -> Box<dyn HeaderHasher + 'a> {
+        plain_hasher(self, header)
+    }
+
+    /// Target a share must meet at difficulty 1.
+    fn diff1_target(&self) -> [u8; 32];
+
+    /// Share target for a pool difficulty.
+    fn target_for_difficulty(&self, difficulty: f64) -> [u8; 32] {
+        difficulty_to_target(&self.diff1_target(), difficulty)
+    }
+
+    /// Pool difficulty a share target stands for.
+    fn difficulty_for_target(&self, target: &[u8]) -> f64 {
+        target_to_difficulty(&self.diff1_target(), target)
+    }
+
+    /// Expected number of hashes to find one share at `difficulty`.
+    fn work_for_difficulty(&self, difficulty: f64) -> f64 {
+        work_for_difficulty(&self.diff1_target(), difficulty)
+    }
+
+    /// Whether the nonce is 64 bits with the extranonce as its high half,
+    /// as for Ethash, rather than the 32-bit field of a Bitcoin header.
+    fn wide_nonce(&self) -> bool {
+        false
+    }
+
+    /// Nonces to try between checks for cancellation and new jobs.
+    fn batch_size(&self) -> usize {
+        4096
+    }
+
+    /// Whether state that will speed hashing up, such as an Ethash DAG, is
+    /// still being built.
+    fn is_warming_up(&self) -> bool {
+        false
+    }
+
+    /// The hash as the big-endian integer targets are compared against.
+    fn hash_value(&self, hash: &[u8; 32]) -> [u8; 32] {
+        *hash
+    }
+
+    /// Whether `hash` meets `target`; an empty target means difficulty 1.
+    fn meets_target(&self, hash: &[u8; 32], target: &[u8]) -> bool {
+        let value = self.hash_value(hash);
+        if target.is_empty() {
+            return meets_target(&value, &self.diff1_target());
+        }
+        meets_target(&value, target)
+    }
+
+    /// Recomputes the hash for `nonce` and checks it against `target`.
+    fn verify(&self, header: &[u8], nonce: u64, target: &[u8]) -> bool {
+        self.meets_target(&self.hash(header, nonce), target)
+    }
+
+    /// Mix digest submitted with a share, for algorithms that have one.
+    fn mix_digest(&self, _header: &[u8], _nonce: u64) -> Option<[u8; 32]> {
+        None
+    }
+
+    /// Hash and mix digest of `nonce` from the plainest implementation
+    /// available, which shares are checked against before submission.
+    /// Override when `hash` or `mix_digest` take a faster path.
+    fn reference_hash(&self, header: &[u8], nonce: u64) -> ([u8; 32], Option<[u8; 32]>) {
+        (self.hash(header, nonce), self.mix_digest(header, nonce))
+    }
+
+    /// Readies per-job state such as an Ethash epoch's cache, returning the
+    /// implementation to mine `job` with, or `None` to mine with `self`.
+    /// May block for a long time, so call it off the async runtime.
+    fn prepare(&self, _job: &MiningJob) -> Result<Option<Arc<dyn MiningAlgorithm>>> {
+        Ok(None)
+    }
+}
+
+/// Hashes nonces against one fixed header.
+pub trait HeaderHasher {
+    fn hash(&self, nonce: u64) -> [u8; 32];
+
+    /// Hashes `nonces` in order into `out`.
+    fn hash_batch(&self, nonces: &[u64], out: &mut Vec<[u8; 32]>) {
+        out.extend(nonces.iter().map(|&nonce| self.hash(nonce)));
+    }
+}
+
+struct PlainHasher<'a, A: ?Sized> {
+    algorithm: &'a A,
+    header: Vec<u8>,
+}
+
+impl<A: MiningAlgorithm + ?Sized> HeaderHasher for PlainHasher<'_, A> {
+    fn hash(&self, nonce: u64) -> [u8; 32] {
+        self.algorithm.hash(&self.header, nonce)
+    }
+}
+
+/// A hasher that calls `algorithm.hash` for every nonce.
+pub fn plain_hasher<'a, A: MiningAlgorithm + ?Sized>(algorithm: &'a A, header: &[u8]) -> Box<dyn HeaderHasher + 'a> {
+    Box::new(PlainHasher { algorithm, header: header.to_vec() })
+}
+
+/// Algorithm implementations keyed by id.
+pub struct Registry {
+    algorithms: RwLock<BTreeMap<String, Arc<dyn MiningAlgorithm>>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self {
+            algorithms: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// A registry holding the algorithms shipped with the miner.
+    pub fn with_builtins() -> Self {
+        let registry = Self::new();
+        registry.register(Arc::new(sha256::Sha256::new()));
+        registry.register(Arc::new(ethash::Ethash::new(ethash::Variant::Ethash)));
+        registry.register(Arc::new(ethash::Ethash::new(ethash::Variant::Etchash)));
+        registry.register(Arc::new(gxhash::GxHash::new()));
+        registry
+    }
+
+    /// Adds an implementation, replacing and returning any registered
+    /// under the same id.
+    pub fn register(&self, algorithm: Arc<dyn MiningAlgorithm>) -> Option<Arc<dyn MiningAlgorithm>> {
+        let id = algorithm.id().to_string();
+        self.algorithms.write().unwrap().insert(id, algorithm)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<dyn MiningAlgorithm>> {
+        self.algorithms.read().unwrap().get(id).cloned()
+    }
+
+    /// The implementation for `algorithm`, or an error if none is registered.
+    pub fn resolve(&self, algorithm: &Algorithm) -> Result<Arc<dyn MiningAlgorithm>> {
+        self.get(algorithm.id())
+            .ok_or_else(|| anyhow::anyhow!("No implementation registered for {}", algorithm))
+    }
+
+    /// Display name of `algorithm`, or its id if nothing is registered
+    /// under it.
+    pub fn name(&self, algorithm: &Algorithm) -> String {
+        self.get(algorithm.id()).map_or_else(|| algorithm.to_string(), |implementation| implementation.name().to_string())
+    }
+
+    /// The implementation prepared for mining `job`. Blocks while any
+    /// per-job state is generated.
+    pub fn for_job(&self, job: &MiningJob) -> Result<Arc<dyn MiningAlgorithm>> {
+        let algorithm = self.resolve(&job.algorithm)?;
+        Ok(algorithm.prepare(job)?.unwrap_or(algorithm))
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.algorithms.read().unwrap().keys().cloned().collect()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Process-wide registry the engine mines from, pre-filled with the
+/// built-in algorithms. Other crates add or replace implementations with
+/// `registry().register(..)`.
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::with_builtins)
+}
+
+/// Nonces hashed per `HeaderHasher::hash_batch` call while scanning.
+const SCAN_CHUNK: usize = 256;
+
+/// Tries up to `hashes` nonces and returns the first share found.
+pub fn scan(algorithm: &dyn MiningAlgorithm, work: &mut WorkCursor, device_id: &str, hashes: usize) -> Option<Share> {
+    let mut header = work.job().header.clone();
+    let mut hasher = algorithm.header_hasher(&header);
+    let mut nonces = Vec::with_capacity(SCAN_CHUNK);
+    let mut results = Vec::with_capacity(SCAN_CHUNK);
+    let mut remaining = hashes;
+
+    while remaining > 0 {
+        // A run never crosses an extranonce2 roll, which gives the cursor
+        // a new header.
+        let (start, count) = work.nonce_run(remaining.min(SCAN_CHUNK));
+        let job = work.job();
+        if job.header != header {
+            header.clone_from(&job.header);
+            hasher = algorithm.header_hasher(&header);
+        }
+
+        let first = if algorithm.wide_nonce() { work.wide_nonce(start) } else { start as u64 };
+        nonces.clear();
+        nonces.extend((0..count as u64).map(|i| first + i));
+        results.clear();
+        hasher.hash_batch(&nonces, &mut results);
+
+        if let Some(found) = results.iter().position(|hash| algorithm.meets_target(hash, &job.target)) {
+            let (nonce, hash) = (nonces[found], results[found]);
+            let mix_digest = algorithm.mix_digest(&job.header, nonce);
+            // Later nonces of the run are left for the next scan.
+            work.advance(found + 1);
+            let mut share = work.share(nonce, hash.to_vec(), device_id);
+            if let Some(digest) = mix_digest {
+                // Pools check the digest against the header it was mixed from.
+                share.mix_digest = Some(digest.to_vec());
+                share.header_hash = Some(header.clone());
+            }
+            return Some(share);
+        }
+        work.advance(count);
+        remaining -= count;
+    }
+    None
+}
+
+/// Mines until a share is found, resuming from wherever `work` left off.
+/// Each batch is scanned on a blocking thread, and cancellation is checked
+/// between batches. If the future is dropped mid-batch, `work` stays where
+/// that batch began.
+pub async fn mine(
+    algorithm: &Arc<dyn MiningAlgorithm>,
+    device: &Device,
+    work: &mut WorkCursor,
+    cancel_rx: &mut mpsc::Receiver<()>,
+) -> Result<Share> {
+    loop {
+        let batch = {
+            let algorithm = algorithm.clone();
+            let mut work = work.clone();
+            let device_id = device.id.clone();
+            tokio::task::spawn_blocking(move || {
+                let share = scan(algorithm.as_ref(), &mut work, &device_id, algorithm.batch_size());
+                (work, share)
+            })
+        };
+
+        tokio::select! {
+            biased;
+            _ = cancel_rx.recv() => {
+                tracing::info!("Mining cancelled for device: {}", device.id);
+                anyhow::bail!("Mining cancelled");
+            }
+            result = batch => {
+                let (scanned, share) = result?;
+                *work = scanned;
+                if let Some(share) = share {
+                    return Ok(share);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::work::Extranonce2Range;
+
+    /// Shares whenever the nonce is a multiple of 16.
+    struct EveryNth;
+
+    impl MiningAlgorithm for EveryNth {
+        fn id(&self) -> &str {
+            "every-nth"
+        }
+
+        fn hash(&self, _header: &[u8], nonce: u64) -> [u8; 32] {
+            [if nonce.is_multiple_of(16) { 0x00 } else { 0xff }; 32]
+        }
+
+        fn diff1_target(&self) -> [u8; 32] {
+            [0x0f; 32]
+        }
+    }
+
+    /// Takes a second per hash and never shares.
+    struct Slow;
+
+    impl MiningAlgorithm for Slow {
+        fn id(&self) -> &str {
+            "slow"
+        }
+
+        fn hash(&self, _header: &[u8], _nonce: u64) -> [u8; 32] {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            [0xff; 32]
+        }
+
+        fn diff1_target(&self) -> [u8; 32] {
+            [0x0f; 32]
+        }
+
+        fn batch_size(&self) -> usize {
+            1
+        }
+    }
+
+    #[test]
+    fn test_builtins_are_registered() {
+        let registry = Registry::with_builtins();
+        assert_eq!(registry.ids(), vec!["etchash", "ethash", "gxhash", "sha256"]);
+        for algorithm in [Algorithm::SHA256, Algorithm::ETHASH, Algorithm::ETCHASH, Algorithm::GXHASH] {
+            let implementation = registry.resolve(&algorithm).unwrap();
+            assert_eq!(implementation.id(), algorithm.id());
+        }
+        assert_eq!(registry.resolve(&Algorithm::SHA256).unwrap().diff1_target(), jxpoolminer_core::SHA256_DIFF1);
+        assert_eq!(registry.resolve(&Algorithm::ETHASH).unwrap().diff1_target(), [0xff; 32]);
+        assert!(Registry::new().resolve(&Algorithm::SHA256).is_err());
+    }
+
+    #[test]
+    fn test_custom_algorithm_mines_through_scan() {
+        let registry = Registry::new();
+        assert!(registry.register(Arc::new(EveryNth)).is_none());
+        let job = MiningJob::new("1".to_string(), Algorithm::new("every-nth"), 1.0);
+        let algorithm = registry.for_job(&job).unwrap();
+        assert_eq!(algorithm.target_for_difficulty(1.0)[..7], [0x0f; 7]);
+        assert_eq!(registry.name(&Algorithm::new("unregistered")), "unregistered");
+
+        let mut work = WorkCursor::new(job, Extranonce2Range::full(4));
+        assert_eq!(scan(algorithm.as_ref(), &mut work, "cpu-0", 16).unwrap().nonce, 0);
+        assert!(scan(algorithm.as_ref(), &mut work, "cpu-0", 15).is_none());
+        assert_eq!(scan(algorithm.as_ref(), &mut work, "cpu-0", 1).unwrap().nonce, 16);
+
+        assert!(algorithm.verify(&[], 32, &[]));
+        assert!(!algorithm.verify(&[], 33, &[]));
+        let mut batch = Vec::new();
+        algorithm.hash_batch(&[], &[0, 1], &mut batch);
+        assert_eq!(batch, vec![[0x00; 32], [0xff; 32]]);
+    }
+
+    #[tokio::test]
+    async fn test_mine_keeps_runtime_responsive() {
+        let device = Device::new("test-0".to_string(), "Test".to_string(), jxpoolminer_core::DeviceType::CPU { cores: 1 });
+        let job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0);
+        let (cancel_tx, mut cancel_rx) = mpsc::channel(1);
+
+        let every_nth: Arc<dyn MiningAlgorithm> = Arc::new(EveryNth);
+        let mut work = WorkCursor::new(job.clone(), Extranonce2Range::full(4));
+        assert_eq!(mine(&every_nth, &device, &mut work, &mut cancel_rx).await.unwrap().nonce, 0);
+        assert_eq!(mine(&every_nth, &device, &mut work, &mut cancel_rx).await.unwrap().nonce, 16);
+
+        // A batch in progress does not hold up the single-threaded runtime
+        // the test runs on, and cancellation interrupts the wait for it.
+        let slow: Arc<dyn MiningAlgorithm> = Arc::new(Slow);
+        let mut work = WorkCursor::new(job, Extranonce2Range::full(4));
+        let started = std::time::Instant::now();
+        let timed_out = tokio::time::timeout(std::time::Duration::from_millis(50), mine(&slow, &device, &mut work, &mut cancel_rx)).await;
+        assert!(timed_out.is_err());
+        assert!(started.elapsed() < std::time::Duration::from_millis(500));
+        cancel_tx.send(()).await.unwrap();
+        assert!(mine(&slow, &device, &mut work, &mut cancel_rx).await.is_err());
+        assert!(started.elapsed() < std::time::Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_builtin_shares_verify() {
+        for algorithm in [Algorithm::SHA256, Algorithm::ETHASH, Algorithm::GXHASH] {
+            let implementation = registry().resolve(&algorithm).unwrap();
+            let mut job = MiningJob::new("1".to_string(), algorithm.clone(), 1.0);
+            job.header = vec![0x5a; 76];
+            job.target = implementation.target_for_difficulty(1.0 / 65536.0).to_vec();
+            let mut work = WorkCursor::new(job.clone(), Extranonce2Range::full(4));
+
+            let share = scan(implementation.as_ref(), &mut work, "cpu-0", 1 << 22).unwrap();
+            assert!(implementation.verify(&job.header, share.nonce, &job.target));
+            assert_eq!(share.hash, implementation.hash(&job.header, share.nonce).to_vec());
+            let mix_digest = implementation.mix_digest(&job.header, share.nonce);
+            assert_eq!(mix_digest.is_some(), algorithm == Algorithm::ETHASH);
+            assert_eq!(share.mix_digest, mix_digest.map(|digest| digest.to_vec()));
+            assert_eq!(share.header_hash.is_some(), mix_digest.is_some());
+        }
+    }
+
+    #[test]
+    fn test_ethash_nonces_are_64_bit() {
+        let implementation = registry().resolve(&Algorithm::ETHASH).unwrap();
+        let mut job = MiningJob::new("1".to_string(), Algorithm::ETHASH, 1.0);
+        job.header = vec![0x5a; 32];
+        job.extranonce1 = vec![0x08];
+        job.extranonce2_size = 3;
+        job.target = implementation.target_for_difficulty(1.0).to_vec();
+        let mut work = WorkCursor::new(job.clone(), Extranonce2Range { start: 5, end: 6 });
+
+        let share = scan(implementation.as_ref(), &mut work, "cpu-0", 1).unwrap();
+        assert_eq!(share.nonce, 0x0800_0005_0000_0000);
+        assert_eq!(share.hash, implementation.hash(&job.header, share.nonce).to_vec());
+        assert_ne!(share.hash, implementation.hash(&job.header, 0).to_vec());
+        assert_eq!(share.header_hash, Some(job.header));
     }
 }
@@ -0,0 +1,264 @@
+//! SHA-256d kernels. Every kernel hashes a batch of nonces over one
+//! midstate; the SIMD ones run several nonces side by side, one per lane.
+//! The kernel is picked at runtime from the features the CPU reports, and
+//! the portable scalar one is always available.
+
+use super::Midstate;
+use jxpoolminer_core::CpuFeature;
+use std::sync::OnceLock;
+
+/// SHA-256 initial hash values.
+pub(super) const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+    0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+pub(super) const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// First word of SHA-256 padding.
+pub(super) const PAD: u32 = 0x8000_0000;
+/// Bit lengths of the 80-byte header and of the 32-byte inner digest.
+pub(super) const HEADER_BITS: u32 = 640;
+pub(super) const DIGEST_BITS: u32 = 256;
+
+/// One SHA-256 compression in portable Rust.
+pub(super) fn compress(state: [u32; 8], block: &[u32; 16]) -> [u32; 8] {
+    let mut w = [0u32; 64];
+    w[..16].copy_from_slice(block);
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    let mut out = state;
+    for (word, value) in out.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(value);
+    }
+    out
+}
+
+/// Second header block: the last 12 header bytes, the nonce and padding.
+pub(super) fn header_block(tail: &[u32; 3], nonce: u32) -> [u32; 16] {
+    let mut block = [0u32; 16];
+    block[..3].copy_from_slice(tail);
+    // The nonce sits little-endian in the header, while SHA-256 reads
+    // big-endian words.
+    block[3] = nonce.swap_bytes();
+    block[4] = PAD;
+    block[15] = HEADER_BITS;
+    block
+}
+
+/// Block hashing the inner digest again.
+pub(super) fn digest_block(inner: &[u32; 8]) -> [u32; 16] {
+    let mut block = [0u32; 16];
+    block[..8].copy_from_slice(inner);
+    block[8] = PAD;
+    block[15] = DIGEST_BITS;
+    block
+}
+
+pub(super) fn to_bytes(words: &[u32; 8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    for (chunk, word) in hash.chunks_exact_mut(4).zip(words) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    hash
+}
+
+fn scalar(midstate: &Midstate, nonce: u32) -> [u8; 32] {
+    let inner = compress(midstate.state, &header_block(&midstate.tail, nonce));
+    to_bytes(&compress(H0, &digest_block(&inner)))
+}
+
+/// A SHA-256d implementation for one instruction set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kernel {
+    Scalar,
+    /// 4 lanes of 32 bits.
+    Sse41,
+    /// 8 lanes.
+    Avx2,
+    /// 16 lanes.
+    Avx512,
+    /// One nonce at a time on the SHA extensions.
+    ShaNi,
+}
+
+impl Kernel {
+    /// Every kernel, fastest first.
+    pub const ALL: [Kernel; 5] = [Kernel::Avx512, Kernel::ShaNi, Kernel::Avx2, Kernel::Sse41, Kernel::Scalar];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kernel::Scalar => "Scalar",
+            Kernel::Sse41 => "SSE4.1",
+            Kernel::Avx2 => "AVX2",
+            Kernel::Avx512 => "AVX-512",
+            Kernel::ShaNi => "SHA-NI",
+        }
+    }
+
+    /// Nonces hashed per kernel call.
+    pub fn lanes(&self) -> usize {
+        match self {
+            Kernel::Scalar | Kernel::ShaNi => 1,
+            Kernel::Sse41 => 4,
+            Kernel::Avx2 => 8,
+            Kernel::Avx512 => 16,
+        }
+    }
+
+    pub fn required_features(&self) -> &'static [CpuFeature] {
+        match self {
+            Kernel::Scalar => &[],
+            Kernel::Sse41 => &[CpuFeature::Sse41],
+            Kernel::Avx2 => &[CpuFeature::Avx2],
+            Kernel::Avx512 => &[CpuFeature::Avx512F],
+            Kernel::ShaNi => &[CpuFeature::ShaNi, CpuFeature::Sse41],
+        }
+    }
+
+    /// Whether this CPU can run the kernel.
+    pub fn is_supported(&self) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        {
+            match self {
+                Kernel::Scalar => true,
+                Kernel::Sse41 => is_x86_feature_detected!("sse4.1"),
+                Kernel::Avx2 => is_x86_feature_detected!("avx2"),
+                Kernel::Avx512 => is_x86_feature_detected!("avx512f"),
+                Kernel::ShaNi => is_x86_feature_detected!("sha") && is_x86_feature_detected!("sse4.1"),
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            *self == Kernel::Scalar
+        }
+    }
+
+    /// Kernels this CPU can run, fastest first.
+    pub fn available() -> Vec<Kernel> {
+        Self::ALL.into_iter().filter(Kernel::is_supported).collect()
+    }
+
+    /// Fastest kernel needing only `features`, among those this CPU runs.
+    pub fn for_features(features: &[CpuFeature]) -> Kernel {
+        Self::ALL.into_iter()
+            .find(|kernel| kernel.is_supported() && kernel.required_features().iter().all(|f| features.contains(f)))
+            .unwrap_or(Kernel::Scalar)
+    }
+
+    /// Fastest kernel for this CPU, detected once.
+    pub fn active() -> Kernel {
+        static ACTIVE: OnceLock<Kernel> = OnceLock::new();
+        *ACTIVE.get_or_init(|| Self::available()[0])
+    }
+
+    /// SHA-256d of the header under `midstate` for each of `nonces`, in
+    /// order, appended to `out`.
+    pub fn hash(&self, midstate: &Midstate, nonces: &[u32], out: &mut Vec<[u8; 32]>) {
+        assert!(self.is_supported(), "{} kernel is not supported on this CPU", self.name());
+        let lanes = self.lanes();
+        if lanes == 1 {
+            match self {
+                #[cfg(target_arch = "x86_64")]
+                // SAFETY: support was checked above.
+                Kernel::ShaNi => out.extend(nonces.iter().map(|&nonce| unsafe { super::x86::sha_ni(midstate, nonce) })),
+                _ => out.extend(nonces.iter().map(|&nonce| scalar(midstate, nonce))),
+            }
+            return;
+        }
+
+        let mut lane_nonces = [0u32; 16];
+        let mut lane_hashes = [[0u8; 32]; 16];
+        for chunk in nonces.chunks(lanes) {
+            // A short last chunk hashes padding nonces that are thrown away.
+            lane_nonces[..chunk.len()].copy_from_slice(chunk);
+            self.hash_lanes(midstate, &lane_nonces, &mut lane_hashes);
+            out.extend_from_slice(&lane_hashes[..chunk.len()]);
+        }
+    }
+
+    fn hash_lanes(&self, midstate: &Midstate, nonces: &[u32; 16], out: &mut [[u8; 32]; 16]) {
+        // SAFETY: callers check `is_supported` first.
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            match self {
+                Kernel::Sse41 => super::x86::sse41::hash(midstate, nonces, out),
+                Kernel::Avx2 => super::x86::avx2::hash(midstate, nonces, out),
+                Kernel::Avx512 => super::x86::avx512::hash(midstate, nonces, out),
+                Kernel::Scalar | Kernel::ShaNi => unreachable!("{} is not a lane kernel", self.name()),
+            }
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            let _ = (midstate, nonces, out);
+            unreachable!("{} needs x86_64", self.name())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_available_kernel_matches_scalar() {
+        let prefix: Vec<u8> = (0..76u8).map(|b| b.wrapping_mul(151).wrapping_add(7)).collect();
+        let midstate = Midstate::new(&prefix);
+        // Odd lengths leave partial chunks for every lane count.
+        let nonces: Vec<u32> = (0..37).map(|i| i * 0x0101_0101 + 3).chain([0, u32::MAX]).collect();
+
+        let mut expected = Vec::new();
+        Kernel::Scalar.hash(&midstate, &nonces, &mut expected);
+        assert_eq!(expected[0], midstate.hash(nonces[0]));
+
+        let available = Kernel::available();
+        assert!(available.contains(&Kernel::Scalar));
+        for kernel in available {
+            let mut hashes = Vec::new();
+            kernel.hash(&midstate, &nonces, &mut hashes);
+            assert_eq!(hashes, expected, "{} kernel disagrees with scalar", kernel.name());
+        }
+    }
+
+    #[test]
+    fn test_kernel_selection() {
+        assert_eq!(Kernel::for_features(&[]), Kernel::Scalar);
+        assert_eq!(Kernel::active(), Kernel::available()[0]);
+        let all = [CpuFeature::Sse41, CpuFeature::Avx2, CpuFeature::Avx512F, CpuFeature::ShaNi];
+        assert_eq!(Kernel::for_features(&all), Kernel::active());
+        for kernel in Kernel::available() {
+            assert_eq!(Kernel::for_features(kernel.required_features()), kernel);
+        }
+    }
+}
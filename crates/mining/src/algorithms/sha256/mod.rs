@@ -0,0 +1,194 @@
+//! Bitcoin-style SHA-256d over the 80-byte block header.
+//!
+//! The first 64 header bytes never change while nonces are scanned, so
+//! their compression is done once per header (the midstate). Each nonce
+//! then costs one compression for the second block and one for the outer
+//! hash instead of the three a plain `sha256d` does.
+
+pub mod kernel;
+#[cfg(target_arch = "x86_64")]
+mod x86;
+
+pub use kernel::Kernel;
+
+use super::{HeaderHasher, MiningAlgorithm};
+use crate::header::{self, NONCE_OFFSET};
+use jxpoolminer_core::SHA256_DIFF1;
+
+const BLOCK_BYTES: usize = 64;
+
+/// SHA-256 state after the first 64 header bytes, plus the remaining
+/// header words before the nonce.
+#[derive(Debug, Clone)]
+pub struct Midstate {
+    state: [u32; 8],
+    tail: [u32; 3],
+}
+
+impl Midstate {
+    /// `header` is the 76-byte prefix; anything past it is ignored.
+    pub fn new(header: &[u8]) -> Self {
+        assert!(header.len() >= NONCE_OFFSET, "header prefix is {} bytes, need {}", header.len(), NONCE_OFFSET);
+        let words = |bytes: &[u8]| -> Vec<u32> {
+            bytes.chunks_exact(4).map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap())).collect()
+        };
+        let first: [u32; 16] = words(&header[..BLOCK_BYTES]).try_into().unwrap();
+        Self {
+            state: kernel::compress(kernel::H0, &first),
+            tail: words(&header[BLOCK_BYTES..NONCE_OFFSET]).try_into().unwrap(),
+        }
+    }
+
+    /// SHA-256d of the header with `nonce` stored little-endian at offset
+    /// 76, in the byte order `sha256d` returns.
+    pub fn hash(&self, nonce: u32) -> [u8; 32] {
+        let mut out = Vec::with_capacity(1);
+        Kernel::Scalar.hash(self, &[nonce], &mut out);
+        out[0]
+    }
+}
+
+/// A midstate hashed through one kernel.
+struct KernelHasher {
+    midstate: Midstate,
+    kernel: Kernel,
+}
+
+impl HeaderHasher for KernelHasher {
+    fn hash(&self, nonce: u64) -> [u8; 32] {
+        let mut out = Vec::with_capacity(1);
+        self.kernel.hash(&self.midstate, &[nonce as u32], &mut out);
+        out[0]
+    }
+
+    fn hash_batch(&self, nonces: &[u64], out: &mut Vec<[u8; 32]>) {
+        let nonces: Vec<u32> = nonces.iter().map(|&nonce| nonce as u32).collect();
+        self.kernel.hash(&self.midstate, &nonces, out);
+    }
+}
+
+pub struct Sha256 {
+    kernel: Kernel,
+}
+
+impl Sha256 {
+    /// Hashes with the fastest kernel this CPU supports.
+    pub fn new() -> Self {
+        Self { kernel: Kernel::active() }
+    }
+
+    /// Hashes with `kernel`, which must be supported on this CPU.
+    pub fn with_kernel(kernel: Kernel) -> Self {
+        assert!(kernel.is_supported(), "{} kernel is not supported on this CPU", kernel.name());
+        Self { kernel }
+    }
+
+    pub fn kernel(&self) -> Kernel {
+        self.kernel
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MiningAlgorithm for Sha256 {
+    fn id(&self) -> &str {
+        "sha256"
+    }
+
+    fn name(&self) -> &str {
+        "SHA-256"
+    }
+
+    fn hash(&self, header: &[u8], nonce: u64) -> [u8; 32] {
+        let mut data = Vec::with_capacity(header.len() + 4);
+        data.extend_from_slice(header);
+        data.extend_from_slice(&(nonce as u32).to_le_bytes());
+        header::sha256d(&data)
+    }
+
+    fn header_hasher<'a>(&'a self, header: &[u8]) -> Box<dyn HeaderHasher + 'a> {
+        if header.len() == NONCE_OFFSET {
+            Box::new(KernelHasher { midstate: Midstate::new(header), kernel: self.kernel })
+        } else {
+            super::plain_hasher(self, header)
+        }
+    }
+
+    fn diff1_target(&self) -> [u8; 32] {
+        SHA256_DIFF1
+    }
+
+    fn batch_size(&self) -> usize {
+        10000
+    }
+
+    /// SHA-256 hashes are compared as little-endian integers, so the bytes
+    /// are reversed before the big-endian target comparison.
+    fn hash_value(&self, hash: &[u8; 32]) -> [u8; 32] {
+        let mut value = *hash;
+        value.reverse();
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+
+    #[test]
+    fn test_check_target() {
+        let check_target = |hash: &[u8; 32], target: &[u8]| Sha256::new().meets_target(hash, target);
+
+        let hash = [0u8; 32];
+        let target = vec![0xFF; 32];
+        assert!(check_target(&hash, &target));
+
+        let hash = [0xFF; 32];
+        let target = vec![0x00; 32];
+        assert!(!check_target(&hash, &target));
+
+        // Leading zeros of the number sit at the end of the hash bytes.
+        let mut hash = [0u8; 32];
+        hash[26] = 0xFF;
+        hash[27] = 0xFF;
+        let target = Sha256::new().target_for_difficulty(1.0);
+        assert!(check_target(&hash, &target));
+        hash[0] = 1;
+        assert!(!check_target(&hash, &target));
+        assert!(!check_target(&[0xFF; 32], &[]));
+    }
+
+    #[test]
+    fn test_genesis_block() {
+        let header = hex::decode(GENESIS_HEADER).unwrap();
+        let nonce = u32::from_le_bytes(header[NONCE_OFFSET..].try_into().unwrap()) as u64;
+        assert_eq!(nonce, 2083236893);
+
+        let prefix = &header[..NONCE_OFFSET];
+        for kernel in Kernel::available() {
+            let sha256 = Sha256::with_kernel(kernel);
+            let hash = sha256.header_hasher(prefix).hash(nonce);
+            assert_eq!(header::display_hash(&hash), "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f");
+            assert_eq!(hash, sha256.hash(prefix, nonce));
+            assert!(sha256.verify(prefix, nonce, &sha256.target_for_difficulty(1.0)));
+            assert!(!sha256.verify(prefix, nonce + 1, &sha256.target_for_difficulty(1.0)));
+        }
+    }
+
+    #[test]
+    fn test_midstate_matches_sha256d() {
+        let prefix: Vec<u8> = (0..NONCE_OFFSET as u8).map(|b| b.wrapping_mul(37)).collect();
+        let midstate = Midstate::new(&prefix);
+        for nonce in [0, 1, 0x1234_5678, u32::MAX] {
+            let mut full = prefix.clone();
+            full.extend_from_slice(&nonce.to_le_bytes());
+            assert_eq!(midstate.hash(nonce), header::sha256d(&full));
+        }
+    }
+}
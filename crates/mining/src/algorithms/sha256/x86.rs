@@ -0,0 +1,206 @@
+//! x86_64 kernels. The lane kernels share one SHA-256 written against a
+//! handful of vector operations, instantiated per instruction set by
+//! `lane_kernel!` so every helper carries that set's `target_feature`.
+
+use super::kernel::{digest_block, header_block, to_bytes, DIGEST_BITS, H0, K, PAD};
+use super::Midstate;
+use std::arch::x86_64::*;
+
+macro_rules! lane_kernel {
+    (
+        $name:ident, $feature:literal, $lanes:literal, $vector:ty, $shift:ty,
+        set1: $set1:ident, add: $add:ident, xor: $xor:ident, and: $and:ident,
+        or: $or:ident, andnot: $andnot:ident, srli: $srli:ident, slli: $slli:ident,
+        loadu: $loadu:ident, storeu: $storeu:ident $(,)?
+    ) => {
+        pub mod $name {
+            use super::*;
+
+            type V = $vector;
+            const LANES: usize = $lanes;
+
+            #[inline]
+            #[target_feature(enable = $feature)]
+            fn splat(x: u32) -> V {
+                $set1(x as i32)
+            }
+
+            #[inline]
+            #[target_feature(enable = $feature)]
+            fn add(a: V, b: V) -> V {
+                $add(a, b)
+            }
+
+            #[inline]
+            #[target_feature(enable = $feature)]
+            fn xor(a: V, b: V) -> V {
+                $xor(a, b)
+            }
+
+            #[inline]
+            #[target_feature(enable = $feature)]
+            fn rotr<const R: $shift, const L: $shift>(x: V) -> V {
+                $or($srli::<R>(x), $slli::<L>(x))
+            }
+
+            #[inline]
+            #[target_feature(enable = $feature)]
+            fn compress(state: [V; 8], mut w: [V; 16]) -> [V; 8] {
+                let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+                for i in 0..64 {
+                    let wi = if i < 16 {
+                        w[i]
+                    } else {
+                        // W[i-15], W[i-2], W[i-7] and W[i-16] in a ring of 16.
+                        let w15 = w[(i + 1) % 16];
+                        let w2 = w[(i + 14) % 16];
+                        let s0 = xor(xor(rotr::<7, 25>(w15), rotr::<18, 14>(w15)), $srli::<3>(w15));
+                        let s1 = xor(xor(rotr::<17, 15>(w2), rotr::<19, 13>(w2)), $srli::<10>(w2));
+                        w[i % 16] = add(add(w[i % 16], s0), add(w[(i + 9) % 16], s1));
+                        w[i % 16]
+                    };
+
+                    let s1 = xor(xor(rotr::<6, 26>(e), rotr::<11, 21>(e)), rotr::<25, 7>(e));
+                    let ch = xor($and(e, f), $andnot(e, g));
+                    let t1 = add(add(add(h, s1), add(ch, splat(K[i]))), wi);
+                    let s0 = xor(xor(rotr::<2, 30>(a), rotr::<13, 19>(a)), rotr::<22, 10>(a));
+                    let maj = $or($and(a, b), $and(c, $or(a, b)));
+                    let t2 = add(s0, maj);
+                    h = g;
+                    g = f;
+                    f = e;
+                    e = add(d, t1);
+                    d = c;
+                    c = b;
+                    b = a;
+                    a = add(t1, t2);
+                }
+                [
+                    add(state[0], a), add(state[1], b), add(state[2], c), add(state[3], d),
+                    add(state[4], e), add(state[5], f), add(state[6], g), add(state[7], h),
+                ]
+            }
+
+            /// Hashes the first `LANES` of `nonces` into `out`.
+            ///
+            /// # Safety
+            /// The CPU must support the kernel's instruction set.
+            #[target_feature(enable = $feature)]
+            pub unsafe fn hash(midstate: &Midstate, nonces: &[u32; 16], out: &mut [[u8; 32]; 16]) {
+                let mut swapped = [0u32; LANES];
+                for (word, nonce) in swapped.iter_mut().zip(nonces) {
+                    *word = nonce.swap_bytes();
+                }
+
+                let mut w = [splat(0); 16];
+                for (word, value) in w.iter_mut().zip(header_block(&midstate.tail, 0)) {
+                    *word = splat(value);
+                }
+                w[3] = $loadu(swapped.as_ptr() as *const _);
+                let inner = compress(midstate.state.map(|word| splat(word)), w);
+
+                let mut w = [splat(0); 16];
+                w[..8].copy_from_slice(&inner);
+                w[8] = splat(PAD);
+                w[15] = splat(DIGEST_BITS);
+                let outer = compress(H0.map(|word| splat(word)), w);
+
+                let mut words = [[0u32; LANES]; 8];
+                for (lanes, vector) in words.iter_mut().zip(outer) {
+                    $storeu(lanes.as_mut_ptr() as *mut _, vector);
+                }
+                for (lane, hash) in out.iter_mut().take(LANES).enumerate() {
+                    *hash = to_bytes(&std::array::from_fn(|i| words[i][lane]));
+                }
+            }
+        }
+    };
+}
+
+lane_kernel!(
+    sse41, "sse4.1", 4, __m128i, i32,
+    set1: _mm_set1_epi32, add: _mm_add_epi32, xor: _mm_xor_si128, and: _mm_and_si128,
+    or: _mm_or_si128, andnot: _mm_andnot_si128, srli: _mm_srli_epi32, slli: _mm_slli_epi32,
+    loadu: _mm_loadu_si128, storeu: _mm_storeu_si128,
+);
+
+lane_kernel!(
+    avx2, "avx2", 8, __m256i, i32,
+    set1: _mm256_set1_epi32, add: _mm256_add_epi32, xor: _mm256_xor_si256, and: _mm256_and_si256,
+    or: _mm256_or_si256, andnot: _mm256_andnot_si256, srli: _mm256_srli_epi32, slli: _mm256_slli_epi32,
+    loadu: _mm256_loadu_si256, storeu: _mm256_storeu_si256,
+);
+
+lane_kernel!(
+    avx512, "avx512f", 16, __m512i, u32,
+    set1: _mm512_set1_epi32, add: _mm512_add_epi32, xor: _mm512_xor_si512, and: _mm512_and_si512,
+    or: _mm512_or_si512, andnot: _mm512_andnot_si512, srli: _mm512_srli_epi32, slli: _mm512_slli_epi32,
+    loadu: _mm512_loadu_si512, storeu: _mm512_storeu_si512,
+);
+
+/// One compression on the SHA extensions, which keep the state as ABEF
+/// and CDGH halves.
+#[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+fn compress_sha_ni(state: [u32; 8], block: &[u32; 16]) -> [u32; 8] {
+    let [a, b, c, d, e, f, g, h] = state.map(|word| word as i32);
+    let mut abef = _mm_set_epi32(a, b, e, f);
+    let mut cdgh = _mm_set_epi32(c, d, g, h);
+    let (abef_start, cdgh_start) = (abef, cdgh);
+
+    let mut msg = [_mm_setzero_si128(); 4];
+    for (i, group) in msg.iter_mut().enumerate() {
+        let w = &block[i * 4..i * 4 + 4];
+        *group = _mm_set_epi32(w[3] as i32, w[2] as i32, w[1] as i32, w[0] as i32);
+    }
+
+    for i in 0..16 {
+        if i >= 4 {
+            // W[4i..4i+4] from the four previous groups.
+            let t1 = _mm_sha256msg1_epu32(msg[i % 4], msg[(i + 1) % 4]);
+            let t2 = _mm_alignr_epi8::<4>(msg[(i + 3) % 4], msg[(i + 2) % 4]);
+            msg[i % 4] = _mm_sha256msg2_epu32(_mm_add_epi32(t1, t2), msg[(i + 3) % 4]);
+        }
+        let k = &K[i * 4..i * 4 + 4];
+        let wk = _mm_add_epi32(msg[i % 4], _mm_set_epi32(k[3] as i32, k[2] as i32, k[1] as i32, k[0] as i32));
+        cdgh = _mm_sha256rnds2_epu32(cdgh, abef, wk);
+        abef = _mm_sha256rnds2_epu32(abef, cdgh, _mm_shuffle_epi32::<0x0E>(wk));
+    }
+    abef = _mm_add_epi32(abef, abef_start);
+    cdgh = _mm_add_epi32(cdgh, cdgh_start);
+
+    let lanes = |v: __m128i| {
+        let mut words = [0u32; 4];
+        // SAFETY: `words` is 16 bytes and storeu has no alignment needs.
+        unsafe { _mm_storeu_si128(words.as_mut_ptr() as *mut __m128i, v) };
+        words
+    };
+    let [f, e, b, a] = lanes(abef);
+    let [h, g, d, c] = lanes(cdgh);
+    [a, b, c, d, e, f, g, h]
+}
+
+/// SHA-256d of the header under `midstate` with `nonce`.
+///
+/// # Safety
+/// The CPU must support SHA-NI and SSE4.1.
+#[target_feature(enable = "sha,sse2,ssse3,sse4.1")]
+pub unsafe fn sha_ni(midstate: &Midstate, nonce: u32) -> [u8; 32] {
+    let inner = compress_sha_ni(midstate.state, &header_block(&midstate.tail, nonce));
+    to_bytes(&compress_sha_ni(H0, &digest_block(&inner)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::kernel::compress;
+
+    #[test]
+    fn test_sha_ni_compression_matches_scalar() {
+        if !is_x86_feature_detected!("sha") || !is_x86_feature_detected!("sse4.1") {
+            return;
+        }
+        let block: [u32; 16] = std::array::from_fn(|i| (i as u32).wrapping_mul(0x9e37_79b9));
+        // SAFETY: support was checked above.
+        assert_eq!(unsafe { compress_sha_ni(H0, &block) }, compress(H0, &block));
+    }
+}
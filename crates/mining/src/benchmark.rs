@@ -0,0 +1,203 @@
+//! Measures devices on synthetic jobs, without a pool. Each run mines a
+//! job whose target no hash can meet, so the device hashes flat out for
+//! the whole duration, and samples its hash counter once per interval.
+
+use crate::Engine;
+use crate::algorithms::{self, MiningAlgorithm};
+use jxpoolminer_core::{Algorithm, BenchmarkResult, Device, DeviceType, MiningJob};
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a run waits for the first hash and for the algorithm to warm
+/// up, e.g. while an Ethash cache and DAG are generated, before giving up.
+const WARMUP_TIMEOUT: Duration = Duration::from_secs(900);
+
+/// Reads a device's current power draw in watts, if it can.
+pub type PowerReader = Arc<dyn Fn(&Device) -> Option<f64> + Send + Sync>;
+
+pub struct Benchmark {
+    duration: Duration,
+    interval: Duration,
+    power: Option<PowerReader>,
+}
+
+impl Benchmark {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            interval: Duration::from_secs(1),
+            power: None,
+        }
+    }
+
+    /// Length of the intervals stability is computed over.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Samples power once per interval to report hashes per watt.
+    pub fn with_power_reader(mut self, power: PowerReader) -> Self {
+        self.power = Some(power);
+        self
+    }
+
+    /// Whether a run measures `device` itself. The engine hashes GPUs and
+    /// ASICs in software on the host CPU, so only CPUs can be benchmarked.
+    pub fn supports(device: &Device) -> bool {
+        matches!(device.device_type, DeviceType::CPU { .. })
+    }
+
+    /// Benchmarks every supported device on each of its algorithms, one at
+    /// a time. Unsupported devices and failed runs are logged and left out.
+    pub async fn run_all(&self, devices: &[Device]) -> Vec<BenchmarkResult> {
+        let mut results = Vec::new();
+        for device in devices {
+            if !Self::supports(device) {
+                tracing::info!("Skipping benchmark of {}: not supported for this device type", device.id);
+                continue;
+            }
+            for algorithm in &device.capabilities.supported_algorithms {
+                match self.run(device, algorithm.clone()).await {
+                    Ok(result) => results.push(result),
+                    Err(e) => tracing::error!("Benchmark of {} on {} failed: {}", device.id, algorithms::registry().name(algorithm), e),
+                }
+            }
+        }
+        results
+    }
+
+    /// Mines `algorithm` on `device` for the configured duration.
+    pub async fn run(&self, device: &Device, algorithm: Algorithm) -> Result<BenchmarkResult> {
+        if !Self::supports(device) {
+            anyhow::bail!("{} cannot be benchmarked: the miner does not drive its hardware", device.id);
+        }
+        let name = algorithms::registry().name(&algorithm);
+        tracing::info!("Benchmarking {} on {} for {:?}", device.id, name, self.duration);
+        let engine = Engine::new(vec![device.clone()])?;
+        let job = synthetic_job(algorithm.clone());
+        engine.start_mining(&device.id, job.clone()).await?;
+        // The same implementation the engine mines with, since prepared
+        // state such as an Ethash epoch is shared.
+        let prepared = tokio::task::spawn_blocking(move || algorithms::registry().for_job(&job)).await?;
+        let measured = match prepared {
+            Ok(prepared) => self.measure(&engine, device, prepared.as_ref()).await,
+            Err(e) => Err(e),
+        };
+        engine.stop_mining(&device.id).await?;
+        let (interval_hashrates, power_watts) = measured?;
+
+        let (hashrate, stddev) = mean_and_stddev(&interval_hashrates);
+        let result = BenchmarkResult {
+            device_id: device.id.clone(),
+            device_name: device.name.clone(),
+            algorithm,
+            hashrate,
+            stddev,
+            interval_hashrates,
+            power_watts,
+            duration_secs: self.duration.as_secs_f64(),
+            timestamp: chrono::Utc::now(),
+        };
+        tracing::info!("{} on {}: {:.0} H/s (±{:.1}%)", device.id, name, result.hashrate, result.variation() * 100.0);
+        Ok(result)
+    }
+
+    async fn measure(&self, engine: &Engine, device: &Device, algorithm: &dyn MiningAlgorithm) -> Result<(Vec<f64>, Option<f64>)> {
+        // Intervals start at the first hash, and once an Ethash DAG is
+        // ready, so setup time and light-cache hashing are not counted.
+        let warmup = tokio::time::timeout(WARMUP_TIMEOUT, async {
+            while engine.hashes_done(&device.id).await == 0 || algorithm.is_warming_up() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await;
+        if warmup.is_err() {
+            anyhow::bail!("{} was not warmed up within {:?}", device.id, WARMUP_TIMEOUT);
+        }
+
+        let intervals = (self.duration.as_secs_f64() / self.interval.as_secs_f64()).ceil().max(1.0) as usize;
+        let mut hashrates = Vec::with_capacity(intervals);
+        let mut power = Vec::new();
+        let mut previous = (engine.hashes_done(&device.id).await, Instant::now());
+        for _ in 0..intervals {
+            tokio::time::sleep(self.interval).await;
+            let now = (engine.hashes_done(&device.id).await, Instant::now());
+            let elapsed = now.1.duration_since(previous.1).as_secs_f64();
+            hashrates.push(now.0.saturating_sub(previous.0) as f64 / elapsed);
+            previous = now;
+            if let Some(watts) = self.power.as_ref().and_then(|read| read(device)) {
+                power.push(watts);
+            }
+        }
+
+        let power_watts = (!power.is_empty()).then(|| mean_and_stddev(&power).0);
+        Ok((hashrates, power_watts))
+    }
+}
+
+/// A job no hash meets, with a fixed header of the size the algorithm
+/// mines over.
+pub fn synthetic_job(algorithm: Algorithm) -> MiningJob {
+    let mut job = MiningJob::new(format!("benchmark-{}", algorithm), algorithm, 1.0);
+    job.target = vec![0; 32];
+    job.header = match job.algorithm.id() {
+        // Ethash hashes the 32-byte header hash of epoch 0's seed.
+        "ethash" | "etchash" => {
+            job.seed_hash = vec![0; 32];
+            vec![0x5a; 32]
+        }
+        _ => vec![0x5a; crate::header::NONCE_OFFSET],
+    };
+    job
+}
+
+fn mean_and_stddev(values: &[f64]) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jxpoolminer_core::GPUVendor;
+
+    #[test]
+    fn test_mean_and_stddev() {
+        assert_eq!(mean_and_stddev(&[]), (0.0, 0.0));
+        assert_eq!(mean_and_stddev(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]), (5.0, 2.0));
+    }
+
+    #[tokio::test]
+    async fn test_benchmarks_every_supported_algorithm() {
+        let mut device = Device::new("cpu-0".to_string(), "Test CPU".to_string(), DeviceType::CPU { cores: 1 });
+        device.capabilities.supported_algorithms = vec![Algorithm::GXHASH, Algorithm::SHA256];
+        let benchmark = Benchmark::new(Duration::from_millis(300))
+            .with_interval(Duration::from_millis(100))
+            .with_power_reader(Arc::new(|_: &Device| Some(50.0)));
+
+        let results = benchmark.run_all(&[device]).await;
+        assert_eq!(results.iter().map(|r| r.algorithm.clone()).collect::<Vec<_>>(), vec![Algorithm::GXHASH, Algorithm::SHA256]);
+        for result in &results {
+            assert_eq!(result.interval_hashrates.len(), 3);
+            assert!(result.hashrate > 0.0);
+            assert_eq!(result.power_watts, Some(50.0));
+            assert_eq!(result.hashes_per_watt(), Some(result.hashrate / 50.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skips_devices_the_engine_does_not_drive() {
+        let mut gpu = Device::new("gpu-0".to_string(), "Test GPU".to_string(), DeviceType::GPU { vendor: GPUVendor::AMD });
+        gpu.capabilities.supported_algorithms = vec![Algorithm::SHA256];
+        let benchmark = Benchmark::new(Duration::from_millis(100));
+
+        assert!(!Benchmark::supports(&gpu));
+        assert!(benchmark.run_all(&[gpu.clone()]).await.is_empty());
+        assert!(benchmark.run(&gpu, Algorithm::SHA256).await.is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//! Ties the pieces together: pool jobs go to the engine, shares found by the
+//! engine go back to the pool, and the results end up in the collector.
+
+use crate::algorithms;
+use crate::Engine;
+use jxpoolminer_core::{Algorithm, MiningJob, Share};
+use jxpoolminer_pool::Client;
+use jxpoolminer_stats::Collector;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::{JoinHandle, JoinSet};
+
+/// How often found shares are collected from the device tasks.
+const SHARE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How often device hashrates are copied into the collector.
+const HASHRATE_INTERVAL: Duration = Duration::from_secs(5);
+/// How long shutdown waits for submissions still in flight.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+enum Command {
+    Start(String),
+    Stop(String),
+}
+
+/// Runs mining for a set of devices against one pool client. Devices are
+/// opted in with `start_device` and follow every job the pool sends until
+/// stopped or until `shutdown`.
+pub struct Coordinator {
+    engine: Arc<Engine>,
+    commands: mpsc::UnboundedSender<Command>,
+    shutdown: watch::Sender<bool>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Coordinator {
+    pub fn new(engine: Arc<Engine>, client: Arc<Client>, collector: Arc<Collector>) -> Self {
+        let (commands, command_rx) = mpsc::unbounded_channel();
+        let (shutdown, shutdown_rx) = watch::channel(false);
+
+        let run = Run {
+            engine: engine.clone(),
+            client,
+            collector,
+            active: BTreeSet::new(),
+            assigned: HashMap::new(),
+            current_job: None,
+            submissions: JoinSet::new(),
+        };
+        let task = tokio::spawn(run.run(command_rx, shutdown_rx));
+
+        Self {
+            engine,
+            commands,
+            shutdown,
+            task: Mutex::new(Some(task)),
+        }
+    }
+
+    pub fn engine(&self) -> &Arc<Engine> {
+        &self.engine
+    }
+
+    /// Starts mining on a device, with the pool's current job if there is
+    /// one and otherwise as soon as the first job arrives.
+    pub async fn start_device(&self, device_id: &str) -> Result<()> {
+        if !self.engine.devices().await.iter().any(|d| d.id == device_id) {
+            anyhow::bail!("Device not found: {}", device_id);
+        }
+        self.send(Command::Start(device_id.to_string()))
+    }
+
+    pub async fn start_all(&self) -> Result<()> {
+        for device in self.engine.devices().await {
+            self.send(Command::Start(device.id))?;
+        }
+        Ok(())
+    }
+
+    /// Stops a device after submitting any shares it has already found.
+    pub fn stop_device(&self, device_id: &str) -> Result<()> {
+        self.send(Command::Stop(device_id.to_string()))
+    }
+
+    /// Stops every device, waits for outstanding submissions and ends the
+    /// coordinator. Calling it again is a no-op.
+    pub async fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+        if let Some(task) = self.task.lock().await.take() {
+            if let Err(e) = task.await {
+                tracing::error!("Mining coordinator failed: {}", e);
+            }
+        }
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.commands.send(command)
+            .map_err(|_| anyhow::anyhow!("Mining coordinator is shut down"))
+    }
+}
+
+impl Drop for Coordinator {
+    fn drop(&mut self) {
+        self.shutdown.send_replace(true);
+    }
+}
+
+/// State owned by the coordinator task.
+struct Run {
+    engine: Arc<Engine>,
+    client: Arc<Client>,
+    collector: Arc<Collector>,
+    active: BTreeSet<String>,
+    /// Algorithm of the job each active device is mining, used to weigh
+    /// its shares.
+    assigned: HashMap<String, Algorithm>,
+    current_job: Option<MiningJob>,
+    submissions: JoinSet<()>,
+}
+
+impl Run {
+    async fn run(mut self, mut commands: mpsc::UnboundedReceiver<Command>, mut shutdown: watch::Receiver<bool>) {
+        let mut jobs = self.client.jobs();
+        let mut share_poll = tokio::time::interval(SHARE_POLL_INTERVAL);
+        let mut hashrate = tokio::time::interval(HASHRATE_INTERVAL);
+        // A job may already be waiting from before the coordinator started.
+        self.current_job = jobs.borrow_and_update().clone();
+
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => break,
+                changed = jobs.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let job = jobs.borrow_and_update().clone();
+                    match job {
+                        Some(job) => self.dispatch(job).await,
+                        None => self.idle().await,
+                    }
+                }
+                Some(command) = commands.recv() => match command {
+                    Command::Start(device_id) => self.start(device_id).await,
+                    Command::Stop(device_id) => self.stop(&device_id).await,
+                },
+                _ = share_poll.tick() => self.collect_all_shares().await,
+                _ = hashrate.tick() => self.sample_hashrates().await,
+                Some(_) = self.submissions.join_next(), if !self.submissions.is_empty() => {}
+            }
+        }
+
+        self.finish().await;
+    }
+
+    /// Moves every active device onto `job`. Devices switch in place, so
+    /// shares already found for jobs the pool still accepts are kept.
+    async fn dispatch(&mut self, job: MiningJob) {
+        tracing::info!("Dispatching job {} to {} device(s)", job.id, self.active.len());
+        let devices: Vec<_> = self.active.iter().cloned().collect();
+        for device_id in devices {
+            self.assign(&device_id, &job).await;
+        }
+        self.current_job = Some(job);
+    }
+
+    /// Stops every active device when the pool session is lost; its jobs
+    /// and unsubmitted shares would be rejected by the next session. The
+    /// devices stay active and resume with that session's first job.
+    async fn idle(&mut self) {
+        let Some(job) = self.current_job.take() else {
+            return;
+        };
+        tracing::info!("Pool session lost; idling {} device(s) mining job {}", self.active.len(), job.id);
+        let devices: Vec<_> = self.active.iter().cloned().collect();
+        for device_id in devices {
+            self.release(&device_id).await;
+        }
+    }
+
+    async fn start(&mut self, device_id: String) {
+        if !self.active.insert(device_id.clone()) {
+            return;
+        }
+        tracing::info!("Device {} joined mining", device_id);
+        match self.current_job.clone() {
+            Some(job) => self.assign(&device_id, &job).await,
+            None => tracing::info!("Device {} waiting for the first pool job", device_id),
+        }
+    }
+
+    async fn stop(&mut self, device_id: &str) {
+        if !self.active.remove(device_id) {
+            return;
+        }
+        self.collect_shares(device_id).await;
+        self.release(device_id).await;
+        tracing::info!("Device {} left mining", device_id);
+    }
+
+    async fn assign(&mut self, device_id: &str, job: &MiningJob) {
+        match self.engine.start_mining(device_id, job.clone()).await {
+            Ok(()) => {
+                self.assigned.insert(device_id.to_string(), job.algorithm.clone());
+            }
+            Err(e) => tracing::error!("Failed to start mining on {}: {}", device_id, e),
+        }
+    }
+
+    async fn release(&mut self, device_id: &str) {
+        if let Err(e) = self.engine.stop_mining(device_id).await {
+            tracing::error!("Failed to stop mining on {}: {}", device_id, e);
+        }
+        self.assigned.remove(device_id);
+        self.collector.record_hashrate(device_id, 0.0).await;
+    }
+
+    async fn collect_all_shares(&mut self) {
+        self.engine.set_session_difficulty(self.client.difficulty().await).await;
+        let devices: Vec<_> = self.active.iter().cloned().collect();
+        for device_id in devices {
+            self.collect_shares(&device_id).await;
+        }
+    }
+
+    async fn collect_shares(&mut self, device_id: &str) {
+        let Some(algorithm) = self.assigned.get(device_id).cloned() else {
+            return;
+        };
+        while let Some(share) = self.engine.get_share(device_id).await {
+            self.submit(share, &algorithm);
+        }
+    }
+
+    /// Submits in the background so a slow pool never holds up job switches.
+    fn submit(&mut self, share: Share, algorithm: &Algorithm) {
+        let client = self.client.clone();
+        let collector = self.collector.clone();
+        let work = algorithms::registry().resolve(algorithm)
+            .map_or(0.0, |implementation| implementation.work_for_difficulty(share.difficulty));
+
+        self.submissions.spawn(async move {
+            let device_id = share.device_id.clone();
+            match client.submit_share(share).await {
+                Ok(accepted) => collector.record_share(&device_id, accepted, work).await,
+                // Never reached the pool, so it is neither accepted nor rejected.
+                Err(e) => tracing::warn!("Failed to submit share from {}: {}", device_id, e),
+            }
+        });
+    }
+
+    async fn sample_hashrates(&mut self) {
+        for device_id in &self.active {
+            let hardware_errors = self.engine.hardware_errors(device_id).await;
+            self.collector.update_hardware_errors(device_id, hardware_errors).await;
+            let hashrate = self.engine.hashrate(device_id).await;
+            self.collector.record_hashrate(device_id, hashrate.smoothed).await;
+        }
+    }
+
+    async fn finish(mut self) {
+        tracing::info!("Stopping mining coordinator");
+        let devices: Vec<_> = self.active.iter().cloned().collect();
+        for device_id in devices {
+            self.stop(&device_id).await;
+        }
+
+        let pending = self.submissions.len();
+        let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+            while self.submissions.join_next().await.is_some() {}
+        }).await;
+        if drained.is_err() {
+            tracing::warn!("Abandoning share submissions still in flight out of {}", pending);
+            self.submissions.abort_all();
+        }
+    }
+}
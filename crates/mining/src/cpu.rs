@@ -0,0 +1,316 @@
+//! CPU mining on a dedicated rayon pool, so hashing never occupies tokio
+//! workers. Each thread owns a slice of the nonce space and hands shares
+//! back through the device's share channel. `DeviceSettings` can lower the
+//! threads' priority, pin them to cores and idle them for part of the time.
+
+use crate::algorithms::{self, MiningAlgorithm};
+use crate::work::{Extranonce2Range, NonceRange, WorkCursor};
+use jxpoolminer_core::{DeviceSettings, MiningJob, Share};
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Most nonces a worker hashes between looks at its stop flag, which bounds
+/// how long cancellation and job switches take.
+pub const CPU_BATCH: usize = 4096;
+
+/// Longest a throttled worker sleeps between looks at its stop flag.
+const IDLE_SLICE: Duration = Duration::from_millis(10);
+
+pub struct CpuMiner {
+    pool: rayon::ThreadPool,
+    threads: usize,
+    /// Duty cycle in millionths, shared with the workers.
+    duty_cycle: Arc<AtomicU32>,
+}
+
+impl CpuMiner {
+    pub fn new(device_id: &str, threads: usize) -> Result<Self> {
+        Self::with_settings(device_id, threads, &DeviceSettings::default())
+    }
+
+    /// A pool of `threads` workers with the priority, affinity and duty
+    /// cycle of `settings`. `settings.threads` is left to the caller, which
+    /// knows the default.
+    pub fn with_settings(device_id: &str, threads: usize, settings: &DeviceSettings) -> Result<Self> {
+        let settings = settings.clamped();
+        let threads = threads.max(1);
+        let name = device_id.to_string();
+        let (nice, affinity) = (settings.nice, settings.affinity.clone());
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(move |index| format!("{}-miner-{}", name, index))
+            .start_handler(move |index| limit_thread(index, nice, &affinity))
+            .build()?;
+        let miner = Self { pool, threads, duty_cycle: Arc::new(AtomicU32::new(0)) };
+        miner.set_duty_cycle(settings.duty_cycle);
+        Ok(miner)
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Changes the share of time workers hash, taking effect after their
+    /// current batch.
+    pub fn set_duty_cycle(&self, duty_cycle: f64) {
+        let duty_cycle = duty_cycle.clamp(DeviceSettings::MIN_DUTY_CYCLE, 1.0);
+        self.duty_cycle.store((duty_cycle * 1e6).round() as u32, Ordering::Relaxed);
+    }
+
+    pub fn duty_cycle(&self) -> f64 {
+        self.duty_cycle.load(Ordering::Relaxed) as f64 / 1e6
+    }
+
+    /// Puts every thread to work on `job`. Workers run until the returned
+    /// handle is stopped or dropped, or the share channel closes.
+    pub fn start(
+        &self,
+        algorithm: Arc<dyn MiningAlgorithm>,
+        job: &MiningJob,
+        extranonce2: Extranonce2Range,
+        device_id: &str,
+        shares: mpsc::Sender<Share>,
+        hashes: Arc<AtomicU64>,
+    ) -> CpuWork {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (done_tx, done_rx) = mpsc::channel::<()>(1);
+
+        for index in 0..self.threads {
+            let nonces = NonceRange::full().split(self.threads, index);
+            let mut work = WorkCursor::new(job.clone(), extranonce2).with_nonce_range(nonces);
+            let stop = stop.clone();
+            let shares = shares.clone();
+            let hashes = hashes.clone();
+            let device_id = device_id.to_string();
+            let algorithm = algorithm.clone();
+            let done = done_tx.clone();
+            let duty_cycle = self.duty_cycle.clone();
+
+            self.pool.spawn(move || {
+                // Dropped on return; `CpuWork::stopped` waits for all of them.
+                let _done = done;
+                let batch = algorithm.batch_size();
+                let mut counted = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    let started = Instant::now();
+                    let share = algorithms::scan(algorithm.as_ref(), &mut work, &device_id, batch.min(CPU_BATCH));
+                    // One shared add per batch keeps threads off each
+                    // other's cache lines.
+                    let total = work.hashes();
+                    hashes.fetch_add(total - counted, Ordering::Relaxed);
+                    counted = total;
+
+                    if let Some(share) = share {
+                        tracing::info!("Found share! Device: {}, Nonce: {}", device_id, share.nonce);
+                        if !send_share(&shares, share, &stop) {
+                            break;
+                        }
+                    }
+                    idle(started.elapsed(), duty_cycle.load(Ordering::Relaxed), &stop);
+                }
+            });
+        }
+
+        CpuWork { stop, done: done_rx }
+    }
+}
+
+/// Hands `share` to the channel, waiting while it is full but giving up
+/// once `stop` is set so a slow consumer cannot hold a worker past its
+/// batch. Returns false if the channel is closed.
+fn send_share(shares: &mpsc::Sender<Share>, mut share: Share, stop: &AtomicBool) -> bool {
+    loop {
+        match shares.try_send(share) {
+            Ok(()) => return true,
+            Err(mpsc::error::TrySendError::Closed(_)) => return false,
+            Err(mpsc::error::TrySendError::Full(unsent)) => {
+                if stop.load(Ordering::Relaxed) {
+                    tracing::warn!("Dropping share {} of job {}: share channel full", unsent.nonce, unsent.job_id);
+                    return true;
+                }
+                share = unsent;
+                std::thread::sleep(IDLE_SLICE);
+            }
+        }
+    }
+}
+
+/// Sleeps long enough after `busy` of hashing to keep to `duty_cycle`
+/// (in millionths), returning early once `stop` is set.
+fn idle(busy: Duration, duty_cycle: u32, stop: &AtomicBool) {
+    if duty_cycle >= 1_000_000 {
+        return;
+    }
+    let duty_cycle = duty_cycle.max(1) as f64 / 1e6;
+    let deadline = Instant::now() + busy.mul_f64((1.0 - duty_cycle) / duty_cycle);
+    while !stop.load(Ordering::Relaxed) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        std::thread::sleep(left.min(IDLE_SLICE));
+    }
+}
+
+/// Applies niceness and core pinning to the calling worker thread.
+#[cfg(target_os = "linux")]
+fn limit_thread(index: usize, nice: i32, affinity: &[usize]) {
+    if nice != 0 {
+        // On Linux priorities are per thread, so this leaves the rest of
+        // the process alone.
+        // SAFETY: plain syscalls on the current thread.
+        let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, nice) };
+        if result != 0 {
+            tracing::warn!("Cannot set mining thread niceness to {}: {}", nice, std::io::Error::last_os_error());
+        }
+    }
+    if !affinity.is_empty() {
+        let core = affinity[index % affinity.len()];
+        if core >= libc::CPU_SETSIZE as usize {
+            tracing::warn!("Cannot pin mining thread {} to core {}: no such core", index, core);
+            return;
+        }
+        // SAFETY: an all-zero cpu_set_t is empty, and `core` fits in it.
+        let result = unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_SET(core, &mut set);
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+        };
+        if result != 0 {
+            tracing::warn!("Cannot pin mining thread {} to core {}: {}", index, core, std::io::Error::last_os_error());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn limit_thread(_index: usize, nice: i32, affinity: &[usize]) {
+    if nice != 0 || !affinity.is_empty() {
+        tracing::warn!("Thread priority and core pinning are only supported on Linux");
+    }
+}
+
+/// Workers mining one job.
+pub struct CpuWork {
+    stop: Arc<AtomicBool>,
+    done: mpsc::Receiver<()>,
+}
+
+impl CpuWork {
+    /// Asks every worker to return after its current batch.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits until every worker has returned.
+    pub async fn stopped(&mut self) {
+        while self.done.recv().await.is_some() {}
+    }
+}
+
+impl Drop for CpuWork {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jxpoolminer_core::Algorithm;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_workers_scan_disjoint_nonces() {
+        let miner = CpuMiner::new("cpu-test", 4).unwrap();
+        let mut job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0);
+        job.target = vec![0xff; 32];
+        let (share_tx, mut share_rx) = mpsc::channel(1000);
+        let hashes = Arc::new(AtomicU64::new(0));
+
+        let mut work = miner.start(algorithms::registry().resolve(&job.algorithm).unwrap(), &job, Extranonce2Range::full(0), "cpu-test", share_tx, hashes.clone());
+        let mut nonces = HashSet::new();
+        let mut quarters = HashSet::new();
+        // Every thread contributes from its own quarter of the space.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while quarters.len() < 4 {
+                let share = share_rx.recv().await.unwrap();
+                assert!(nonces.insert(share.nonce), "nonce {} mined twice", share.nonce);
+                quarters.insert(share.nonce >> 30);
+            }
+        }).await.expect("not every worker found shares");
+
+        work.stop();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            // Drain so no worker stays blocked on a full channel.
+            while share_rx.try_recv().is_ok() {}
+            work.stopped().await;
+        }).await.expect("workers did not stop within 1s");
+        assert!(hashes.load(Ordering::Relaxed) >= nonces.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_stop_is_bounded_by_one_batch() {
+        let miner = CpuMiner::new("cpu-test", 2).unwrap();
+        let mut job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0);
+        job.target = vec![0; 32];
+        let (share_tx, _share_rx) = mpsc::channel(1);
+        let hashes = Arc::new(AtomicU64::new(0));
+
+        let mut work = miner.start(algorithms::registry().resolve(&job.algorithm).unwrap(), &job, Extranonce2Range::full(4), "cpu-test", share_tx, hashes.clone());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        work.stop();
+        tokio::time::timeout(Duration::from_secs(1), work.stopped()).await.expect("workers did not stop within 1s");
+
+        let stopped_at = hashes.load(Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(hashes.load(Ordering::Relaxed), stopped_at);
+        assert_eq!(miner.threads(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stop_is_not_held_up_by_a_full_share_channel() {
+        let miner = CpuMiner::new("cpu-test", 2).unwrap();
+        let mut job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0);
+        job.target = vec![0xff; 32];
+        // Nobody reads, so the channel fills with the first share.
+        let (share_tx, _share_rx) = mpsc::channel(1);
+
+        let mut work = miner.start(algorithms::registry().resolve(&job.algorithm).unwrap(), &job, Extranonce2Range::full(4), "cpu-test", share_tx, Arc::new(AtomicU64::new(0)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        work.stop();
+        tokio::time::timeout(Duration::from_secs(1), work.stopped()).await.expect("workers did not stop within 1s");
+    }
+
+    #[test]
+    fn test_idle_keeps_to_the_duty_cycle() {
+        let stop = AtomicBool::new(false);
+        let started = Instant::now();
+        idle(Duration::from_millis(20), 1_000_000, &stop);
+        assert!(started.elapsed() < Duration::from_millis(5));
+
+        // 20ms busy at 50% duty idles another 20ms.
+        let started = Instant::now();
+        idle(Duration::from_millis(20), 500_000, &stop);
+        assert!(started.elapsed() >= Duration::from_millis(20));
+
+        stop.store(true, Ordering::Relaxed);
+        let started = Instant::now();
+        idle(Duration::from_secs(10), 10_000, &stop);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_settings_are_clamped() {
+        let settings = DeviceSettings { duty_cycle: 0.0, nice: 0, ..DeviceSettings::default() };
+        let miner = CpuMiner::with_settings("cpu-test", 1, &settings).unwrap();
+        assert_eq!(miner.duty_cycle(), DeviceSettings::MIN_DUTY_CYCLE);
+        miner.set_duty_cycle(1.5);
+        assert_eq!(miner.duty_cycle(), 1.0);
+        miner.set_duty_cycle(0.6);
+        assert_eq!(miner.duty_cycle(), 0.6);
+    }
+}
@@ -1,18 +1,66 @@
-use jxpoolminer_core::{Device, MiningJob, Share, DeviceStatus};
+use jxpoolminer_core::{Device, DeviceSettings, DeviceStatus, DeviceType, MiningJob, Share};
 use anyhow::Result;
-use std::collections::HashMap;
-use tokio::sync::{RwLock, mpsc};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::{RwLock, mpsc, watch};
 use std::sync::Arc;
-use crate::algorithms;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use crate::algorithms::{self, MiningAlgorithm};
+use crate::algorithms::ethash::DagProgress;
+use crate::algorithms::sha256::Kernel;
+use crate::cpu::CpuMiner;
+use crate::hashrate::{Hashrate, HashrateMeter, SAMPLE_INTERVAL};
+use crate::verify::{ShareVerifier, Verdict};
+use crate::work::{Extranonce2Range, WorkCursor};
+
+/// Jobs a device's shares may still be submitted for, newest last. Pools
+/// keep accepting shares for recent jobs until one arrives with
+/// `clean_jobs`.
+const LIVE_JOBS: usize = 8;
+
+/// How long `stop_mining` waits for a device's workers to wind down.
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
 
 struct MiningTask {
     cancel_tx: mpsc::Sender<()>,
+    job_tx: watch::Sender<MiningJob>,
+    settings_tx: watch::Sender<DeviceSettings>,
     share_rx: Arc<RwLock<mpsc::Receiver<Share>>>,
+    live_jobs: VecDeque<MiningJob>,
+    verifier: Arc<std::sync::Mutex<ShareVerifier>>,
+    handle: tokio::task::JoinHandle<()>,
+    sampler: tokio::task::JoinHandle<()>,
+}
+
+impl MiningTask {
+    fn switch_job(&mut self, job: MiningJob) {
+        if job.clean_jobs {
+            self.live_jobs.clear();
+        }
+        self.live_jobs.retain(|live| live.id != job.id);
+        self.live_jobs.push_back(job.clone());
+        while self.live_jobs.len() > LIVE_JOBS {
+            self.live_jobs.pop_front();
+        }
+        let live_jobs = &self.live_jobs;
+        self.verifier.lock().unwrap().retain(|id| live_jobs.iter().any(|live| live.id == id));
+        self.job_tx.send_replace(job);
+    }
 }
 
 pub struct Engine {
     devices: Arc<RwLock<Vec<Device>>>,
     active_tasks: Arc<RwLock<HashMap<String, MiningTask>>>,
+    hash_counters: Arc<RwLock<HashMap<String, Arc<AtomicU64>>>>,
+    hashrates: Arc<RwLock<HashMap<String, Hashrate>>>,
+    hardware_errors: Arc<RwLock<HashMap<String, u64>>>,
+    session_difficulty: Arc<RwLock<Option<f64>>>,
+    settings: Arc<RwLock<HashMap<String, DeviceSettings>>>,
+    /// Fraction of each device's duty cycle it may use, when lowered.
+    throttles: Arc<RwLock<HashMap<String, f64>>>,
+    /// Devices stopped until resumed, with the job to resume with.
+    halted: Arc<RwLock<HashMap<String, Option<MiningJob>>>>,
+    cpu_threads: Option<usize>,
 }
 
 impl Engine {
@@ -20,51 +68,197 @@ impl Engine {
         Ok(Self {
             devices: Arc::new(RwLock::new(devices)),
             active_tasks: Arc::new(RwLock::new(HashMap::new())),
+            hash_counters: Arc::new(RwLock::new(HashMap::new())),
+            hashrates: Arc::new(RwLock::new(HashMap::new())),
+            hardware_errors: Arc::new(RwLock::new(HashMap::new())),
+            session_difficulty: Arc::new(RwLock::new(None)),
+            settings: Arc::new(RwLock::new(HashMap::new())),
+            throttles: Arc::new(RwLock::new(HashMap::new())),
+            halted: Arc::new(RwLock::new(HashMap::new())),
+            cpu_threads: None,
         })
     }
     
-    pub async fn start_mining(&self, device_id: &str, job: MiningJob) -> Result<()> {
-        let device = {
+    /// Mining threads per CPU device; defaults to one per core.
+    pub fn with_cpu_threads(mut self, threads: usize) -> Self {
+        self.cpu_threads = Some(threads.max(1));
+        self
+    }
+    
+    /// Resource limits per device id. A device's own thread count takes
+    /// precedence over `with_cpu_threads`.
+    pub fn with_device_settings(self, settings: impl IntoIterator<Item = (String, DeviceSettings)>) -> Self {
+        let settings = settings.into_iter().map(|(id, settings)| (id, settings.clamped())).collect();
+        Self {
+            settings: Arc::new(RwLock::new(settings)),
+            ..self
+        }
+    }
+    
+    /// Changes a device's limits, applying them to its running task:
+    /// a new duty cycle after the current batch, a new thread count,
+    /// priority or affinity by restarting its workers.
+    pub async fn set_device_settings(&self, device_id: &str, settings: DeviceSettings) -> Result<()> {
+        if !self.devices.read().await.iter().any(|d| d.id == device_id) {
+            anyhow::bail!("Device not found: {}", device_id);
+        }
+        self.settings.write().await.insert(device_id.to_string(), settings.clamped());
+        self.apply_settings(device_id).await;
+        Ok(())
+    }
+    
+    /// A device's limits as configured, before any throttling.
+    pub async fn device_settings(&self, device_id: &str) -> DeviceSettings {
+        self.settings.read().await.get(device_id).cloned().unwrap_or_default()
+    }
+    
+    /// Scales a device's duty cycle by `factor`, from 0 to 1, without
+    /// touching its configured settings. Only CPU devices slow down.
+    pub async fn set_throttle(&self, device_id: &str, factor: f64) -> Result<()> {
+        if !self.devices.read().await.iter().any(|d| d.id == device_id) {
+            anyhow::bail!("Device not found: {}", device_id);
+        }
+        let factor = if factor.is_nan() { 1.0 } else { factor.clamp(0.0, 1.0) };
+        self.throttles.write().await.insert(device_id.to_string(), factor);
+        self.apply_settings(device_id).await;
+        Ok(())
+    }
+    
+    pub async fn throttle(&self, device_id: &str) -> f64 {
+        self.throttles.read().await.get(device_id).copied().unwrap_or(1.0)
+    }
+    
+    /// The settings a device mines with: its own, throttled.
+    async fn effective_settings(&self, device_id: &str) -> DeviceSettings {
+        let settings = self.device_settings(device_id).await;
+        let duty_cycle = settings.duty_cycle * self.throttle(device_id).await;
+        DeviceSettings { duty_cycle, ..settings }.clamped()
+    }
+    
+    async fn apply_settings(&self, device_id: &str) {
+        let settings = self.effective_settings(device_id).await;
+        if let Some(task) = self.active_tasks.read().await.get(device_id) {
+            task.settings_tx.send_replace(settings);
+        }
+    }
+    
+    /// Stops a device and keeps it stopped, with `reason` as its error
+    /// status, until `resume`. Jobs sent meanwhile are held for then.
+    pub async fn halt(&self, device_id: &str, reason: &str) -> Result<()> {
+        if !self.devices.read().await.iter().any(|d| d.id == device_id) {
+            anyhow::bail!("Device not found: {}", device_id);
+        }
+        let job = {
+            let tasks = self.active_tasks.read().await;
+            tasks.get(device_id).map(|task| task.job_tx.borrow().clone())
+        };
+        {
+            // Halted before the task stops, so nothing restarts it between.
+            let mut halted = self.halted.write().await;
+            let pending = halted.entry(device_id.to_string()).or_default();
+            if job.is_some() {
+                *pending = job;
+            }
+        }
+        self.stop_task(device_id).await;
+        self.update_device_status(device_id, DeviceStatus::Error(reason.to_string())).await;
+        tracing::warn!("Device {} halted: {}", device_id, reason);
+        Ok(())
+    }
+    
+    /// Lifts a `halt`, restarting the device on the newest job it was
+    /// given if it was mining.
+    pub async fn resume(&self, device_id: &str) -> Result<()> {
+        let Some(pending) = self.halted.write().await.remove(device_id) else {
+            return Ok(());
+        };
+        self.update_device_status(device_id, DeviceStatus::Idle).await;
+        tracing::info!("Device {} resumed", device_id);
+        match pending {
+            Some(job) => self.start_mining(device_id, job).await,
+            None => Ok(()),
+        }
+    }
+    
+    pub async fn is_halted(&self, device_id: &str) -> bool {
+        self.halted.read().await.contains_key(device_id)
+    }
+    
+    /// Starts mining `job` on a device. A device that is already mining
+    /// keeps its task and switches to `job` at its next batch boundary;
+    /// with `clean_jobs` set, shares still queued for older jobs are dropped.
+    pub async fn start_mining(&self, device_id: &str, mut job: MiningJob) -> Result<()> {
+        let (device, index, count) = {
             let devices = self.devices.read().await;
-            devices.iter()
-                .find(|d| d.id == device_id)
-                .cloned()
-                .ok_or_else(|| anyhow::anyhow!("Device not found: {}", device_id))?
+            let index = devices.iter()
+                .position(|d| d.id == device_id)
+                .ok_or_else(|| anyhow::anyhow!("Device not found: {}", device_id))?;
+            (devices[index].clone(), index, devices.len())
         };
         
-        self.stop_mining(device_id).await?;
-        
-        let (cancel_tx, mut cancel_rx) = mpsc::channel(1);
-        let (share_tx, share_rx) = mpsc::channel(100);
+        let implementation = algorithms::registry().resolve(&job.algorithm)?;
+        if job.target.is_empty() {
+            job.target = implementation.target_for_difficulty(job.difficulty).to_vec();
+        } else {
+            // Pools that send a target leave the difficulty for us to derive.
+            job.difficulty = implementation.difficulty_for_target(&job.target);
+        }
         
-        let device_clone = device.clone();
-        let job_clone = job.clone();
+        if let Some(pending) = self.halted.write().await.get_mut(device_id) {
+            tracing::info!("Device {} is halted; job {} waits for it to resume", device_id, job.id);
+            *pending = Some(job);
+            return Ok(());
+        }
         
-        tokio::spawn(async move {
-            tracing::info!("Starting mining on device: {}", device_clone.id);
-            
-            loop {
-                match algorithms::mine(&device_clone, job_clone.clone(), &mut cancel_rx).await {
-                    Ok(share) => {
-                        tracing::info!("Found share! Device: {}, Nonce: {}", device_clone.id, share.nonce);
-                        if share_tx.send(share).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Mining error on device {}: {}", device_clone.id, e);
-                        break;
-                    }
-                }
+        let mut tasks = self.active_tasks.write().await;
+        if let Some(task) = tasks.get_mut(device_id) {
+            if !task.cancel_tx.is_closed() {
+                tracing::info!("Switching device {} to job {} (clean={})", device_id, job.id, job.clean_jobs);
+                task.switch_job(job);
+                return Ok(());
             }
-        });
+        }
+        
+        let (cancel_tx, cancel_rx) = mpsc::channel(1);
+        let (share_tx, share_rx) = mpsc::channel(100);
+        let (job_tx, job_rx) = watch::channel(job.clone());
+        let (settings_tx, settings_rx) = watch::channel(self.effective_settings(device_id).await);
+        
+        let hashes = self.hash_counters.write().await
+            .entry(device_id.to_string())
+            .or_default()
+            .clone();
+        let default_threads = match device.device_type {
+            DeviceType::CPU { cores } => self.cpu_threads.unwrap_or(cores),
+            _ => 1,
+        };
+        let sampler = tokio::spawn(sample_hashrate(device_id.to_string(), hashes.clone(), self.hashrates.clone()));
+        let device_task = DeviceTask {
+            device,
+            index,
+            count,
+            default_threads,
+            hashes,
+            job_rx,
+            settings_rx,
+            cancel_rx,
+            share_tx,
+        };
+        let handle = tokio::spawn(device_task.run());
         
         let task = MiningTask {
             cancel_tx,
+            job_tx,
+            settings_tx,
             share_rx: Arc::new(RwLock::new(share_rx)),
+            live_jobs: VecDeque::from([job]),
+            verifier: Arc::new(std::sync::Mutex::new(ShareVerifier::new())),
+            handle,
+            sampler,
         };
         
-        self.active_tasks.write().await.insert(device_id.to_string(), task);
+        tasks.insert(device_id.to_string(), task);
+        drop(tasks);
         
         {
             let mut devices = self.devices.write().await;
@@ -78,29 +272,118 @@ impl Engine {
     }
     
     pub async fn stop_mining(&self, device_id: &str) -> Result<()> {
-        if let Some(task) = self.active_tasks.write().await.remove(device_id) {
+        self.stop_task(device_id).await;
+        // A halted device keeps its error status but no longer resumes
+        // mining.
+        match self.halted.write().await.get_mut(device_id) {
+            Some(pending) => *pending = None,
+            None => self.update_device_status(device_id, DeviceStatus::Idle).await,
+        }
+        Ok(())
+    }
+    
+    async fn stop_task(&self, device_id: &str) {
+        let task = self.active_tasks.write().await.remove(device_id);
+        if let Some(task) = task {
+            task.sampler.abort();
             let _ = task.cancel_tx.send(()).await;
+            // Closing the share channel releases workers blocked on it.
+            drop(task.share_rx);
+            if tokio::time::timeout(STOP_TIMEOUT, task.handle).await.is_err() {
+                tracing::warn!("Device {} did not stop within {:?}", device_id, STOP_TIMEOUT);
+            }
             tracing::info!("Mining stopped on device: {}", device_id);
         }
-        
-        {
-            let mut devices = self.devices.write().await;
-            if let Some(device) = devices.iter_mut().find(|d| d.id == device_id) {
-                device.status = DeviceStatus::Idle;
+        self.hashrates.write().await.remove(device_id);
+    }
+    
+    /// Next share found by a device, skipping any whose job has been
+    /// superseded by a clean job (or aged out of the live set). Shares are
+    /// re-hashed with the reference implementation first; ones that fail
+    /// count as hardware errors, and those and duplicates are dropped.
+    pub async fn get_share(&self, device_id: &str) -> Option<Share> {
+        let session_difficulty = *self.session_difficulty.read().await;
+        loop {
+            let (share, job, verifier) = {
+                let tasks = self.active_tasks.read().await;
+                let task = tasks.get(device_id)?;
+                let share = task.share_rx.write().await.try_recv().ok()?;
+                let Some(job) = task.live_jobs.iter().find(|job| job.id == share.job_id) else {
+                    tracing::debug!("Dropping stale share for job {} from device {}", share.job_id, device_id);
+                    continue;
+                };
+                (share, job.clone(), task.verifier.clone())
+            };
+            // The re-hash, and on a job's first share readying its
+            // algorithm, can take long enough to stall the runtime.
+            let checked = tokio::task::spawn_blocking(move || {
+                let verdict = verifier.lock().unwrap().check(&job, &share, session_difficulty);
+                (share, verdict)
+            }).await;
+            let (share, verdict) = match checked {
+                Ok(checked) => checked,
+                Err(e) => {
+                    tracing::error!("Share verification for {} failed: {}", device_id, e);
+                    continue;
+                }
+            };
+            match verdict {
+                Ok(Verdict::Valid) => return Some(share),
+                Ok(Verdict::Duplicate) => {
+                    tracing::warn!("Dropping duplicate share from {} (job {}, nonce {})", device_id, share.job_id, share.nonce);
+                }
+                Ok(Verdict::BelowSessionDifficulty) => {
+                    tracing::debug!("Dropping share from {} below the session difficulty", device_id);
+                }
+                Ok(Verdict::HardwareError(reason)) => {
+                    tracing::error!("Hardware error on {}: {} (job {}, nonce {})", device_id, reason, share.job_id, share.nonce);
+                    *self.hardware_errors.write().await.entry(device_id.to_string()).or_default() += 1;
+                }
+                Err(e) => tracing::error!("Cannot verify share from {}: {}", device_id, e),
             }
         }
-        
-        Ok(())
     }
     
-    pub async fn get_share(&self, device_id: &str) -> Option<Share> {
+    /// Sets the pool's current difficulty, below which shares are no
+    /// longer worth submitting even for jobs sent at a lower one.
+    pub async fn set_session_difficulty(&self, difficulty: f64) {
+        *self.session_difficulty.write().await = Some(difficulty);
+    }
+    
+    /// Shares from a device that failed verification since the engine was
+    /// created.
+    pub async fn hardware_errors(&self, device_id: &str) -> u64 {
+        self.hardware_errors.read().await.get(device_id).copied().unwrap_or(0)
+    }
+    
+    /// Id of the job a device is currently mining.
+    pub async fn current_job(&self, device_id: &str) -> Option<String> {
         let tasks = self.active_tasks.read().await;
-        if let Some(task) = tasks.get(device_id) {
-            let mut rx = task.share_rx.write().await;
-            rx.try_recv().ok()
-        } else {
-            None
-        }
+        tasks.get(device_id).map(|task| task.job_tx.borrow().id.clone())
+    }
+    
+    /// Total hashes a device has computed since the engine was created.
+    pub async fn hashes_done(&self, device_id: &str) -> u64 {
+        self.hash_counters.read().await
+            .get(device_id)
+            .map_or(0, |counter| counter.load(Ordering::Relaxed))
+    }
+    
+    /// Measured hashrate of a device; zero while it is not mining.
+    pub async fn hashrate(&self, device_id: &str) -> Hashrate {
+        self.hashrates.read().await.get(device_id).copied().unwrap_or_default()
+    }
+    
+    /// SHA-256 kernel a CPU device mines with; `None` for other devices.
+    pub async fn sha256_kernel(&self, device_id: &str) -> Option<Kernel> {
+        let devices = self.devices.read().await;
+        let device = devices.iter().find(|d| d.id == device_id)?;
+        matches!(device.device_type, DeviceType::CPU { .. }).then(Kernel::active)
+    }
+    
+    /// Progress of the Ethash DAG currently being generated, if any.
+    pub fn dag_progress(&self) -> watch::Receiver<Option<DagProgress>> {
+        algorithms::ethash::dag_progress()
     }
     
     pub async fn devices(&self) -> Vec<Device> {
@@ -114,3 +397,325 @@ impl Engine {
         }
     }
 }
+
+/// Samples a device's hash counter into `hashrates` until aborted.
+async fn sample_hashrate(device_id: String, hashes: Arc<AtomicU64>, hashrates: Arc<RwLock<HashMap<String, Hashrate>>>) {
+    let mut meter = HashrateMeter::new();
+    let mut ticks = tokio::time::interval(SAMPLE_INTERVAL);
+    loop {
+        ticks.tick().await;
+        let rate = meter.sample(hashes.load(Ordering::Relaxed), Instant::now());
+        hashrates.write().await.insert(device_id.clone(), rate);
+    }
+}
+
+/// The implementation to mine `job` with, readied on a blocking thread
+/// since that can mean generating an Ethash cache.
+async fn prepare(job: &MiningJob) -> Result<Arc<dyn MiningAlgorithm>> {
+    let job = job.clone();
+    tokio::task::spawn_blocking(move || algorithms::registry().for_job(&job)).await?
+}
+
+/// Everything a device's mining task owns.
+struct DeviceTask {
+    device: Device,
+    /// Position among the engine's devices, for extranonce2 partitioning.
+    index: usize,
+    count: usize,
+    /// Threads for CPU devices whose settings do not name a count.
+    default_threads: usize,
+    hashes: Arc<AtomicU64>,
+    job_rx: watch::Receiver<MiningJob>,
+    settings_rx: watch::Receiver<DeviceSettings>,
+    cancel_rx: mpsc::Receiver<()>,
+    share_tx: mpsc::Sender<Share>,
+}
+
+impl DeviceTask {
+    async fn run(self) {
+        tracing::info!("Starting mining on device: {}", self.device.id);
+        match self.device.device_type {
+            DeviceType::CPU { .. } => self.run_cpu().await,
+            _ => self.run_async().await,
+        }
+    }
+    
+    fn extranonce2_range(&self, job: &MiningJob) -> Extranonce2Range {
+        // Each device owns a disjoint slice of the extranonce2 space so no
+        // two devices ever hash the same coinbase.
+        Extranonce2Range::full(job.extranonce2_size).split(self.count, self.index)
+    }
+    
+    fn cpu_miner(&self, settings: &DeviceSettings) -> Option<CpuMiner> {
+        let threads = settings.threads.unwrap_or(self.default_threads);
+        match CpuMiner::with_settings(&self.device.id, threads, settings) {
+            Ok(miner) => {
+                tracing::info!(
+                    "Mining on {} with {} thread(s) at {:.0}% duty, nice {}, SHA-256 kernel {}",
+                    self.device.id, miner.threads(), miner.duty_cycle() * 100.0, settings.nice, Kernel::active().name(),
+                );
+                Some(miner)
+            }
+            Err(e) => {
+                tracing::error!("Failed to start CPU workers for {}: {}", self.device.id, e);
+                None
+            }
+        }
+    }
+    
+    async fn run_cpu(mut self) {
+        let mut settings = self.settings_rx.borrow_and_update().clone();
+        let Some(mut miner) = self.cpu_miner(&settings) else {
+            return;
+        };
+        
+        'jobs: loop {
+            let job = self.job_rx.borrow_and_update().clone();
+            let range = self.extranonce2_range(&job);
+            let mut work = match prepare(&job).await {
+                Ok(algorithm) => Some(miner.start(algorithm, &job, range, &self.device.id, self.share_tx.clone(), self.hashes.clone())),
+                Err(e) => {
+                    tracing::error!("Cannot mine job {} on {}: {}", job.id, self.device.id, e);
+                    None
+                }
+            };
+            
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = self.cancel_rx.recv() => {
+                        if let Some(work) = work.as_mut() {
+                            work.stop();
+                            work.stopped().await;
+                        }
+                        break 'jobs;
+                    }
+                    // Replaced workers finish their batch before the new
+                    // ones get their threads.
+                    changed = self.job_rx.changed() => match changed {
+                        Ok(()) => continue 'jobs,
+                        Err(_) => break 'jobs,
+                    },
+                    changed = self.settings_rx.changed() => {
+                        if changed.is_err() {
+                            break 'jobs;
+                        }
+                        let new_settings = self.settings_rx.borrow_and_update().clone();
+                        if new_settings.threads == settings.threads && new_settings.nice == settings.nice && new_settings.affinity == settings.affinity {
+                            miner.set_duty_cycle(new_settings.duty_cycle);
+                            tracing::info!("{} now mining at {:.0}% duty", self.device.id, miner.duty_cycle() * 100.0);
+                            settings = new_settings;
+                            continue;
+                        }
+                        // Threads are configured as they start, so the
+                        // pool is rebuilt and the job restarted on it.
+                        if let Some(work) = work.as_mut() {
+                            work.stop();
+                            work.stopped().await;
+                        }
+                        settings = new_settings;
+                        match self.cpu_miner(&settings) {
+                            Some(rebuilt) => miner = rebuilt,
+                            None => break 'jobs,
+                        }
+                        continue 'jobs;
+                    }
+                    _ = self.share_tx.closed() => break 'jobs,
+                }
+            }
+        }
+    }
+    
+    async fn run_async(mut self) {
+        'jobs: loop {
+            let job = self.job_rx.borrow_and_update().clone();
+            let range = self.extranonce2_range(&job);
+            let algorithm = match prepare(&job).await {
+                Ok(algorithm) => algorithm,
+                Err(e) => {
+                    tracing::error!("Cannot mine job {} on {}: {}", job.id, self.device.id, e);
+                    tokio::select! {
+                        _ = self.cancel_rx.recv() => break 'jobs,
+                        changed = self.job_rx.changed() => match changed {
+                            Ok(()) => continue 'jobs,
+                            Err(_) => break 'jobs,
+                        },
+                    }
+                }
+            };
+            let mut work = WorkCursor::new(job, range).with_hash_counter(self.hashes.clone());
+            
+            loop {
+                tokio::select! {
+                    biased;
+                    // A new job is picked up at once; the batch under way
+                    // finishes on its blocking thread and is dropped.
+                    changed = self.job_rx.changed() => match changed {
+                        Ok(()) => continue 'jobs,
+                        Err(_) => break 'jobs,
+                    },
+                    result = algorithms::mine(&algorithm, &self.device, &mut work, &mut self.cancel_rx) => match result {
+                        Ok(share) => {
+                            tracing::info!("Found share! Device: {}, Nonce: {}", self.device.id, share.nonce);
+                            if self.share_tx.send(share).await.is_err() {
+                                break 'jobs;
+                            }
+                        }
+                        Err(e) => {
+                            tracing::error!("Mining error on device {}: {}", self.device.id, e);
+                            break 'jobs;
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jxpoolminer_core::{Algorithm, DeviceType};
+    use std::time::{Duration, Instant};
+
+    fn engine() -> Engine {
+        let device = Device::new("cpu-0".to_string(), "Test CPU".to_string(), DeviceType::CPU { cores: 1 });
+        Engine::new(vec![device]).unwrap()
+    }
+
+    /// A job whose target is all `fill` bytes: 0x00 never yields a share,
+    /// 0xff makes every hash one.
+    fn job(id: &str, fill: u8, clean_jobs: bool) -> MiningJob {
+        let mut job = MiningJob::new(id.to_string(), Algorithm::SHA256, 1.0);
+        job.target = vec![fill; 32];
+        job.clean_jobs = clean_jobs;
+        job
+    }
+
+    async fn next_share(engine: &Engine) -> Share {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(share) = engine.get_share("cpu-0").await {
+                    return share;
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }).await.expect("no share within 5s")
+    }
+
+    #[tokio::test]
+    async fn test_switches_job_at_batch_boundary() {
+        let engine = engine();
+        engine.start_mining("cpu-0", job("a", 0x00, true)).await.unwrap();
+        let hashes_before = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let hashes = engine.hashes_done("cpu-0").await;
+                if hashes > 0 {
+                    return hashes;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("no hashes within 5s");
+        
+        let switched_at = Instant::now();
+        engine.start_mining("cpu-0", job("b", 0xff, false)).await.unwrap();
+        let share = next_share(&engine).await;
+        let latency = switched_at.elapsed();
+        
+        assert_eq!(share.job_id, "b");
+        assert!(latency < Duration::from_secs(1), "job switch took {:?}", latency);
+        assert_eq!(engine.current_job("cpu-0").await.as_deref(), Some("b"));
+        // The same task kept going, so the hash count carried on.
+        assert!(engine.hashes_done("cpu-0").await > hashes_before);
+    }
+
+    #[tokio::test]
+    async fn test_clean_job_drops_queued_shares() {
+        let engine = engine();
+        engine.start_mining("cpu-0", job("a", 0xff, true)).await.unwrap();
+        let first = next_share(&engine).await;
+        assert_eq!(first.job_id, "a");
+        // Let the queue fill up with shares for the old job.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        
+        engine.start_mining("cpu-0", job("b", 0xff, true)).await.unwrap();
+        for _ in 0..20 {
+            assert_eq!(next_share(&engine).await.job_id, "b");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_clean_job_keeps_queued_shares() {
+        let engine = engine();
+        engine.start_mining("cpu-0", job("a", 0xff, true)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        
+        engine.start_mining("cpu-0", job("b", 0xff, false)).await.unwrap();
+        assert_eq!(next_share(&engine).await.job_id, "a");
+        
+        let mut share = next_share(&engine).await;
+        while share.job_id == "a" {
+            share = next_share(&engine).await;
+        }
+        assert_eq!(share.job_id, "b");
+    }
+
+    #[tokio::test]
+    async fn test_stop_then_start_spawns_fresh_task() {
+        let engine = engine();
+        engine.start_mining("cpu-0", job("a", 0xff, true)).await.unwrap();
+        next_share(&engine).await;
+        engine.stop_mining("cpu-0").await.unwrap();
+        assert!(engine.get_share("cpu-0").await.is_none());
+        assert_eq!(engine.current_job("cpu-0").await, None);
+        
+        engine.start_mining("cpu-0", job("b", 0xff, false)).await.unwrap();
+        assert_eq!(next_share(&engine).await.job_id, "b");
+        assert!(engine.start_mining("gpu-9", job("c", 0xff, false)).await.is_err());
+        assert_eq!(engine.sha256_kernel("cpu-0").await, Some(Kernel::active()));
+        assert_eq!(engine.sha256_kernel("gpu-9").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_measures_hashrate_while_mining() {
+        let engine = engine();
+        engine.start_mining("cpu-0", job("a", 0x00, true)).await.unwrap();
+        let hashrate = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let hashrate = engine.hashrate("cpu-0").await;
+                if hashrate.instant > 0.0 {
+                    return hashrate;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }).await.expect("no hashrate within 5s");
+        assert!(hashrate.smoothed > 0.0);
+        
+        engine.stop_mining("cpu-0").await.unwrap();
+        assert_eq!(engine.hashrate("cpu-0").await, Hashrate::default());
+    }
+
+    #[tokio::test]
+    async fn test_settings_apply_to_running_task() {
+        let engine = engine();
+        engine.start_mining("cpu-0", job("a", 0xff, true)).await.unwrap();
+        next_share(&engine).await;
+        
+        let settings = DeviceSettings { threads: Some(2), duty_cycle: 0.5, ..DeviceSettings::default() };
+        engine.set_device_settings("cpu-0", settings.clone()).await.unwrap();
+        assert_eq!(engine.device_settings("cpu-0").await, settings);
+        // The workers were rebuilt and keep mining the same job.
+        let hashes = engine.hashes_done("cpu-0").await;
+        assert_eq!(next_share(&engine).await.job_id, "a");
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while engine.hashes_done("cpu-0").await <= hashes {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("no hashes after the settings change");
+        
+        let throttled = DeviceSettings { duty_cycle: 0.0, ..settings };
+        engine.set_device_settings("cpu-0", throttled).await.unwrap();
+        assert_eq!(engine.device_settings("cpu-0").await.duty_cycle, DeviceSettings::MIN_DUTY_CYCLE);
+        assert!(engine.set_device_settings("gpu-9", DeviceSettings::default()).await.is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! Hashrate measured from a device's hash counter.
+
+use std::time::{Duration, Instant};
+
+/// How often device hash counters are sampled.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Time constant of the smoothed hashrate's exponential average.
+pub const SMOOTHING: Duration = Duration::from_secs(60);
+
+/// A device's measured speed, in hashes per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Hashrate {
+    /// Over the last sample interval.
+    pub instant: f64,
+    /// Exponential average over roughly `SMOOTHING`.
+    pub smoothed: f64,
+}
+
+/// Turns successive readings of a hash counter into a `Hashrate`.
+#[derive(Debug, Clone, Default)]
+pub struct HashrateMeter {
+    last: Option<(u64, Instant)>,
+    rate: Option<Hashrate>,
+}
+
+impl HashrateMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the counter reading `hashes` taken at `now`. The first
+    /// reading only sets the baseline.
+    pub fn sample(&mut self, hashes: u64, now: Instant) -> Hashrate {
+        let previous = self.last.replace((hashes, now));
+        let Some((previous_hashes, previous_time)) = previous else {
+            return self.rate();
+        };
+        let elapsed = now.saturating_duration_since(previous_time).as_secs_f64();
+        if elapsed <= 0.0 {
+            return self.rate();
+        }
+
+        let instant = hashes.saturating_sub(previous_hashes) as f64 / elapsed;
+        let smoothed = match self.rate {
+            // Weighted by elapsed time, so irregular sampling still decays
+            // at the same rate.
+            Some(rate) => {
+                let weight = 1.0 - (-elapsed / SMOOTHING.as_secs_f64()).exp();
+                rate.smoothed + (instant - rate.smoothed) * weight
+            }
+            None => instant,
+        };
+        let rate = Hashrate { instant, smoothed };
+        self.rate = Some(rate);
+        rate
+    }
+
+    pub fn rate(&self) -> Hashrate {
+        self.rate.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_tracks_instant_and_smoothed_rates() {
+        let start = Instant::now();
+        let mut meter = HashrateMeter::new();
+        assert_eq!(meter.sample(500, start), Hashrate::default());
+
+        let rate = meter.sample(1500, start + Duration::from_secs(1));
+        assert_eq!(rate, Hashrate { instant: 1000.0, smoothed: 1000.0 });
+
+        // A sudden stop shows at once in the instant rate only.
+        let rate = meter.sample(1500, start + Duration::from_secs(2));
+        assert_eq!(rate.instant, 0.0);
+        assert!(rate.smoothed > 950.0 && rate.smoothed < 1000.0, "{:?}", rate);
+
+        // After many time constants the average catches up.
+        let rate = meter.sample(1500, start + Duration::from_secs(2) + SMOOTHING * 10);
+        assert!(rate.smoothed < 1.0, "{:?}", rate);
+        assert_eq!(meter.rate(), rate);
+    }
+
+    #[test]
+    fn test_irregular_sampling_decays_the_same() {
+        let start = Instant::now();
+        let mut fine = HashrateMeter::new();
+        let mut coarse = HashrateMeter::new();
+        for meter in [&mut fine, &mut coarse] {
+            meter.sample(0, start);
+            meter.sample(1000, start + Duration::from_secs(1));
+        }
+        for i in 2..=11 {
+            fine.sample(1000, start + Duration::from_secs(i));
+        }
+        coarse.sample(1000, start + Duration::from_secs(11));
+        assert!((fine.rate().smoothed - coarse.rate().smoothed).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,136 @@
+//! Block header assembly from Stratum V1 job data.
+
+use jxpoolminer_core::MiningJob;
+use sha2::{Sha256, Digest};
+
+pub const HEADER_SIZE: usize = 80;
+/// Offset of the 32-bit nonce within the header.
+pub const NONCE_OFFSET: usize = 76;
+
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    let first = Sha256::digest(data);
+    Sha256::digest(first).into()
+}
+
+/// `coinb1 || extranonce1 || extranonce2 || coinb2`
+pub fn build_coinbase(job: &MiningJob, extranonce2: &[u8]) -> Vec<u8> {
+    let mut coinbase = Vec::with_capacity(
+        job.coinbase1.len() + job.extranonce1.len() + extranonce2.len() + job.coinbase2.len(),
+    );
+    coinbase.extend_from_slice(&job.coinbase1);
+    coinbase.extend_from_slice(&job.extranonce1);
+    coinbase.extend_from_slice(extranonce2);
+    coinbase.extend_from_slice(&job.coinbase2);
+    coinbase
+}
+
+/// Folds the merkle branch into the coinbase hash. Branch hashes are in
+/// internal byte order, exactly as the pool sends them.
+pub fn merkle_root(coinbase_hash: [u8; 32], branches: &[Vec<u8>]) -> [u8; 32] {
+    let mut root = coinbase_hash;
+    let mut concat = [0u8; 64];
+    for branch in branches {
+        concat[..32].copy_from_slice(&root);
+        concat[32..].copy_from_slice(branch);
+        root = sha256d(&concat);
+    }
+    root
+}
+
+/// Serializes the header: version, prevhash, merkle root, ntime, nbits and
+/// nonce, with the integer fields little-endian.
+pub fn build_header(job: &MiningJob, merkle_root: &[u8; 32], nonce: u32) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[0..4].copy_from_slice(&job.version.to_le_bytes());
+    header[4..36].copy_from_slice(&job.prev_hash);
+    header[36..68].copy_from_slice(merkle_root);
+    header[68..72].copy_from_slice(&job.ntime.to_le_bytes());
+    header[72..76].copy_from_slice(&job.nbits.to_le_bytes());
+    header[NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+    header
+}
+
+/// Builds the header for `extranonce2` with a zero nonce.
+pub fn header_for(job: &MiningJob, extranonce2: &[u8]) -> [u8; HEADER_SIZE] {
+    let coinbase = build_coinbase(job, extranonce2);
+    let root = merkle_root(sha256d(&coinbase), &job.merkle_branches);
+    build_header(job, &root, 0)
+}
+
+/// Fills `job.header` with the 76-byte header prefix for a job that came in
+/// as coinbase parts. Jobs that already carry a header are left untouched.
+pub fn prepare_job(job: &mut MiningJob, extranonce2: &[u8]) {
+    if job.header.is_empty() && job.has_coinbase() {
+        job.header = header_for(job, extranonce2)[..NONCE_OFFSET].to_vec();
+    }
+}
+
+/// Block hash in the usual display order (byte-reversed).
+pub fn display_hash(hash: &[u8; 32]) -> String {
+    let mut reversed = *hash;
+    reversed.reverse();
+    hex::encode(reversed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jxpoolminer_core::Algorithm;
+
+    fn decode(s: &str) -> Vec<u8> {
+        hex::decode(s).unwrap()
+    }
+
+    /// The genesis block expressed as a `mining.notify`, with "The " and
+    /// "Time" of the coinbase message standing in for the two extranonces.
+    fn genesis_job() -> MiningJob {
+        let mut job = MiningJob::new("genesis".to_string(), Algorithm::SHA256, 1.0);
+        job.coinbase1 = decode("01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d010445");
+        job.extranonce1 = decode("54686520");
+        job.extranonce2_size = 4;
+        job.coinbase2 = decode("732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000");
+        job.version = 1;
+        job.nbits = 0x1d00ffff;
+        job.ntime = 0x495fab29;
+        job
+    }
+
+    #[test]
+    fn test_genesis_block() {
+        let job = genesis_job();
+        let coinbase = build_coinbase(&job, &decode("54696d65"));
+        let root = merkle_root(sha256d(&coinbase), &job.merkle_branches);
+        assert_eq!(display_hash(&root), "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        
+        let header = build_header(&job, &root, 2083236893);
+        assert_eq!(
+            display_hash(&sha256d(&header)),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+    }
+
+    #[test]
+    fn test_merkle_branch_block_170() {
+        let mut coinbase_txid: [u8; 32] = decode("b1fea52486ce0c62bb442b530a3f0132b826c74e473d1f2c220bfa78111c5082").try_into().unwrap();
+        coinbase_txid.reverse();
+        let mut branch = decode("f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16");
+        branch.reverse();
+        
+        let root = merkle_root(coinbase_txid, &[branch]);
+        assert_eq!(display_hash(&root), "7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff");
+    }
+
+    #[test]
+    fn test_prepare_job_leaves_nonce_to_miner() {
+        let mut job = genesis_job();
+        prepare_job(&mut job, &decode("54696d65"));
+        assert_eq!(job.header.len(), NONCE_OFFSET);
+        
+        let mut header = job.header.clone();
+        header.extend_from_slice(&2083236893u32.to_le_bytes());
+        assert_eq!(
+            display_hash(&sha256d(&header)),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+    }
+}
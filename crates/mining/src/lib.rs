@@ -1,4 +1,16 @@
 pub mod engine;
+pub mod coordinator;
 pub mod algorithms;
+pub mod cpu;
+pub mod header;
+pub mod work;
+pub mod hashrate;
+pub mod verify;
+pub mod benchmark;
+pub mod thermal;
 
 pub use engine::Engine;
+pub use coordinator::Coordinator;
+pub use hashrate::Hashrate;
+pub use benchmark::{Benchmark, PowerReader};
+pub use thermal::ThermalController;
@@ -0,0 +1,266 @@
+//! Keeps devices within their temperature limits. Telemetry readings go
+//! through a `ThermalGuard` per device, whose decisions are applied to the
+//! engine: throttling above the target temperature, a halt at the critical
+//! one, and recovery once the device has cooled past the hysteresis.
+
+use crate::Engine;
+use jxpoolminer_core::ThermalLimits;
+use jxpoolminer_devices::Reading;
+use jxpoolminer_stats::Collector;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// How much of the duty cycle each reading above target takes away, and
+/// each reading below the resume temperature gives back.
+const THROTTLE_STEP: f64 = 0.1;
+/// Lowest throttle short of halting.
+const MIN_THROTTLE: f64 = 0.1;
+
+/// Status the engine reports for a device halted by the guard.
+pub const OVERHEAT: &str = "overheat";
+
+/// What a guard wants done with its device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThermalAction {
+    /// Mine at this fraction of the configured duty cycle.
+    Throttle(f64),
+    Halt,
+    Resume,
+}
+
+/// Thermal state machine of one device.
+#[derive(Debug, Clone)]
+pub struct ThermalGuard {
+    throttle: f64,
+    halted: bool,
+}
+
+impl Default for ThermalGuard {
+    fn default() -> Self {
+        Self { throttle: 1.0, halted: false }
+    }
+}
+
+impl ThermalGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn throttle(&self) -> f64 {
+        self.throttle
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Takes the device's latest temperature and returns what, if
+    /// anything, should change.
+    pub fn update(&mut self, limits: &ThermalLimits, temperature: f32) -> Option<ThermalAction> {
+        if temperature >= limits.critical {
+            if self.halted {
+                return None;
+            }
+            self.halted = true;
+            // Restarts gently rather than straight back at full speed.
+            self.throttle = MIN_THROTTLE;
+            return Some(ThermalAction::Halt);
+        }
+
+        let cool = temperature <= limits.resume_below();
+        if self.halted {
+            if !cool {
+                return None;
+            }
+            self.halted = false;
+            return Some(ThermalAction::Resume);
+        }
+
+        let throttle = if temperature > limits.target {
+            (self.throttle - THROTTLE_STEP).max(MIN_THROTTLE)
+        } else if cool {
+            (self.throttle + THROTTLE_STEP).min(1.0)
+        } else {
+            self.throttle
+        };
+        // Rounded so repeated steps land exactly on 1.0 again.
+        let throttle = (throttle * 100.0).round() / 100.0;
+        if throttle == self.throttle {
+            return None;
+        }
+        self.throttle = throttle;
+        Some(ThermalAction::Throttle(throttle))
+    }
+}
+
+/// Applies every device's guard to the engine, logging each change and
+/// recording it as a collector event.
+pub struct ThermalController {
+    engine: Arc<Engine>,
+    collector: Arc<Collector>,
+    guards: HashMap<String, ThermalGuard>,
+}
+
+impl ThermalController {
+    pub fn new(engine: Arc<Engine>, collector: Arc<Collector>) -> Self {
+        Self {
+            engine,
+            collector,
+            guards: HashMap::new(),
+        }
+    }
+
+    /// Follows `readings`, typically `Telemetry::subscribe`, until they
+    /// end or the task is aborted.
+    pub fn spawn(mut self, mut readings: watch::Receiver<HashMap<String, Reading>>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while readings.changed().await.is_ok() {
+                let temperatures: Vec<_> = readings.borrow_and_update().iter()
+                    .filter_map(|(device_id, reading)| Some((device_id.clone(), reading.temperature?)))
+                    .collect();
+                for (device_id, temperature) in temperatures {
+                    self.update(&device_id, temperature).await;
+                }
+            }
+        })
+    }
+
+    /// Feeds one temperature of a device through its guard, with the
+    /// limits currently configured for it.
+    pub async fn update(&mut self, device_id: &str, temperature: f32) {
+        let limits = self.engine.device_settings(device_id).await.thermal;
+        let guard = self.guards.entry(device_id.to_string()).or_default();
+        let Some(action) = guard.update(&limits, temperature) else {
+            return;
+        };
+
+        let (result, message) = match action {
+            ThermalAction::Throttle(factor) => {
+                tracing::warn!("{} at {:.1}°C, mining at {:.0}% speed", device_id, temperature, factor * 100.0);
+                (
+                    self.engine.set_throttle(device_id, factor).await,
+                    format!("Throttled to {:.0}% at {:.1}°C (target {:.0}°C)", factor * 100.0, temperature, limits.target),
+                )
+            }
+            ThermalAction::Halt => {
+                tracing::error!("{} reached {:.1}°C, critical limit {:.0}°C; stopping it", device_id, temperature, limits.critical);
+                // Resumes at the guard's reduced speed.
+                let _ = self.engine.set_throttle(device_id, guard.throttle()).await;
+                (
+                    self.engine.halt(device_id, OVERHEAT).await,
+                    format!("Stopped at {:.1}°C (critical {:.0}°C)", temperature, limits.critical),
+                )
+            }
+            ThermalAction::Resume => {
+                tracing::info!("{} cooled to {:.1}°C; resuming", device_id, temperature);
+                (
+                    self.engine.resume(device_id).await,
+                    format!("Resumed at {:.1}°C", temperature),
+                )
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("Thermal protection of {} failed: {}", device_id, e);
+        }
+        self.collector.record_event(device_id, message).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jxpoolminer_core::{Algorithm, Device, DeviceStatus, DeviceType, MiningJob};
+
+    fn limits() -> ThermalLimits {
+        ThermalLimits { target: 80.0, critical: 90.0, hysteresis: 5.0 }
+    }
+
+    #[test]
+    fn test_guard_follows_temperature_sequence() {
+        let mut guard = ThermalGuard::new();
+        let actions: Vec<_> = [70.0, 81.0, 82.0, 78.0, 75.0, 74.0, 74.0, 74.0]
+            .into_iter()
+            .map(|t| guard.update(&limits(), t))
+            .collect();
+        assert_eq!(actions, vec![
+            None,
+            Some(ThermalAction::Throttle(0.9)),
+            Some(ThermalAction::Throttle(0.8)),
+            // Between resume temperature and target the speed holds.
+            None,
+            Some(ThermalAction::Throttle(0.9)),
+            Some(ThermalAction::Throttle(1.0)),
+            None,
+            None,
+        ]);
+    }
+
+    #[test]
+    fn test_guard_halts_at_critical_with_hysteresis() {
+        let mut guard = ThermalGuard::new();
+        let actions: Vec<_> = [85.0, 91.0, 95.0, 85.0, 78.0, 75.0, 70.0]
+            .into_iter()
+            .map(|t| guard.update(&limits(), t))
+            .collect();
+        assert_eq!(actions, vec![
+            Some(ThermalAction::Throttle(0.9)),
+            Some(ThermalAction::Halt),
+            None,
+            None,
+            None,
+            Some(ThermalAction::Resume),
+            Some(ThermalAction::Throttle(0.2)),
+        ]);
+        assert!(!guard.is_halted());
+
+        // Never throttles below the minimum while running.
+        for _ in 0..20 {
+            guard.update(&limits(), 85.0);
+        }
+        assert_eq!(guard.throttle(), MIN_THROTTLE);
+    }
+
+    #[tokio::test]
+    async fn test_controller_throttles_halts_and_resumes_engine() {
+        let device = Device::new("cpu-0".to_string(), "Test CPU".to_string(), DeviceType::CPU { cores: 1 });
+        let engine = Arc::new(Engine::new(vec![device]).unwrap());
+        let collector = Arc::new(Collector::new());
+        let mut controller = ThermalController::new(engine.clone(), collector.clone());
+        let mut job = MiningJob::new("a".to_string(), Algorithm::SHA256, 1.0);
+        job.header = vec![0; 76];
+        job.target = vec![0; 32];
+        engine.start_mining("cpu-0", job.clone()).await.unwrap();
+
+        // Default limits: target 85°C, critical 95°C, resume at 80°C.
+        controller.update("cpu-0", 88.0).await;
+        assert_eq!(engine.throttle("cpu-0").await, 0.9);
+        // The configured settings are left alone.
+        assert_eq!(engine.device_settings("cpu-0").await.duty_cycle, 1.0);
+
+        controller.update("cpu-0", 96.0).await;
+        assert!(engine.is_halted("cpu-0").await);
+        assert_eq!(engine.current_job("cpu-0").await, None);
+        assert_eq!(engine.devices().await[0].status, DeviceStatus::Error(OVERHEAT.to_string()));
+
+        // A new job while halted does not restart the device.
+        let mut next = job.clone();
+        next.id = "b".to_string();
+        engine.start_mining("cpu-0", next).await.unwrap();
+        assert_eq!(engine.current_job("cpu-0").await, None);
+
+        controller.update("cpu-0", 82.0).await;
+        assert!(engine.is_halted("cpu-0").await);
+        controller.update("cpu-0", 70.0).await;
+        assert!(!engine.is_halted("cpu-0").await);
+        assert_eq!(engine.current_job("cpu-0").await.as_deref(), Some("b"));
+        assert_eq!(engine.devices().await[0].status, DeviceStatus::Mining);
+        assert_eq!(engine.throttle("cpu-0").await, MIN_THROTTLE);
+
+        let events = collector.events().await;
+        assert_eq!(events.len(), 3);
+        assert!(events[1].message.starts_with("Stopped at 96.0°C"), "{:?}", events[1]);
+        engine.stop_mining("cpu-0").await.unwrap();
+    }
+}
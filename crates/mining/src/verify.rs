@@ -0,0 +1,173 @@
+//! Re-checks found shares before they reach the pool. Every share is
+//! hashed again from its job with the reference implementation, so a
+//! miscompiled SIMD kernel or a failing ASIC gets its shares discarded and
+//! counted as hardware errors instead of getting the worker banned.
+
+use crate::algorithms::{self, MiningAlgorithm};
+use crate::header;
+use jxpoolminer_core::{MiningJob, Share};
+use anyhow::Result;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Outcome of checking one share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Valid,
+    /// Already seen for the same job, extranonce2 and nonce.
+    Duplicate,
+    /// Meets the job target but not a session difficulty raised since.
+    BelowSessionDifficulty,
+    /// The share does not hold up against the reference hash.
+    HardwareError(String),
+}
+
+struct JobState {
+    algorithm: Arc<dyn MiningAlgorithm>,
+    /// `(extranonce2, nonce)` of every valid share.
+    seen: HashSet<(Vec<u8>, u64)>,
+}
+
+/// Share checks for one device, with per-job state kept until `retain`
+/// drops the job.
+#[derive(Default)]
+pub struct ShareVerifier {
+    jobs: HashMap<String, JobState>,
+}
+
+impl ShareVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks `share` against `job`, the job it was found for. A share that
+    /// is valid is remembered, so the same one comes back as a duplicate.
+    /// `session_difficulty` is the pool's current difficulty, if known.
+    pub fn check(&mut self, job: &MiningJob, share: &Share, session_difficulty: Option<f64>) -> Result<Verdict> {
+        let state = match self.jobs.entry(job.id.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(JobState {
+                algorithm: algorithms::registry().for_job(job)?,
+                seen: HashSet::new(),
+            }),
+        };
+
+        if !state.algorithm.wide_nonce() && u32::try_from(share.nonce).is_err() {
+            return Ok(Verdict::HardwareError(format!("nonce {:#x} does not fit the header", share.nonce)));
+        }
+        let header = header_prefix(job, share);
+        let (hash, mix_digest) = state.algorithm.reference_hash(&header, share.nonce);
+        if share.hash != hash {
+            return Ok(Verdict::HardwareError(format!(
+                "hash {} differs from reference {}", hex::encode(&share.hash), hex::encode(hash),
+            )));
+        }
+        if share.mix_digest.as_deref() != mix_digest.as_ref().map(|digest| &digest[..]) {
+            return Ok(Verdict::HardwareError("mix digest differs from reference".to_string()));
+        }
+        if !state.algorithm.meets_target(&hash, &job.target) {
+            return Ok(Verdict::HardwareError("hash does not meet the job target".to_string()));
+        }
+        if let Some(difficulty) = session_difficulty.filter(|&difficulty| difficulty > job.difficulty) {
+            let target = state.algorithm.target_for_difficulty(difficulty);
+            if !state.algorithm.meets_target(&hash, &target) {
+                return Ok(Verdict::BelowSessionDifficulty);
+            }
+        }
+
+        if !state.seen.insert((share.extranonce2.clone(), share.nonce)) {
+            return Ok(Verdict::Duplicate);
+        }
+        Ok(Verdict::Valid)
+    }
+
+    /// Forgets every job `live` rejects.
+    pub fn retain(&mut self, mut live: impl FnMut(&str) -> bool) {
+        self.jobs.retain(|id, _| live(id));
+    }
+}
+
+/// The 76-byte header prefix `share` was hashed over: the job's own
+/// header, or one rebuilt from the share's extranonce2 and ntime.
+fn header_prefix(job: &MiningJob, share: &Share) -> Vec<u8> {
+    if !job.has_coinbase() {
+        return job.header.clone();
+    }
+    let mut job = job.clone();
+    job.ntime = share.ntime;
+    header::header_for(&job, &share.extranonce2)[..header::NONCE_OFFSET].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::scan;
+    use crate::work::{Extranonce2Range, WorkCursor};
+    use jxpoolminer_core::Algorithm;
+
+    fn mined(job: &MiningJob) -> Share {
+        let algorithm = algorithms::registry().for_job(job).unwrap();
+        let mut work = WorkCursor::new(job.clone(), Extranonce2Range::full(job.extranonce2_size));
+        scan(algorithm.as_ref(), &mut work, "cpu-0", 1 << 22).unwrap()
+    }
+
+    fn coinbase_job() -> MiningJob {
+        let mut job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0 / 65536.0);
+        job.coinbase1 = vec![0x01; 42];
+        job.extranonce1 = vec![0xaa, 0xbb];
+        job.extranonce2_size = 4;
+        job.coinbase2 = vec![0x02; 30];
+        job.ntime = 0x6500_0000;
+        job.target = algorithms::registry().resolve(&job.algorithm).unwrap().target_for_difficulty(job.difficulty).to_vec();
+        job
+    }
+
+    #[test]
+    fn test_valid_share_then_duplicate() {
+        let job = coinbase_job();
+        let share = mined(&job);
+        let mut verifier = ShareVerifier::new();
+        assert_eq!(verifier.check(&job, &share, None).unwrap(), Verdict::Valid);
+        assert_eq!(verifier.check(&job, &share, None).unwrap(), Verdict::Duplicate);
+
+        // A different extranonce2 is a different share (and here a bad one).
+        let mut other = share.clone();
+        other.extranonce2 = vec![0, 0, 0, 1];
+        assert!(matches!(verifier.check(&job, &other, None).unwrap(), Verdict::HardwareError(_)));
+
+        verifier.retain(|_| false);
+        assert_eq!(verifier.check(&job, &share, None).unwrap(), Verdict::Valid);
+    }
+
+    #[test]
+    fn test_corrupt_shares_are_hardware_errors() {
+        let job = coinbase_job();
+        let share = mined(&job);
+        let mut verifier = ShareVerifier::new();
+
+        let mut wrong_hash = share.clone();
+        wrong_hash.hash[0] ^= 1;
+        assert!(matches!(verifier.check(&job, &wrong_hash, None).unwrap(), Verdict::HardwareError(_)));
+
+        // A kernel reporting a hash for the wrong nonce.
+        let mut wrong_nonce = share.clone();
+        wrong_nonce.nonce += 1;
+        assert!(matches!(verifier.check(&job, &wrong_nonce, None).unwrap(), Verdict::HardwareError(_)));
+
+        let mut harder = job.clone();
+        harder.target = vec![0; 32];
+        assert!(matches!(verifier.check(&harder, &share, None).unwrap(), Verdict::HardwareError(_)));
+        assert_eq!(verifier.check(&job, &share, None).unwrap(), Verdict::Valid);
+    }
+
+    #[test]
+    fn test_session_difficulty() {
+        let job = coinbase_job();
+        let share = mined(&job);
+        let mut verifier = ShareVerifier::new();
+        assert_eq!(verifier.check(&job, &share, Some(1e12)).unwrap(), Verdict::BelowSessionDifficulty);
+        // A lower session difficulty never rejects what the job accepts.
+        assert_eq!(verifier.check(&job, &share, Some(1e-9)).unwrap(), Verdict::Valid);
+    }
+}
@@ -0,0 +1,343 @@
+//! Per-device work state: extranonce2 partitioning and the nonce cursor
+//! that rolls extranonce2 once the 32-bit nonce space is used up.
+
+use crate::header;
+use jxpoolminer_core::{MiningJob, Share};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Half-open range of extranonce2 counter values owned by one worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extranonce2Range {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Extranonce2Range {
+    /// The whole counter space for an extranonce2 of `size` bytes. Only the
+    /// low eight bytes are rolled for larger sizes.
+    pub fn full(size: usize) -> Self {
+        let end = if size >= 8 { u64::MAX } else { 1u64 << (size * 8) };
+        Self { start: 0, end }
+    }
+    
+    pub fn len(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Splits the range into `parts` disjoint slices and returns slice
+    /// `index`. The last slice absorbs the remainder. When there are more
+    /// parts than values the whole range is shared instead.
+    pub fn split(&self, parts: usize, index: usize) -> Self {
+        match split_span(self.start, self.end, parts, index) {
+            Some((start, end)) => Self { start, end },
+            None => {
+                tracing::warn!("Extranonce2 space too small for {} workers; ranges will overlap", parts);
+                *self
+            }
+        }
+    }
+}
+
+/// Half-open range of 32-bit header nonces owned by one worker thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceRange {
+    pub start: u32,
+    /// Exclusive, so the full range ends at 2^32.
+    pub end: u64,
+}
+
+impl NonceRange {
+    pub fn full() -> Self {
+        Self { start: 0, end: 1 << 32 }
+    }
+    
+    pub fn len(&self) -> u64 {
+        self.end.saturating_sub(self.start as u64)
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Same slicing as `Extranonce2Range::split`.
+    pub fn split(&self, parts: usize, index: usize) -> Self {
+        match split_span(self.start as u64, self.end, parts, index) {
+            Some((start, end)) => Self { start: start as u32, end },
+            None => *self,
+        }
+    }
+}
+
+fn split_span(start: u64, end: u64, parts: usize, index: usize) -> Option<(u64, u64)> {
+    let parts = parts.max(1) as u64;
+    let index = (index as u64).min(parts - 1);
+    let span = end.saturating_sub(start) / parts;
+    if span == 0 {
+        return None;
+    }
+    
+    let slice_start = start + span * index;
+    let slice_end = if index == parts - 1 { end } else { slice_start + span };
+    Some((slice_start, slice_end))
+}
+
+/// Encodes an extranonce2 counter as `size` big-endian bytes.
+pub fn encode_extranonce2(value: u64, size: usize) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    if size >= 8 {
+        let mut out = vec![0u8; size - 8];
+        out.extend_from_slice(&bytes);
+        out
+    } else {
+        bytes[8 - size..].to_vec()
+    }
+}
+
+/// Walks the (extranonce2, nonce) space of one job for one worker.
+#[derive(Debug, Clone)]
+pub struct WorkCursor {
+    job: MiningJob,
+    range: Extranonce2Range,
+    extranonce2: u64,
+    nonces: NonceRange,
+    next_nonce: u32,
+    exhausted_nonces: bool,
+    hashes: Arc<AtomicU64>,
+}
+
+impl WorkCursor {
+    pub fn new(job: MiningJob, range: Extranonce2Range) -> Self {
+        let mut cursor = Self {
+            job,
+            range,
+            extranonce2: range.start,
+            nonces: NonceRange::full(),
+            next_nonce: 0,
+            exhausted_nonces: false,
+            hashes: Arc::new(AtomicU64::new(0)),
+        };
+        cursor.rebuild_header();
+        cursor
+    }
+    
+    /// Restricts the cursor to a slice of the nonce space, so several
+    /// threads can share one extranonce2 without overlapping.
+    pub fn with_nonce_range(mut self, nonces: NonceRange) -> Self {
+        self.nonces = nonces;
+        self.next_nonce = nonces.start;
+        self
+    }
+    
+    /// Counts every nonce handed out into `counter`, which may be shared
+    /// with earlier cursors so totals survive job switches.
+    pub fn with_hash_counter(mut self, counter: Arc<AtomicU64>) -> Self {
+        self.hashes = counter;
+        self
+    }
+    
+    /// Nonces handed out so far, as counted by the hash counter.
+    pub fn hashes(&self) -> u64 {
+        self.hashes.load(Ordering::Relaxed)
+    }
+    
+    /// The job whose `header` holds the 76-byte prefix for the current
+    /// extranonce2.
+    pub fn job(&self) -> &MiningJob {
+        &self.job
+    }
+    
+    pub fn extranonce2(&self) -> Vec<u8> {
+        encode_extranonce2(self.extranonce2, self.job.extranonce2_size)
+    }
+    
+    /// Returns the next nonce to try. After the last nonce of the range the
+    /// extranonce2 is rolled (wrapping within its range) and the header
+    /// rebuilt before counting restarts at the start of the nonce range.
+    pub fn next_nonce(&mut self) -> u32 {
+        let (nonce, _) = self.nonce_run(1);
+        self.advance(1);
+        nonce
+    }
+    
+    /// The next up to `max` consecutive nonces that share the current
+    /// header, as a first nonce and a count. Nothing is consumed until
+    /// `advance`.
+    pub fn nonce_run(&mut self, max: usize) -> (u32, usize) {
+        if self.exhausted_nonces {
+            self.roll_extranonce2();
+        }
+        let left = self.nonces.end - self.next_nonce as u64;
+        (self.next_nonce, left.min(max.max(1) as u64) as usize)
+    }
+    
+    /// Consumes `count` nonces of the run `nonce_run` returned.
+    pub fn advance(&mut self, count: usize) {
+        self.hashes.fetch_add(count as u64, Ordering::Relaxed);
+        let next = self.next_nonce as u64 + count as u64;
+        if next >= self.nonces.end {
+            self.exhausted_nonces = true;
+        } else {
+            self.next_nonce = next as u32;
+        }
+    }
+    
+    /// `nonce` under a 64-bit nonce whose high half is extranonce1 followed
+    /// by the current extranonce2, for algorithms such as Ethash that have
+    /// no coinbase to roll.
+    pub fn wide_nonce(&self, nonce: u32) -> u64 {
+        let high = self.job.extranonce1.iter()
+            .chain(&self.extranonce2())
+            .fold(0u64, |high, &byte| (high << 8) | byte as u64);
+        (high << 32) | nonce as u64
+    }
+    
+    /// Share for `nonce` under the current extranonce2.
+    pub fn share(&self, nonce: u64, hash: Vec<u8>, device_id: &str) -> Share {
+        Share {
+            job_id: self.job.id.clone(),
+            nonce,
+            hash,
+            device_id: device_id.to_string(),
+            difficulty: self.job.difficulty,
+            extranonce2: self.extranonce2(),
+            ntime: self.job.ntime,
+            mix_digest: None,
+            header_hash: None,
+            timestamp: chrono::Utc::now(),
+        }
+    }
+    
+    fn roll_extranonce2(&mut self) {
+        self.extranonce2 += 1;
+        if self.extranonce2 >= self.range.end {
+            tracing::warn!("Extranonce2 range exhausted for job {}; wrapping", self.job.id);
+            self.extranonce2 = self.range.start;
+        }
+        self.next_nonce = self.nonces.start;
+        self.exhausted_nonces = false;
+        self.rebuild_header();
+    }
+    
+    fn rebuild_header(&mut self) {
+        if self.job.has_coinbase() {
+            let extranonce2 = self.extranonce2();
+            self.job.header = header::header_for(&self.job, &extranonce2)[..header::NONCE_OFFSET].to_vec();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jxpoolminer_core::Algorithm;
+
+    #[test]
+    fn test_ranges_are_disjoint_and_cover_the_space() {
+        let full = Extranonce2Range::full(2);
+        let parts: Vec<_> = (0..3).map(|i| full.split(3, i)).collect();
+        
+        assert_eq!(parts[0], Extranonce2Range { start: 0, end: 21845 });
+        assert_eq!(parts[1].start, parts[0].end);
+        assert_eq!(parts[2].start, parts[1].end);
+        assert_eq!(parts[2].end, 65536);
+        
+        let nested = parts[1].split(2, 1);
+        assert!(nested.start >= parts[1].start && nested.end == parts[1].end);
+    }
+
+    #[test]
+    fn test_extranonce2_encoding() {
+        assert_eq!(encode_extranonce2(0x0102, 4), vec![0, 0, 1, 2]);
+        assert_eq!(encode_extranonce2(1, 10), vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(Extranonce2Range::full(8).end, u64::MAX);
+    }
+
+    #[test]
+    fn test_rolls_extranonce2_when_nonces_run_out() {
+        let mut job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0);
+        job.coinbase1 = vec![0x01];
+        job.coinbase2 = vec![0x02];
+        job.extranonce2_size = 4;
+        let range = Extranonce2Range::full(4).split(4, 2);
+        
+        let mut cursor = WorkCursor::new(job, range);
+        assert_eq!(cursor.extranonce2(), encode_extranonce2(range.start, 4));
+        let first_header = cursor.job().header.clone();
+        
+        cursor.next_nonce = u32::MAX;
+        assert_eq!(cursor.next_nonce(), u32::MAX);
+        assert_eq!(cursor.next_nonce(), 0);
+        assert_eq!(cursor.extranonce2(), encode_extranonce2(range.start + 1, 4));
+        assert_ne!(cursor.job().header, first_header);
+    }
+
+    #[test]
+    fn test_nonce_ranges_roll_extranonce2_independently() {
+        let mut job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0);
+        job.coinbase1 = vec![0x01];
+        job.extranonce2_size = 4;
+        let nonces = NonceRange::full().split(4, 1);
+        assert_eq!(nonces, NonceRange { start: 1 << 30, end: 2 << 30 });
+        assert_eq!(NonceRange::full().split(4, 3).end, 1 << 32);
+        
+        let mut cursor = WorkCursor::new(job, Extranonce2Range::full(4)).with_nonce_range(nonces);
+        assert_eq!(cursor.next_nonce(), 1 << 30);
+        cursor.next_nonce = (2u32 << 30) - 1;
+        assert_eq!(cursor.next_nonce(), (2u32 << 30) - 1);
+        assert_eq!(cursor.next_nonce(), 1 << 30);
+        assert_eq!(cursor.extranonce2(), encode_extranonce2(1, 4));
+    }
+    
+    #[test]
+    fn test_nonce_runs_stop_at_the_header_roll() {
+        let mut job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0);
+        job.coinbase1 = vec![0x01];
+        job.extranonce2_size = 4;
+        let mut cursor = WorkCursor::new(job, Extranonce2Range::full(4)).with_nonce_range(NonceRange { start: 0, end: 300 });
+        
+        assert_eq!(cursor.nonce_run(256), (0, 256));
+        assert_eq!(cursor.nonce_run(256), (0, 256), "runs are not consumed until advanced");
+        cursor.advance(256);
+        let header = cursor.job().header.clone();
+        assert_eq!(cursor.nonce_run(256), (256, 44));
+        cursor.advance(44);
+        assert_eq!(cursor.job().header, header);
+        
+        assert_eq!(cursor.nonce_run(256), (0, 256));
+        assert_ne!(cursor.job().header, header);
+        assert_eq!(cursor.extranonce2(), encode_extranonce2(1, 4));
+        assert_eq!(cursor.hashes(), 300);
+    }
+
+    #[test]
+    fn test_wide_nonces_carry_the_extranonce() {
+        let mut job = MiningJob::new("1".to_string(), Algorithm::ETHASH, 1.0);
+        job.extranonce1 = vec![0x08, 0x0c];
+        job.extranonce2_size = 2;
+        let mut cursor = WorkCursor::new(job, Extranonce2Range { start: 0x0102, end: 0x0200 });
+        
+        assert_eq!(cursor.wide_nonce(7), 0x080c_0102_0000_0007);
+        cursor.next_nonce = u32::MAX;
+        cursor.next_nonce();
+        let nonce = cursor.next_nonce();
+        assert_eq!(cursor.wide_nonce(nonce), 0x080c_0103_0000_0000);
+    }
+
+    #[test]
+    fn test_hash_counter_is_shared() {
+        let job = MiningJob::new("1".to_string(), Algorithm::SHA256, 1.0);
+        let counter = Arc::new(AtomicU64::new(0));
+        
+        let mut first = WorkCursor::new(job.clone(), Extranonce2Range::full(4)).with_hash_counter(counter.clone());
+        (0..10).for_each(|_| { first.next_nonce(); });
+        let mut second = WorkCursor::new(job, Extranonce2Range::full(4)).with_hash_counter(counter.clone());
+        (0..5).for_each(|_| { second.next_nonce(); });
+        
+        assert_eq!(counter.load(Ordering::Relaxed), 15);
+    }
+}
@@ -0,0 +1,447 @@
+//! Scriptable Stratum V1 pool on localhost for tests and offline
+//! development. Shares are checked against the real header hash and every
+//! outcome is counted so tests can assert end-to-end behavior.
+
+pub mod script;
+pub mod shares;
+
+pub use script::{Action, MockPoolConfig};
+pub use shares::RejectReason;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use shares::IssuedJob;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// Snapshot of what the pool has seen so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub connections: u64,
+    pub subscribes: u64,
+    pub authorizes: u64,
+    pub notifies: u64,
+    pub submits: u64,
+    pub accepted: u64,
+    /// All rejections, whatever the reason.
+    pub rejected: u64,
+    pub stale: u64,
+    pub duplicates: u64,
+    pub low_difficulty: u64,
+    pub injected_rejects: u64,
+    /// Request lines that were not valid JSON-RPC.
+    pub malformed_requests: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    connections: AtomicU64,
+    subscribes: AtomicU64,
+    authorizes: AtomicU64,
+    notifies: AtomicU64,
+    submits: AtomicU64,
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    stale: AtomicU64,
+    duplicates: AtomicU64,
+    low_difficulty: AtomicU64,
+    injected_rejects: AtomicU64,
+    malformed_requests: AtomicU64,
+}
+
+impl Counters {
+    fn bump(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Stats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Stats {
+            connections: load(&self.connections),
+            subscribes: load(&self.subscribes),
+            authorizes: load(&self.authorizes),
+            notifies: load(&self.notifies),
+            submits: load(&self.submits),
+            accepted: load(&self.accepted),
+            rejected: load(&self.rejected),
+            stale: load(&self.stale),
+            duplicates: load(&self.duplicates),
+            low_difficulty: load(&self.low_difficulty),
+            injected_rejects: load(&self.injected_rejects),
+            malformed_requests: load(&self.malformed_requests),
+        }
+    }
+}
+
+struct Shared {
+    config: MockPoolConfig,
+    counters: Counters,
+    next_job: AtomicU64,
+    next_connection: AtomicU32,
+    reject_next: AtomicUsize,
+    commands: broadcast::Sender<Action>,
+    connections: Mutex<Vec<JoinHandle<()>>>,
+}
+
+pub struct MockPool {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    accept_task: JoinHandle<()>,
+}
+
+impl MockPool {
+    pub async fn start(config: MockPoolConfig) -> Result<Self> {
+        let listener = TcpListener::bind(&config.bind).await
+            .with_context(|| format!("Failed to bind mock pool to {}", config.bind))?;
+        let addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            config,
+            counters: Counters::default(),
+            next_job: AtomicU64::new(1),
+            next_connection: AtomicU32::new(1),
+            reject_next: AtomicUsize::new(0),
+            commands: broadcast::channel(64).0,
+            connections: Mutex::new(Vec::new()),
+        });
+
+        let accept_shared = shared.clone();
+        let accept_task = tokio::spawn(async move {
+            while let Ok((socket, peer)) = listener.accept().await {
+                tracing::debug!("Mock pool accepted {}", peer);
+                Counters::bump(&accept_shared.counters.connections);
+                let handle = tokio::spawn(Connection::serve(socket, accept_shared.clone()));
+                let mut connections = accept_shared.connections.lock().unwrap();
+                connections.retain(|handle| !handle.is_finished());
+                connections.push(handle);
+            }
+        });
+
+        tracing::info!("Mock pool listening on {}", addr);
+        Ok(Self { addr, shared, accept_task })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("stratum+tcp://{}", self.addr)
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.shared.counters.snapshot()
+    }
+
+    /// Performs `action` on every open connection.
+    pub fn broadcast(&self, action: Action) {
+        let _ = self.shared.commands.send(action);
+    }
+
+    /// Rejects the next `count` submits regardless of their validity.
+    pub fn reject_next(&self, count: usize) {
+        self.shared.reject_next.store(count, Ordering::SeqCst);
+    }
+
+    /// Polls the counters until `condition` holds.
+    pub async fn wait_until<F: Fn(&Stats) -> bool>(&self, timeout: Duration, condition: F) -> Result<Stats> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let stats = self.stats();
+                if condition(&stats) {
+                    return stats;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .with_context(|| format!("Mock pool condition not met within {:?}: {:?}", timeout, self.stats()))
+    }
+
+    /// Stops listening and drops every connection.
+    pub fn shutdown(&self) {
+        self.accept_task.abort();
+        for handle in self.shared.connections.lock().unwrap().drain(..) {
+            handle.abort();
+        }
+    }
+}
+
+impl Drop for MockPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+struct Connection {
+    shared: Arc<Shared>,
+    writer: OwnedWriteHalf,
+    extranonce1: Vec<u8>,
+    difficulty: f64,
+    authorized: bool,
+    jobs: HashMap<String, IssuedJob>,
+    submitted: HashSet<(String, String, String, String)>,
+    actions_tx: mpsc::UnboundedSender<Action>,
+    script: Option<JoinHandle<()>>,
+}
+
+impl Connection {
+    async fn serve(socket: TcpStream, shared: Arc<Shared>) {
+        let id = shared.next_connection.fetch_add(1, Ordering::Relaxed);
+        let (reader, writer) = socket.into_split();
+        let (actions_tx, mut actions) = mpsc::unbounded_channel();
+        let mut commands = shared.commands.subscribe();
+        let mut connection = Connection {
+            difficulty: shared.config.difficulty,
+            shared,
+            writer,
+            extranonce1: id.to_be_bytes().to_vec(),
+            authorized: false,
+            jobs: HashMap::new(),
+            submitted: HashSet::new(),
+            actions_tx,
+            script: None,
+        };
+        let mut lines = BufReader::new(reader).lines();
+
+        loop {
+            let keep_open = tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => connection.handle_line(&line).await,
+                    _ => false,
+                },
+                Some(action) = actions.recv() => connection.perform(action).await,
+                command = commands.recv() => match command {
+                    Ok(action) => connection.perform(action).await,
+                    Err(broadcast::error::RecvError::Lagged(_)) => true,
+                    Err(broadcast::error::RecvError::Closed) => false,
+                },
+            };
+            if !keep_open {
+                break;
+            }
+        }
+
+        if let Some(script) = connection.script.take() {
+            script.abort();
+        }
+        let _ = connection.writer.shutdown().await;
+    }
+
+    async fn send(&mut self, message: Value) -> bool {
+        self.write_line(&message.to_string()).await
+    }
+
+    async fn write_line(&mut self, line: &str) -> bool {
+        self.writer.write_all(format!("{}\n", line).as_bytes()).await.is_ok()
+    }
+
+    async fn reply(&mut self, id: &Value, result: Value) -> bool {
+        self.send(json!({"id": id, "result": result, "error": null})).await
+    }
+
+    async fn reply_error(&mut self, id: &Value, (code, message): (i64, &str)) -> bool {
+        self.send(json!({"id": id, "result": null, "error": [code, message, null]})).await
+    }
+
+    async fn handle_line(&mut self, line: &str) -> bool {
+        let counters = &self.shared.counters;
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request @ Value::Object(_)) => request,
+            _ => {
+                Counters::bump(&counters.malformed_requests);
+                return true;
+            }
+        };
+        let id = request["id"].clone();
+        let params = request["params"].as_array().cloned().unwrap_or_default();
+
+        match request["method"].as_str().unwrap_or_default() {
+            "mining.subscribe" => {
+                Counters::bump(&counters.subscribes);
+                let result = json!([
+                    [["mining.set_difficulty", "1"], ["mining.notify", "1"]],
+                    hex::encode(&self.extranonce1),
+                    self.shared.config.extranonce2_size,
+                ]);
+                self.reply(&id, result).await
+            }
+            "mining.authorize" => {
+                Counters::bump(&counters.authorizes);
+                let authorized = self.shared.config.authorize;
+                let keep_open = self.reply(&id, json!(authorized)).await;
+                if authorized && !self.authorized {
+                    self.authorized = true;
+                    self.start_script();
+                }
+                keep_open
+            }
+            "mining.extranonce.subscribe" => self.reply(&id, json!(true)).await,
+            "mining.submit" => {
+                Counters::bump(&counters.submits);
+                match self.check_submit(&params) {
+                    Ok(()) => {
+                        Counters::bump(&self.shared.counters.accepted);
+                        self.reply(&id, json!(true)).await
+                    }
+                    Err(reason) => {
+                        let counters = &self.shared.counters;
+                        Counters::bump(&counters.rejected);
+                        match reason {
+                            RejectReason::Stale | RejectReason::UnknownJob => Counters::bump(&counters.stale),
+                            RejectReason::Duplicate => Counters::bump(&counters.duplicates),
+                            RejectReason::LowDifficulty => Counters::bump(&counters.low_difficulty),
+                            RejectReason::Injected => Counters::bump(&counters.injected_rejects),
+                            RejectReason::Unauthorized | RejectReason::Malformed => {}
+                        }
+                        self.reply_error(&id, reason.error()).await
+                    }
+                }
+            }
+            method => {
+                tracing::debug!("Mock pool got unknown method {}", method);
+                self.reply_error(&id, (20, "Unknown method")).await
+            }
+        }
+    }
+
+    fn check_submit(&mut self, params: &[Value]) -> std::result::Result<(), RejectReason> {
+        if !self.authorized {
+            return Err(RejectReason::Unauthorized);
+        }
+        let fields: Vec<&str> = params.iter().filter_map(Value::as_str).collect();
+        let [_, job_id, extranonce2, ntime, nonce] = fields.as_slice() else {
+            return Err(RejectReason::Malformed);
+        };
+
+        let injected = self.shared.reject_next
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1))
+            .is_ok();
+        if injected {
+            return Err(RejectReason::Injected);
+        }
+
+        let issued = self.jobs.get(*job_id).ok_or(RejectReason::UnknownJob)?;
+        if issued.stale {
+            return Err(RejectReason::Stale);
+        }
+        let key = (job_id.to_string(), extranonce2.to_lowercase(), ntime.to_lowercase(), nonce.to_lowercase());
+        if self.submitted.contains(&key) {
+            return Err(RejectReason::Duplicate);
+        }
+        shares::validate(issued, extranonce2, ntime, nonce)?;
+        self.submitted.insert(key);
+        Ok(())
+    }
+
+    fn start_script(&mut self) {
+        let script = self.shared.config.script.clone();
+        let interval = self.shared.config.notify_interval;
+        let actions = self.actions_tx.clone();
+        self.script = Some(tokio::spawn(async move {
+            for action in script {
+                match action {
+                    Action::Wait(delay) => tokio::time::sleep(delay).await,
+                    action => {
+                        if actions.send(action).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+            let Some(interval) = interval else {
+                return;
+            };
+            loop {
+                tokio::time::sleep(interval).await;
+                if actions.send(Action::Notify { clean_jobs: false }).is_err() {
+                    return;
+                }
+            }
+        }));
+    }
+
+    async fn perform(&mut self, action: Action) -> bool {
+        match action {
+            Action::Notify { clean_jobs } => {
+                if !self.authorized {
+                    return true;
+                }
+                let sequence = self.shared.next_job.fetch_add(1, Ordering::Relaxed);
+                let ntime = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs() as u32)
+                    .unwrap_or(0);
+                let params = shares::notify_params(sequence, ntime, clean_jobs);
+                if clean_jobs {
+                    for issued in self.jobs.values_mut() {
+                        issued.stale = true;
+                    }
+                }
+                let issued = shares::issue(&params, &self.extranonce1, self.shared.config.extranonce2_size, self.difficulty);
+                self.jobs.insert(issued.job.id.clone(), issued);
+                Counters::bump(&self.shared.counters.notifies);
+                self.send(json!({"id": null, "method": "mining.notify", "params": params})).await
+            }
+            Action::SetDifficulty(difficulty) => {
+                self.difficulty = difficulty;
+                self.send(json!({"id": null, "method": "mining.set_difficulty", "params": [difficulty]})).await
+            }
+            Action::Raw(line) => self.write_line(&line).await,
+            Action::Disconnect => false,
+            Action::Wait(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn request(lines: &mut tokio::io::Lines<BufReader<tokio::net::tcp::OwnedReadHalf>>, writer: &mut OwnedWriteHalf, id: u64, method: &str, params: Value) -> Value {
+        let line = json!({"id": id, "method": method, "params": params}).to_string() + "\n";
+        writer.write_all(line.as_bytes()).await.unwrap();
+        loop {
+            let message: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+            if message["id"] == json!(id) {
+                return message;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_script_and_rejections() {
+        let pool = MockPool::start(MockPoolConfig::default()).await.unwrap();
+        let (reader, mut writer) = TcpStream::connect(pool.addr()).await.unwrap().into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let subscribe = request(&mut lines, &mut writer, 1, "mining.subscribe", json!([])).await;
+        assert_eq!(subscribe["result"][1], "00000001");
+        assert_eq!(subscribe["result"][2], 4);
+
+        let authorize = request(&mut lines, &mut writer, 2, "mining.authorize", json!(["w", "x"])).await;
+        assert_eq!(authorize["result"], true);
+        pool.wait_until(Duration::from_secs(5), |stats| stats.notifies == 1).await.unwrap();
+
+        let unknown = request(&mut lines, &mut writer, 3, "mining.submit", json!(["w", "ff", "00000000", "00000000", "00000000"])).await;
+        assert_eq!(unknown["error"][0], 21);
+
+        pool.reject_next(1);
+        let injected = request(&mut lines, &mut writer, 4, "mining.submit", json!(["w", "1", "00000000", "00000000", "00000000"])).await;
+        assert_eq!(injected["error"][0], 20);
+
+        writer.write_all(b"not json\n").await.unwrap();
+        let stats = pool.wait_until(Duration::from_secs(5), |stats| stats.malformed_requests == 1).await.unwrap();
+        assert_eq!((stats.submits, stats.rejected, stats.stale, stats.injected_rejects), (2, 2, 1, 1));
+
+        pool.broadcast(Action::Disconnect);
+        assert!(tokio::time::timeout(Duration::from_secs(5), lines.next_line()).await.unwrap().unwrap().is_none());
+    }
+}
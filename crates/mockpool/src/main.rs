@@ -0,0 +1,59 @@
+use jxpoolminer_mockpool::{MockPool, MockPoolConfig};
+use std::time::Duration;
+
+const USAGE: &str = "Usage: jxpoolminer-mockpool [--port N] [--difficulty D] [--notify-interval SECS] [--extranonce2-size N] [--reject-authorize]";
+
+fn parse_args() -> anyhow::Result<MockPoolConfig> {
+    let mut port = 3333u16;
+    let mut difficulty = 1.0;
+    let mut notify_interval = None;
+    let mut extranonce2_size = 4;
+    let mut authorize = true;
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow::anyhow!("{} needs a value\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--port" => port = value()?.parse()?,
+            "--difficulty" => difficulty = value()?.parse()?,
+            "--notify-interval" => notify_interval = Some(Duration::from_secs_f64(value()?.parse()?)),
+            "--extranonce2-size" => extranonce2_size = value()?.parse()?,
+            "--reject-authorize" => authorize = false,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            other => anyhow::bail!("Unknown argument {}\n{}", other, USAGE),
+        }
+    }
+    
+    Ok(MockPoolConfig {
+        bind: format!("127.0.0.1:{}", port),
+        extranonce2_size,
+        authorize,
+        notify_interval,
+        ..MockPoolConfig::with_difficulty(difficulty)
+    })
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::from_default_env()
+                .add_directive("jxpoolminer_mockpool=info".parse()?),
+        )
+        .init();
+
+    let pool = MockPool::start(parse_args()?).await?;
+    println!("Mock pool running at {}", pool.url());
+
+    let mut report = tokio::time::interval(Duration::from_secs(10));
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = report.tick() => tracing::info!("{:?}", pool.stats()),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+/// Something the pool does to a connection, either from its script or
+/// broadcast at runtime through `MockPool::broadcast`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Send a fresh `mining.notify`; `clean_jobs` makes earlier jobs stale.
+    Notify { clean_jobs: bool },
+    /// Send `mining.set_difficulty`; applies to jobs notified afterwards.
+    SetDifficulty(f64),
+    /// Write a raw line, e.g. malformed JSON.
+    Raw(String),
+    /// Close the connection.
+    Disconnect,
+    /// Pause the script. Ignored when broadcast.
+    Wait(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct MockPoolConfig {
+    /// Address to listen on; port 0 picks a free one.
+    pub bind: String,
+    pub extranonce2_size: usize,
+    /// Difficulty in effect before the first `SetDifficulty`.
+    pub difficulty: f64,
+    /// Answer to `mining.authorize`.
+    pub authorize: bool,
+    /// Run on every connection once it is authorized.
+    pub script: Vec<Action>,
+    /// After the script, send a non-clean job at this interval.
+    pub notify_interval: Option<Duration>,
+}
+
+impl Default for MockPoolConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:0".to_string(),
+            extranonce2_size: 4,
+            difficulty: 1.0,
+            authorize: true,
+            script: vec![
+                Action::SetDifficulty(1.0),
+                Action::Notify { clean_jobs: true },
+            ],
+            notify_interval: None,
+        }
+    }
+}
+
+impl MockPoolConfig {
+    /// Default script at `difficulty`, low enough for CPU tests to find
+    /// shares quickly when well below 1.
+    pub fn with_difficulty(difficulty: f64) -> Self {
+        Self {
+            difficulty,
+            script: vec![
+                Action::SetDifficulty(difficulty),
+                Action::Notify { clean_jobs: true },
+            ],
+            ..Self::default()
+        }
+    }
+}
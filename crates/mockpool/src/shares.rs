@@ -0,0 +1,129 @@
+//! Job templates and share validation against the real header hash.
+
+use jxpoolminer_core::{difficulty_to_target, meets_target, Algorithm, MiningJob, SHA256_DIFF1};
+use jxpoolminer_mining::header::{self, NONCE_OFFSET};
+use jxpoolminer_pool::stratum::job::parse_notify;
+use serde_json::{json, Value};
+
+const COINBASE1: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff2003a0860108";
+const COINBASE2: &str = "0d2f6a78706f6f6c6d696e65722fffffffff0100f2052a010000001976a914000000000000000000000000000000000000000088ac00000000";
+const VERSION: u32 = 0x2000_0000;
+const NBITS: u32 = 0x1d00_ffff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    UnknownJob,
+    Stale,
+    Duplicate,
+    LowDifficulty,
+    Unauthorized,
+    Malformed,
+    Injected,
+}
+
+impl RejectReason {
+    /// Error code and message in the conventional V1 numbering.
+    pub fn error(&self) -> (i64, &'static str) {
+        match self {
+            RejectReason::UnknownJob => (21, "Job not found"),
+            RejectReason::Stale => (21, "Stale share"),
+            RejectReason::Duplicate => (22, "Duplicate share"),
+            RejectReason::LowDifficulty => (23, "Low difficulty share"),
+            RejectReason::Unauthorized => (24, "Unauthorized worker"),
+            RejectReason::Malformed => (20, "Malformed share"),
+            RejectReason::Injected => (20, "Rejected by test script"),
+        }
+    }
+}
+
+/// A job as handed to one connection.
+#[derive(Debug, Clone)]
+pub struct IssuedJob {
+    pub job: MiningJob,
+    pub difficulty: f64,
+    pub stale: bool,
+}
+
+/// `mining.notify` params for job number `sequence`.
+pub fn notify_params(sequence: u64, ntime: u32, clean_jobs: bool) -> Vec<Value> {
+    let prev_hash = header::sha256d(&sequence.to_le_bytes());
+    vec![
+        json!(format!("{:x}", sequence)),
+        json!(hex::encode(prev_hash)),
+        json!(COINBASE1),
+        json!(COINBASE2),
+        json!([]),
+        json!(format!("{:08x}", VERSION)),
+        json!(format!("{:08x}", NBITS)),
+        json!(format!("{:08x}", ntime)),
+        json!(clean_jobs),
+    ]
+}
+
+/// Decodes notify params back into the job a miner would build, so the
+/// pool checks shares with exactly the client's view of the job.
+pub fn issue(params: &[Value], extranonce1: &[u8], extranonce2_size: usize, difficulty: f64) -> IssuedJob {
+    let mut job = parse_notify(params, Algorithm::SHA256).expect("mock pool produced an invalid notify");
+    job.extranonce1 = extranonce1.to_vec();
+    job.extranonce2_size = extranonce2_size;
+    job.difficulty = difficulty;
+    job.target = difficulty_to_target(&SHA256_DIFF1, difficulty).to_vec();
+    IssuedJob { job, difficulty, stale: false }
+}
+
+/// Checks a `mining.submit` against the job's target and returns the block
+/// header hash on success.
+pub fn validate(issued: &IssuedJob, extranonce2: &str, ntime: &str, nonce: &str) -> Result<[u8; 32], RejectReason> {
+    let extranonce2 = hex::decode(extranonce2).map_err(|_| RejectReason::Malformed)?;
+    if extranonce2.len() != issued.job.extranonce2_size {
+        return Err(RejectReason::Malformed);
+    }
+    let ntime = u32::from_str_radix(ntime, 16).map_err(|_| RejectReason::Malformed)?;
+    let nonce = u32::from_str_radix(nonce, 16).map_err(|_| RejectReason::Malformed)?;
+
+    let mut job = issued.job.clone();
+    job.ntime = ntime;
+    let mut block_header = header::header_for(&job, &extranonce2);
+    block_header[NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+    let hash = header::sha256d(&block_header);
+
+    let mut value = hash;
+    value.reverse();
+    if !meets_target(&value, &job.target) {
+        return Err(RejectReason::LowDifficulty);
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Brute-forces a nonce the way a miner would.
+    fn find_share(issued: &IssuedJob, extranonce2: &[u8]) -> u32 {
+        let mut block_header = header::header_for(&issued.job, extranonce2);
+        (0..u32::MAX)
+            .find(|nonce| {
+                block_header[NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+                let mut value = header::sha256d(&block_header);
+                value.reverse();
+                meets_target(&value, &issued.job.target)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate_share() {
+        let params = notify_params(1, 0x504e86b9, true);
+        let issued = issue(&params, &[0, 0, 0, 1], 4, 1.0 / 1_048_576.0);
+        let nonce = find_share(&issued, &[0, 0, 0, 7]);
+
+        assert!(validate(&issued, "00000007", "504e86b9", &format!("{:08x}", nonce)).is_ok());
+        assert_eq!(
+            validate(&issued, "00000008", "504e86b9", &format!("{:08x}", nonce)).err(),
+            Some(RejectReason::LowDifficulty)
+        );
+        assert_eq!(validate(&issued, "0007", "504e86b9", "00000000").err(), Some(RejectReason::Malformed));
+        assert_eq!(validate(&issued, "00000007", "zz", "00000000").err(), Some(RejectReason::Malformed));
+    }
+}
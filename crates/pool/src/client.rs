@@ -1,104 +1,321 @@
-use crate::{PoolConfig, stratum::StratumClient};
+use crate::{PoolConfig, stratum::{self, StratumError, StratumResponse, SubscribeResult}};
+use crate::supervisor::{self, ConnectionState, Supervisor, Transport};
 use jxpoolminer_core::{Share, MiningJob, Algorithm};
 use anyhow::Result;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, Notify, RwLock};
+use tokio::task::JoinHandle;
 use serde_json::Value;
 
 pub struct Client {
     config: PoolConfig,
-    stratum: Arc<StratumClient>,
-    connected: Arc<RwLock<bool>>,
-    current_job: Arc<RwLock<Option<MiningJob>>>,
+    session: Arc<RwLock<Option<Transport>>>,
+    active_url: Arc<RwLock<Option<String>>>,
+    state: watch::Receiver<ConnectionState>,
+    reconnect: Arc<Notify>,
+    difficulty: Arc<RwLock<f64>>,
+    current_job: Arc<watch::Sender<Option<MiningJob>>>,
+    supervisor: JoinHandle<()>,
 }
 
 impl Client {
+    /// Connects to the primary pool, or the fallback if the primary is
+    /// unreachable, and hands the session to a supervisor that keeps it alive.
     pub async fn connect(config: &PoolConfig) -> Result<Self> {
         tracing::info!("Connecting to pool: {}", config.primary);
         
-        let stratum = Arc::new(StratumClient::new());
-        stratum.connect(&config.primary).await?;
+        let session = match supervisor::open_session(config, &config.primary, false).await {
+            Ok(session) => session,
+            Err(e) => match &config.fallback {
+                Some(fallback) => {
+                    tracing::warn!("Primary pool unavailable ({}), trying fallback: {}", e, fallback);
+                    supervisor::open_session(config, fallback, true).await?
+                }
+                None => return Err(e),
+            },
+        };
         
-        let worker = format!("{}:{}", config.wallet_address, config.worker_name);
-        stratum.subscribe("JxPoolMiner/1.0.0").await?;
-        stratum.authorize(&worker, "x").await?;
+        let (state_tx, state) = watch::channel(ConnectionState::Connecting);
+        let sessions = Arc::new(RwLock::new(None));
+        let active_url = Arc::new(RwLock::new(None));
+        let reconnect = Arc::new(Notify::new());
+        let difficulty = Arc::new(RwLock::new(1.0));
+        let extranonce = Arc::new(RwLock::new(session.subscription.clone()));
+        let current_job = Arc::new(watch::channel(None).0);
         
-        let client = Self {
+        let supervisor = Supervisor {
             config: config.clone(),
-            stratum: stratum.clone(),
-            connected: Arc::new(RwLock::new(true)),
-            current_job: Arc::new(RwLock::new(None)),
+            state: state_tx,
+            session: sessions.clone(),
+            active_url: active_url.clone(),
+            difficulty: difficulty.clone(),
+            extranonce,
+            current_job: current_job.clone(),
+            reconnect: reconnect.clone(),
+        };
+        
+        // Publish the first session before returning so callers never
+        // observe the `Connecting` placeholder.
+        let session_state = if session.on_fallback {
+            ConnectionState::FailedOver
+        } else {
+            ConnectionState::Connected
         };
+        *sessions.write().await = Some(session.transport.clone());
+        *active_url.write().await = Some(session.url.clone());
+        supervisor.state.send_replace(session_state);
         
-        client.start_job_listener().await;
+        let supervisor = tokio::spawn(supervisor.run(session));
         
-        Ok(client)
+        Ok(Self {
+            config: config.clone(),
+            session: sessions,
+            active_url,
+            state,
+            reconnect,
+            difficulty,
+            current_job,
+            supervisor,
+        })
     }
     
-    async fn start_job_listener(&self) {
-        let stratum = self.stratum.clone();
-        let current_job = self.current_job.clone();
+    pub(crate) async fn handle_message(
+        response: StratumResponse,
+        algorithm: &Algorithm,
+        algorithm_version: Option<u32>,
+        extranonce: &RwLock<SubscribeResult>,
+        difficulty: &RwLock<f64>,
+        current_job: &watch::Sender<Option<MiningJob>>,
+    ) {
+        let (Some(method), Some(params)) = (response.method, response.params) else {
+            return;
+        };
         
-        tokio::spawn(async move {
-            loop {
-                if let Some(response) = stratum.receive().await {
-                    if let Some(method) = response.method {
-                        if method == "mining.notify" {
-                            if let Some(params) = response.params {
-                                if let Some(job) = Self::parse_job(params) {
-                                    *current_job.write().await = Some(job);
-                                    tracing::info!("Received new mining job");
-                                }
-                            }
-                        }
+        match method.as_str() {
+            "mining.notify" => {
+                let session_difficulty = *difficulty.read().await;
+                let subscription = extranonce.read().await.clone();
+                match Self::parse_job(&params, algorithm, algorithm_version, &subscription, session_difficulty) {
+                    Some(job) => {
+                        tracing::info!("Received new mining job: {} (clean={})", job.id, job.clean_jobs);
+                        current_job.send_replace(Some(job));
                     }
+                    None => tracing::warn!("Ignoring malformed mining.notify: {:?}", params),
                 }
             }
-        });
+            "mining.set_difficulty" => match params.first().and_then(Value::as_f64) {
+                // Applies from the next job on, as the protocol specifies.
+                Some(new_difficulty) if new_difficulty > 0.0 => {
+                    tracing::info!("Pool difficulty set to {}", new_difficulty);
+                    *difficulty.write().await = new_difficulty;
+                }
+                _ => tracing::warn!("Ignoring invalid mining.set_difficulty: {:?}", params),
+            },
+            "mining.set_extranonce" => {
+                let update = match params.as_slice() {
+                    [extranonce1, extranonce2_size, ..] => SubscribeResult::from_params(extranonce1, extranonce2_size),
+                    _ => None,
+                };
+                match update {
+                    // Like difficulty, takes effect with the next job.
+                    Some(update) => {
+                        tracing::info!(
+                            "Pool set extranonce1={} extranonce2_size={}",
+                            hex::encode(&update.extranonce1), update.extranonce2_size
+                        );
+                        *extranonce.write().await = update;
+                    }
+                    None => tracing::warn!("Ignoring invalid mining.set_extranonce: {:?}", params),
+                }
+            }
+            _ => tracing::debug!("Unhandled pool message: {}", method),
+        }
     }
     
-    fn parse_job(params: Vec<Value>) -> Option<MiningJob> {
-        if params.len() < 2 {
-            return None;
-        }
-        
-        let job_id = params[0].as_str()?.to_string();
-        
-        Some(MiningJob::new(
-            job_id,
-            Algorithm::SHA256,
-            1.0,
-        ))
+    fn parse_job(
+        params: &[Value],
+        algorithm: &Algorithm,
+        algorithm_version: Option<u32>,
+        subscription: &SubscribeResult,
+        difficulty: f64,
+    ) -> Option<MiningJob> {
+        let mut job = if stratum::job::has_bitcoin_header(algorithm) {
+            stratum::job::parse_notify(params, algorithm.clone())?
+        } else {
+            stratum::job::parse_ethash_notify(params, algorithm.clone())?
+        };
+        job.algorithm_version = algorithm_version;
+        job.extranonce1 = subscription.extranonce1.clone();
+        job.extranonce2_size = subscription.extranonce2_size;
+        // The engine derives whichever of target and difficulty the pool
+        // left out, as only the algorithm's implementation knows its
+        // difficulty-1 target.
+        job.difficulty = difficulty;
+        Some(job)
     }
     
+    /// Submits a share and returns the pool's verdict. Rejections are
+    /// `Ok(false)`; timeouts and lost connections are errors.
     pub async fn submit_share(&self, share: Share) -> Result<bool> {
         tracing::info!("Submitting share: nonce={}", share.nonce);
         
-        let nonce_hex = format!("{:08x}", share.nonce);
-        let result_hex = hex::encode(&share.hash);
+        let transport = self.session.read().await.clone()
+            .ok_or_else(|| anyhow::anyhow!("Not connected to pool"))?;
         
-        self.stratum.submit(
-            &self.config.worker_name,
-            &share.job_id,
-            &nonce_hex,
-            &result_hex,
-        ).await?;
-        
-        Ok(true)
+        let ethash = !stratum::job::has_bitcoin_header(&self.config.algorithm);
+        let verdict = match transport {
+            Transport::V1(stratum) if ethash => {
+                let (Some(header_hash), Some(mix_digest)) = (&share.header_hash, &share.mix_digest) else {
+                    anyhow::bail!("{} share for job {} has no header hash or mix digest", self.config.algorithm, share.job_id);
+                };
+                stratum.submit_ethash(&self.config.username(), &share.job_id, share.nonce, header_hash, mix_digest).await
+            }
+            Transport::V1(stratum) => stratum.submit(
+                &self.config.username(),
+                &share.job_id,
+                &hex::encode(&share.extranonce2),
+                &format!("{:08x}", share.ntime),
+                &format!("{:08x}", share.nonce as u32),
+            ).await,
+            Transport::V2(sv2) => sv2.submit(&share.job_id, share.nonce as u32, share.ntime).await,
+        };
+        match verdict {
+            Ok(accepted) => Ok(accepted),
+            Err(StratumError::Rejected { code, message }) => {
+                tracing::warn!("Share rejected by pool ({}): {}", code, message);
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
     }
     
+    /// Waits for the pool's current job.
     pub async fn receive_job(&self) -> Result<MiningJob> {
-        loop {
-            let job_guard = self.current_job.read().await;
-            if let Some(job) = job_guard.as_ref() {
-                return Ok(job.clone());
-            }
-            drop(job_guard);
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        }
+        let mut jobs = self.jobs();
+        let job = jobs.wait_for(Option::is_some).await?;
+        Ok(job.clone().expect("waited for a job"))
+    }
+    
+    /// Watch channel holding the latest job; changes on every notify.
+    pub fn jobs(&self) -> watch::Receiver<Option<MiningJob>> {
+        self.current_job.subscribe()
     }
     
     pub async fn is_connected(&self) -> bool {
-        *self.connected.read().await
+        self.state().is_connected()
+    }
+    
+    pub fn state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+    
+    /// Watch channel that changes whenever the connection state does.
+    pub fn state_changes(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+    
+    /// Difficulty the pool last set for this session.
+    pub async fn difficulty(&self) -> f64 {
+        *self.difficulty.read().await
+    }
+    
+    /// URL of the pool the current session is connected to, if any.
+    pub async fn active_pool(&self) -> Option<String> {
+        self.active_url.read().await.clone()
+    }
+    
+    /// Stratum protocol version of the current session, if any.
+    pub async fn protocol_version(&self) -> Option<u8> {
+        self.session.read().await.as_ref().map(|transport| match transport {
+            Transport::V1(_) => 1,
+            Transport::V2(_) => 2,
+        })
+    }
+    
+    /// Drops the current session; the supervisor reconnects starting with
+    /// the primary pool.
+    pub fn reconnect(&self) {
+        self.reconnect.notify_one();
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.supervisor.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn notification(method: &str, params: Value) -> StratumResponse {
+        StratumResponse {
+            id: None,
+            result: None,
+            error: None,
+            method: Some(method.to_string()),
+            params: params.as_array().cloned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_difficulty_applies_to_next_job() {
+        let subscription = SubscribeResult {
+            extranonce1: vec![0x08, 0x00, 0x00, 0x02],
+            extranonce2_size: 4,
+        };
+        let extranonce = RwLock::new(subscription.clone());
+        let difficulty = RwLock::new(1.0);
+        let current_job = watch::channel(None).0;
+        let notify = json!(["bf", "00".repeat(32), "01", "02", [], "00000002", "1c2ac4af", "504e86b9", true]);
+        
+        Client::handle_message(notification("mining.set_difficulty", json!([16])), &Algorithm::SHA256, None, &extranonce, &difficulty, &current_job).await;
+        Client::handle_message(notification("mining.notify", notify), &Algorithm::SHA256, None, &extranonce, &difficulty, &current_job).await;
+        
+        let job = current_job.borrow().clone().unwrap();
+        assert_eq!(job.difficulty, 16.0);
+        assert!(job.target.is_empty());
+        assert_eq!(job.extranonce1, subscription.extranonce1);
+        
+        Client::handle_message(notification("mining.set_difficulty", json!([0])), &Algorithm::SHA256, None, &extranonce, &difficulty, &current_job).await;
+        assert_eq!(*difficulty.read().await, 16.0);
+    }
+
+    #[tokio::test]
+    async fn test_jobs_use_configured_algorithm() {
+        let extranonce = RwLock::new(SubscribeResult {
+            extranonce1: vec![0x08, 0x00, 0x00, 0x02],
+            extranonce2_size: 4,
+        });
+        let difficulty = RwLock::new(2.0);
+        let current_job = watch::channel(None).0;
+        let notify = json!(["c1", "00".repeat(32), "01", "02", [], "00000002", "1c2ac4af", "504e86b9", false]);
+
+        Client::handle_message(notification("mining.notify", notify), &Algorithm::GXHASH, Some(1), &extranonce, &difficulty, &current_job).await;
+
+        let job = current_job.borrow().clone().unwrap();
+        assert_eq!(job.algorithm, Algorithm::GXHASH);
+        assert_eq!(job.algorithm_version, Some(1));
+        assert_eq!(job.difficulty, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_set_extranonce_applies_to_next_job() {
+        let extranonce = RwLock::new(SubscribeResult {
+            extranonce1: vec![0x08, 0x00, 0x00, 0x02],
+            extranonce2_size: 4,
+        });
+        let difficulty = RwLock::new(1.0);
+        let current_job = watch::channel(None).0;
+        let notify = json!(["c0", "00".repeat(32), "01", "02", [], "00000002", "1c2ac4af", "504e86b9", false]);
+        
+        Client::handle_message(notification("mining.set_extranonce", json!(["aabbccdd01", 3])), &Algorithm::SHA256, None, &extranonce, &difficulty, &current_job).await;
+        Client::handle_message(notification("mining.notify", notify), &Algorithm::SHA256, None, &extranonce, &difficulty, &current_job).await;
+        
+        let job = current_job.borrow().clone().unwrap();
+        assert_eq!(job.extranonce1, vec![0xaa, 0xbb, 0xcc, 0xdd, 0x01]);
+        assert_eq!(job.extranonce2_size, 3);
     }
 }
@@ -1,10 +1,15 @@
 pub mod client;
 pub mod stratum;
+pub mod supervisor;
+pub mod tls;
 
 pub use client::Client;
+pub use stratum::StratumError;
+pub use supervisor::{ConnectionState, ReconnectPolicy};
+pub use tls::TlsConfig;
 
-use jxpoolminer_core::{Share, MiningJob};
-use anyhow::Result;
+use jxpoolminer_core::Algorithm;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct PoolConfig {
@@ -12,7 +17,21 @@ pub struct PoolConfig {
     pub fallback: Option<String>,
     pub wallet_address: String,
     pub worker_name: String,
+    /// Use TLS for every pool, not just `stratum+ssl://` / `stratum+tls://` URLs.
     pub use_tls: bool,
+    pub tls: TlsConfig,
+    pub reconnect: ReconnectPolicy,
+    /// How long subscribe/authorize/submit wait for the pool's answer.
+    pub request_timeout: Duration,
+    /// Send `mining.extranonce.subscribe` so the pool may change extranonce1
+    /// mid-session via `mining.set_extranonce`.
+    pub extranonce_subscribe: bool,
+    /// Algorithm Stratum V1 jobs are mined with, since `mining.notify`
+    /// does not say. Stratum V2 jobs are always SHA-256.
+    pub algorithm: Algorithm,
+    /// Proof-of-work version of `algorithm` the pool expects, for
+    /// versioned algorithms (GXHash); `None` for the original one.
+    pub algorithm_version: Option<u32>,
 }
 
 impl Default for PoolConfig {
@@ -23,6 +42,28 @@ impl Default for PoolConfig {
             wallet_address: "GXC_YOUR_WALLET_ADDRESS_HERE".to_string(),
             worker_name: "worker1".to_string(),
             use_tls: false,
+            tls: TlsConfig::default(),
+            reconnect: ReconnectPolicy::default(),
+            request_timeout: stratum::DEFAULT_REQUEST_TIMEOUT,
+            extranonce_subscribe: true,
+            algorithm: Algorithm::SHA256,
+            algorithm_version: None,
         }
     }
 }
+
+impl PoolConfig {
+    /// Username used for both authorize and submit.
+    pub fn username(&self) -> String {
+        format!("{}:{}", self.wallet_address, self.worker_name)
+    }
+    
+    /// TLS settings for connecting to `url`, or `None` for plain TCP.
+    /// Stratum V2 URLs bring their own encryption and never use TLS.
+    pub fn tls_for(&self, url: &str) -> Option<TlsConfig> {
+        if stratum::v2::is_v2_url(url) {
+            return None;
+        }
+        (self.use_tls || tls::is_tls_url(url)).then(|| self.tls.clone())
+    }
+}
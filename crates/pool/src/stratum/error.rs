@@ -0,0 +1,53 @@
+use serde_json::Value;
+use thiserror::Error;
+
+/// Outcome of a failed Stratum call.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum StratumError {
+    /// The pool answered with an `error` member.
+    #[error("Pool error {code}: {message}")]
+    Rejected { code: i64, message: String },
+    
+    #[error("No response to {method} within {timeout_ms} ms")]
+    Timeout { method: String, timeout_ms: u128 },
+    
+    #[error("Connection to pool lost")]
+    Disconnected,
+    
+    #[error("Failed to write to pool: {0}")]
+    Io(String),
+    
+    #[error("Unexpected response to {method}: {result}")]
+    Malformed { method: String, result: Value },
+}
+
+impl StratumError {
+    /// Decodes the `error` member of a response. Pools use either the V1
+    /// array form `[code, "message", traceback]` or a JSON-RPC style object.
+    pub fn from_value(error: &Value) -> Self {
+        match error {
+            Value::Array(items) => Self::Rejected {
+                code: items.first().and_then(Value::as_i64).unwrap_or(-1),
+                message: items.get(1)
+                    .and_then(Value::as_str)
+                    .unwrap_or("Unknown error")
+                    .to_string(),
+            },
+            Value::Object(fields) => Self::Rejected {
+                code: fields.get("code").and_then(Value::as_i64).unwrap_or(-1),
+                message: fields.get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Unknown error")
+                    .to_string(),
+            },
+            Value::String(message) => Self::Rejected {
+                code: -1,
+                message: message.clone(),
+            },
+            other => Self::Rejected {
+                code: -1,
+                message: other.to_string(),
+            },
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use jxpoolminer_core::{Algorithm, MiningJob};
+use serde_json::Value;
+
+/// Converts a Stratum V1 prevhash into header byte order. Pools send it as
+/// eight 32-bit words, each with its bytes reversed.
+pub fn decode_prev_hash(hex_str: &str) -> Option<Vec<u8>> {
+    let mut bytes = hex::decode(hex_str).ok()?;
+    if bytes.len() != 32 {
+        return None;
+    }
+    for word in bytes.chunks_exact_mut(4) {
+        word.reverse();
+    }
+    Some(bytes)
+}
+
+fn decode_u32(value: &Value) -> Option<u32> {
+    u32::from_str_radix(value.as_str()?, 16).ok()
+}
+
+/// Whether `algorithm`'s pools speak the Bitcoin dialect of Stratum V1,
+/// whose `mining.notify` describes an 80-byte header. Ethash pools send a
+/// header hash, DAG seed and share target instead.
+pub fn has_bitcoin_header(algorithm: &Algorithm) -> bool {
+    *algorithm != Algorithm::ETHASH && *algorithm != Algorithm::ETCHASH
+}
+
+/// Decodes hex with or without a `0x` prefix.
+fn decode_hex(value: &Value) -> Option<Vec<u8>> {
+    let hex_str = value.as_str()?;
+    hex::decode(hex_str.strip_prefix("0x").unwrap_or(hex_str)).ok()
+}
+
+/// Decodes `mining.notify` params:
+/// `[job_id, prevhash, coinb1, coinb2, merkle_branch, version, nbits, ntime, clean_jobs]`.
+/// Session data (extranonce, difficulty) is left for the caller to fill in.
+pub fn parse_notify(params: &[Value], algorithm: Algorithm) -> Option<MiningJob> {
+    if params.len() < 9 {
+        return None;
+    }
+    
+    let mut job = MiningJob::new(params[0].as_str()?.to_string(), algorithm, 1.0);
+    job.prev_hash = decode_prev_hash(params[1].as_str()?)?;
+    job.coinbase1 = hex::decode(params[2].as_str()?).ok()?;
+    job.coinbase2 = hex::decode(params[3].as_str()?).ok()?;
+    job.merkle_branches = params[4].as_array()?
+        .iter()
+        .map(|branch| branch.as_str().and_then(|s| hex::decode(s).ok()).filter(|b| b.len() == 32))
+        .collect::<Option<Vec<_>>>()?;
+    job.version = decode_u32(&params[5])?;
+    job.nbits = decode_u32(&params[6])?;
+    job.ntime = decode_u32(&params[7])?;
+    job.clean_jobs = params[8].as_bool().unwrap_or(false);
+    
+    Some(job)
+}
+
+/// Decodes an Ethereum stratum `mining.notify`:
+/// `[job_id, header_hash, seed_hash, target, clean_jobs]`. Without a target
+/// the caller's difficulty applies, and a new header makes earlier work
+/// stale unless the pool says otherwise.
+pub fn parse_ethash_notify(params: &[Value], algorithm: Algorithm) -> Option<MiningJob> {
+    if params.len() < 3 {
+        return None;
+    }
+    
+    let mut job = MiningJob::new(params[0].as_str()?.to_string(), algorithm, 1.0);
+    job.header = decode_hex(&params[1]).filter(|hash| hash.len() == 32)?;
+    job.seed_hash = decode_hex(&params[2]).filter(|hash| hash.len() == 32)?;
+    if let Some(target) = params.get(3).filter(|target| !target.is_null()) {
+        let target = decode_hex(target).filter(|target| target.len() <= 32)?;
+        job.target = vec![0; 32 - target.len()];
+        job.target.extend(target);
+    }
+    job.clean_jobs = params.get(4).and_then(Value::as_bool).unwrap_or(true);
+    
+    Some(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_notify() {
+        // Example notification from the Stratum V1 specification.
+        let params = json!([
+            "bf",
+            "4d16b6f85af6e2198f44ae2a6de67f78487ae5611b77c6c0440b921e00000000",
+            "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff20020862062f503253482f04b8864e5008",
+            "072f736c7573682f000000000100f2052a010000001976a914d23fcdf86f7e756a64a7a9688ef9903327048ed988ac00000000",
+            [],
+            "00000002",
+            "1c2ac4af",
+            "504e86b9",
+            false
+        ]);
+        let job = parse_notify(params.as_array().unwrap(), Algorithm::SHA256).unwrap();
+        
+        assert_eq!(job.id, "bf");
+        assert_eq!(hex::encode(&job.prev_hash), "f8b6164d19e2f65a2aae448f787fe66d61e57a48c0c6771b1e920b4400000000");
+        assert_eq!(job.coinbase1.len(), 58);
+        assert!(job.merkle_branches.is_empty());
+        assert_eq!(job.version, 2);
+        assert_eq!(job.nbits, 0x1c2ac4af);
+        assert_eq!(job.ntime, 0x504e86b9);
+        assert!(!job.clean_jobs);
+    }
+
+    #[test]
+    fn test_rejects_short_or_malformed_params() {
+        assert!(parse_notify(json!(["bf", "00"]).as_array().unwrap(), Algorithm::SHA256).is_none());
+        let bad_branch = json!(["bf", "00".repeat(32), "", "", ["abcd"], "00000002", "1c2ac4af", "504e86b9", true]);
+        assert!(parse_notify(bad_branch.as_array().unwrap(), Algorithm::SHA256).is_none());
+    }
+
+    #[test]
+    fn test_parse_ethash_notify() {
+        let params = json!([
+            "0x1f",
+            "0x2a8de2adf89af77358250bf908bf04ba94a6e8c3ba87775564a41d269a05e4ce",
+            "0x290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563",
+            "0x00000000ffff",
+        ]);
+        let job = parse_ethash_notify(params.as_array().unwrap(), Algorithm::ETHASH).unwrap();
+        
+        assert_eq!(job.id, "0x1f");
+        assert_eq!(hex::encode(&job.header), "2a8de2adf89af77358250bf908bf04ba94a6e8c3ba87775564a41d269a05e4ce");
+        assert_eq!(hex::encode(&job.seed_hash), "290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563");
+        assert_eq!(job.target.len(), 32);
+        assert_eq!(job.target[26..], [0, 0, 0, 0, 0xff, 0xff]);
+        assert!(job.clean_jobs);
+        
+        assert!(parse_ethash_notify(&params.as_array().unwrap()[..2], Algorithm::ETHASH).is_none());
+        let short_header = json!(["1f", "2a8d", "00".repeat(32)]);
+        assert!(parse_ethash_notify(short_header.as_array().unwrap(), Algorithm::ETHASH).is_none());
+        let untargeted = json!(["1f", "00".repeat(32), "00".repeat(32), null, false]);
+        let job = parse_ethash_notify(untargeted.as_array().unwrap(), Algorithm::ETCHASH).unwrap();
+        assert!(job.target.is_empty());
+        assert!(!job.clean_jobs);
+    }
+}
@@ -1,12 +1,27 @@
+pub mod error;
+pub mod job;
+pub mod v2;
+
+pub use error::StratumError;
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use crate::tls::{self, TlsConfig};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+use tokio::task::JoinHandle;
 use anyhow::{Result, Context};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+type PendingRequests = Arc<std::sync::Mutex<HashMap<u64, oneshot::Sender<std::result::Result<Value, StratumError>>>>>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StratumRequest {
     pub id: u64,
@@ -23,121 +38,458 @@ pub struct StratumResponse {
     pub params: Option<Vec<Value>>,
 }
 
+/// Decoded `mining.subscribe` result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubscribeResult {
+    pub extranonce1: Vec<u8>,
+    pub extranonce2_size: usize,
+}
+
+impl SubscribeResult {
+    /// Parses `[subscriptions, extranonce1, extranonce2_size]`.
+    pub fn from_value(result: &Value) -> Option<Self> {
+        let items = result.as_array()?;
+        Self::from_params(items.get(1)?, items.get(2)?)
+    }
+    
+    /// Parses the `[extranonce1, extranonce2_size]` pair, as also sent by
+    /// `mining.set_extranonce`.
+    pub fn from_params(extranonce1: &Value, extranonce2_size: &Value) -> Option<Self> {
+        Some(Self {
+            extranonce1: hex::decode(extranonce1.as_str()?).ok()?,
+            extranonce2_size: extranonce2_size.as_u64()? as usize,
+        })
+    }
+}
+
+/// Strips the scheme (and any path, such as a V2 authority key) from a pool
+/// URL, leaving the `host:port` to dial.
+pub fn pool_address(url: &str) -> &str {
+    let address = url.strip_prefix("stratum+tcp://")
+        .or_else(|| tls::SCHEMES.iter().find_map(|scheme| url.strip_prefix(scheme)))
+        .or_else(|| url.strip_prefix(v2::SCHEME))
+        .or_else(|| url.strip_prefix("tcp://"))
+        .unwrap_or(url);
+    address.split('/').next().unwrap_or(address)
+}
+
 pub struct StratumClient {
-    stream: Arc<RwLock<Option<TcpStream>>>,
+    writer: Arc<Mutex<Option<Writer>>>,
+    reader_task: std::sync::Mutex<Option<JoinHandle<()>>>,
+    alive: Arc<watch::Sender<bool>>,
     request_id: Arc<RwLock<u64>>,
-    tx: mpsc::UnboundedSender<StratumResponse>,
-    rx: Arc<RwLock<mpsc::UnboundedReceiver<StratumResponse>>>,
+    request_timeout: Duration,
+    tls: Option<TlsConfig>,
+    pending: PendingRequests,
+    notifications_tx: mpsc::UnboundedSender<StratumResponse>,
+    notifications_rx: Arc<RwLock<mpsc::UnboundedReceiver<StratumResponse>>>,
+}
+
+impl Default for StratumClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StratumClient {
     pub fn new() -> Self {
-        let (tx, rx) = mpsc::unbounded_channel();
+        Self::with_request_timeout(DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    pub fn with_request_timeout(request_timeout: Duration) -> Self {
+        let (notifications_tx, notifications_rx) = mpsc::unbounded_channel();
         Self {
-            stream: Arc::new(RwLock::new(None)),
+            writer: Arc::new(Mutex::new(None)),
+            reader_task: std::sync::Mutex::new(None),
+            alive: Arc::new(watch::channel(false).0),
             request_id: Arc::new(RwLock::new(1)),
-            tx,
-            rx: Arc::new(RwLock::new(rx)),
+            request_timeout,
+            tls: None,
+            pending: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            notifications_tx,
+            notifications_rx: Arc::new(RwLock::new(notifications_rx)),
         }
     }
 
+    /// Wraps every connection in TLS. `stratum+ssl://` and `stratum+tls://`
+    /// URLs use TLS with default settings even without this.
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
     pub async fn connect(&self, url: &str) -> Result<()> {
-        let addr = url
-            .strip_prefix("stratum+tcp://")
-            .or_else(|| url.strip_prefix("tcp://"))
-            .unwrap_or(url);
+        let addr = pool_address(url);
+        let tls_config = self.tls.clone()
+            .or_else(|| tls::is_tls_url(url).then(TlsConfig::default));
 
-        tracing::info!("Connecting to Stratum server: {}", addr);
+        tracing::info!("Connecting to Stratum server: {}{}", addr, if tls_config.is_some() { " (TLS)" } else { "" });
         let stream = TcpStream::connect(addr).await
             .context("Failed to connect to pool")?;
         
-        *self.stream.write().await = Some(stream);
-        
-        self.start_reader().await;
+        match tls_config {
+            Some(tls_config) => {
+                let stream = tls::connect(stream, addr, &tls_config).await?;
+                let (reader, writer) = tokio::io::split(stream);
+                self.attach(reader, Box::new(writer)).await;
+            }
+            None => {
+                let (reader, writer) = stream.into_split();
+                self.attach(reader, Box::new(writer)).await;
+            }
+        }
         
         Ok(())
     }
 
-    async fn start_reader(&self) {
-        let stream_clone = self.stream.clone();
-        let tx = self.tx.clone();
+    async fn attach<R: AsyncRead + Send + Unpin + 'static>(&self, reader: R, writer: Writer) {
+        *self.writer.lock().await = Some(writer);
+        self.alive.send_replace(true);
+        
+        self.start_reader(reader);
+    }
+
+    fn start_reader<R: AsyncRead + Send + Unpin + 'static>(&self, reader: R) {
+        let notifications = self.notifications_tx.clone();
+        let pending = self.pending.clone();
+        let alive = self.alive.clone();
 
-        tokio::spawn(async move {
-            let mut stream_guard = stream_clone.write().await;
-            if let Some(stream) = stream_guard.as_mut() {
-                let (reader, _) = stream.split();
-                let mut reader = BufReader::new(reader);
-                let mut line = String::new();
-
-                loop {
-                    line.clear();
-                    match reader.read_line(&mut line).await {
-                        Ok(0) => {
-                            tracing::warn!("Connection closed by server");
-                            break;
-                        }
-                        Ok(_) => {
-                            if let Ok(response) = serde_json::from_str::<StratumResponse>(&line) {
-                                let _ = tx.send(response);
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!("Error reading from stream: {}", e);
-                            break;
-                        }
+        let handle = tokio::spawn(async move {
+            let mut reader = BufReader::new(reader);
+            let mut line = String::new();
+
+            loop {
+                line.clear();
+                match reader.read_line(&mut line).await {
+                    Ok(0) => {
+                        tracing::warn!("Connection closed by server");
+                        break;
+                    }
+                    Ok(_) => match serde_json::from_str::<StratumResponse>(&line) {
+                        Ok(message) => Self::dispatch(message, &pending, &notifications),
+                        Err(e) => tracing::warn!("Ignoring malformed line from pool ({}): {}", e, line.trim()),
+                    },
+                    Err(e) => {
+                        tracing::error!("Error reading from stream: {}", e);
+                        break;
                     }
                 }
             }
+
+            alive.send_replace(false);
+            Self::fail_pending(&pending);
         });
+
+        if let Some(previous) = self.reader_task.lock().unwrap().replace(handle) {
+            previous.abort();
+        }
+    }
+
+    /// Completes the matching pending call for responses; anything carrying a
+    /// `method` is a notification (or a server-initiated request).
+    fn dispatch(
+        message: StratumResponse,
+        pending: &PendingRequests,
+        notifications: &mpsc::UnboundedSender<StratumResponse>,
+    ) {
+        if message.method.is_some() {
+            let _ = notifications.send(message);
+            return;
+        }
+        
+        let Some(id) = message.id else {
+            tracing::debug!("Dropping response without id");
+            return;
+        };
+        
+        let waiter = pending.lock().unwrap().remove(&id);
+        match waiter {
+            Some(waiter) => {
+                let outcome = match message.error {
+                    Some(ref error) if !error.is_null() => Err(StratumError::from_value(error)),
+                    _ => Ok(message.result.unwrap_or(Value::Null)),
+                };
+                let _ = waiter.send(outcome);
+            }
+            None => tracing::debug!("Dropping response to unknown request id {}", id),
+        }
     }
 
+    fn fail_pending(pending: &PendingRequests) {
+        for (_, waiter) in pending.lock().unwrap().drain() {
+            let _ = waiter.send(Err(StratumError::Disconnected));
+        }
+    }
+
+    /// Whether the socket is still open in both directions.
+    pub fn is_connected(&self) -> bool {
+        *self.alive.borrow()
+    }
+
+    /// Resolves once the connection has been lost or shut down.
+    pub async fn closed(&self) {
+        let mut alive = self.alive.subscribe();
+        let _ = alive.wait_for(|alive| !*alive).await;
+    }
+
+    /// Tears down the connection; `closed()` resolves afterwards.
+    pub async fn shutdown(&self) {
+        if let Some(handle) = self.reader_task.lock().unwrap().take() {
+            handle.abort();
+        }
+        if let Some(mut writer) = self.writer.lock().await.take() {
+            let _ = writer.shutdown().await;
+        }
+        self.alive.send_replace(false);
+        Self::fail_pending(&self.pending);
+    }
+
+    /// Writes a request without waiting for its response.
     pub async fn send_request(&self, method: &str, params: Vec<Value>) -> Result<u64> {
+        let id = self.next_id().await;
+        self.write_request(id, method, params).await?;
+        Ok(id)
+    }
+
+    /// Sends a request and waits for the pool's answer to it.
+    pub async fn call(&self, method: &str, params: Vec<Value>) -> std::result::Result<Value, StratumError> {
+        let id = self.next_id().await;
+        let (waiter_tx, waiter_rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, waiter_tx);
+        
+        // The reader flags the connection dead before failing pending calls,
+        // so a call registered after that sweep is caught here.
+        if !self.is_connected() {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(StratumError::Disconnected);
+        }
+        
+        if let Err(e) = self.write_request(id, method, params).await {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(e);
+        }
+        
+        match tokio::time::timeout(self.request_timeout, waiter_rx).await {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(_)) => Err(StratumError::Disconnected),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(StratumError::Timeout {
+                    method: method.to_string(),
+                    timeout_ms: self.request_timeout.as_millis(),
+                })
+            }
+        }
+    }
+
+    async fn next_id(&self) -> u64 {
         let mut id_guard = self.request_id.write().await;
         let id = *id_guard;
         *id_guard += 1;
-        drop(id_guard);
+        id
+    }
 
+    async fn write_request(&self, id: u64, method: &str, params: Vec<Value>) -> std::result::Result<(), StratumError> {
         let request = StratumRequest {
             id,
             method: method.to_string(),
             params,
         };
 
-        let json = serde_json::to_string(&request)? + "\n";
+        let json = serde_json::to_string(&request)
+            .map_err(|e| StratumError::Io(e.to_string()))? + "\n";
         
-        let mut stream_guard = self.stream.write().await;
-        if let Some(stream) = stream_guard.as_mut() {
-            stream.write_all(json.as_bytes()).await?;
-            stream.flush().await?;
+        let mut writer_guard = self.writer.lock().await;
+        let writer = writer_guard.as_mut().ok_or(StratumError::Disconnected)?;
+        let written = async {
+            writer.write_all(json.as_bytes()).await?;
+            writer.flush().await
+        }.await;
+
+        if let Err(e) = written {
+            writer_guard.take();
+            self.alive.send_replace(false);
+            return Err(StratumError::Io(e.to_string()));
         }
 
-        Ok(id)
+        Ok(())
     }
 
-    pub async fn subscribe(&self, user_agent: &str) -> Result<u64> {
-        self.send_request("mining.subscribe", vec![
+    pub async fn subscribe(&self, user_agent: &str) -> std::result::Result<SubscribeResult, StratumError> {
+        let result = self.call("mining.subscribe", vec![
             Value::String(user_agent.to_string()),
-        ]).await
+        ]).await?;
+        
+        SubscribeResult::from_value(&result).ok_or_else(|| StratumError::Malformed {
+            method: "mining.subscribe".to_string(),
+            result,
+        })
+    }
+
+    /// Subscribes to an Ethash pool, which answers `true` instead of
+    /// assigning an extranonce1. The high four bytes of the 64-bit nonce
+    /// are rolled as extranonce2 instead.
+    pub async fn subscribe_ethash(&self, user_agent: &str) -> std::result::Result<SubscribeResult, StratumError> {
+        let result = self.call("mining.subscribe", vec![
+            Value::String(user_agent.to_string()),
+        ]).await?;
+        
+        if result.as_bool() != Some(true) {
+            return Err(StratumError::Malformed {
+                method: "mining.subscribe".to_string(),
+                result,
+            });
+        }
+        Ok(SubscribeResult { extranonce1: vec![], extranonce2_size: 4 })
     }
 
-    pub async fn authorize(&self, username: &str, password: &str) -> Result<u64> {
-        self.send_request("mining.authorize", vec![
+    pub async fn authorize(&self, username: &str, password: &str) -> std::result::Result<bool, StratumError> {
+        let result = self.call("mining.authorize", vec![
             Value::String(username.to_string()),
             Value::String(password.to_string()),
-        ]).await
+        ]).await?;
+        
+        Ok(result.as_bool().unwrap_or(false))
+    }
+
+    /// Opts in to `mining.set_extranonce`. Pools that do not know the
+    /// method answer with an error, which callers may ignore.
+    pub async fn extranonce_subscribe(&self) -> std::result::Result<bool, StratumError> {
+        let result = self.call("mining.extranonce.subscribe", vec![]).await?;
+        Ok(result.as_bool().unwrap_or(false))
     }
 
-    pub async fn submit(&self, worker: &str, job_id: &str, nonce: &str, result: &str) -> Result<u64> {
-        self.send_request("mining.submit", vec![
+    /// Submits `[worker, job_id, extranonce2, ntime, nonce]`, all hex except
+    /// the first two. Returns whether the pool accepted the share; a
+    /// rejection with a reason comes back as `StratumError::Rejected`.
+    pub async fn submit(
+        &self,
+        worker: &str,
+        job_id: &str,
+        extranonce2: &str,
+        ntime: &str,
+        nonce: &str,
+    ) -> std::result::Result<bool, StratumError> {
+        let result = self.call("mining.submit", vec![
             Value::String(worker.to_string()),
             Value::String(job_id.to_string()),
+            Value::String(extranonce2.to_string()),
+            Value::String(ntime.to_string()),
             Value::String(nonce.to_string()),
-            Value::String(result.to_string()),
-        ]).await
+        ]).await?;
+        
+        Ok(result.as_bool().unwrap_or(false))
+    }
+
+    /// Submits an Ethash share as `[worker, job_id, nonce, header_hash,
+    /// mix_digest]`, the last three as `0x`-prefixed hex.
+    pub async fn submit_ethash(
+        &self,
+        worker: &str,
+        job_id: &str,
+        nonce: u64,
+        header_hash: &[u8],
+        mix_digest: &[u8],
+    ) -> std::result::Result<bool, StratumError> {
+        let result = self.call("mining.submit", vec![
+            Value::String(worker.to_string()),
+            Value::String(job_id.to_string()),
+            Value::String(format!("0x{:016x}", nonce)),
+            Value::String(format!("0x{}", hex::encode(header_hash))),
+            Value::String(format!("0x{}", hex::encode(mix_digest))),
+        ]).await?;
+        
+        Ok(result.as_bool().unwrap_or(false))
     }
 
+    /// Next unsolicited message from the pool (`mining.notify`,
+    /// `mining.set_difficulty`, ...). Responses to calls never arrive here.
     pub async fn receive(&self) -> Option<StratumResponse> {
-        let mut rx = self.rx.write().await;
+        let mut rx = self.notifications_rx.write().await;
         rx.recv().await
     }
 }
+
+impl Drop for StratumClient {
+    fn drop(&mut self) {
+        if let Ok(mut task) = self.reader_task.lock() {
+            if let Some(handle) = task.take() {
+                handle.abort();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    /// Pool stand-in that sends a notification, then answers requests in
+    /// reverse order so correlation by id is actually exercised.
+    async fn spawn_pool() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            
+            writer.write_all(b"{\"id\":null,\"method\":\"mining.set_difficulty\",\"params\":[8]}\n").await.unwrap();
+            
+            let mut requests = Vec::new();
+            while requests.len() < 2 {
+                let line = lines.next_line().await.unwrap().unwrap();
+                requests.push(serde_json::from_str::<Value>(&line).unwrap());
+            }
+            for request in requests.iter().rev() {
+                let reply = match request["method"].as_str().unwrap() {
+                    "mining.subscribe" => json!({"id": request["id"], "result": [[["mining.notify", "ae6812eb4cd7735a302a8a9dd95cf71f"]], "08000002", 4], "error": null}),
+                    _ => json!({"id": request["id"], "result": null, "error": [23, "Low difficulty share", null]}),
+                };
+                writer.write_all(format!("{}\n", reply).as_bytes()).await.unwrap();
+            }
+            // Swallow anything else without answering.
+            while let Ok(Some(_)) = lines.next_line().await {}
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_responses_are_correlated_by_id() {
+        let addr = spawn_pool().await;
+        let client = Arc::new(StratumClient::with_request_timeout(Duration::from_millis(200)));
+        client.connect(&addr).await.unwrap();
+        
+        let submitter = client.clone();
+        let submit = tokio::spawn(async move {
+            submitter.submit("worker1", "job", "00000000", "504e86b9", "00000000").await
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let subscribed = client.subscribe("test").await.unwrap();
+        
+        assert_eq!(subscribed, SubscribeResult {
+            extranonce1: vec![0x08, 0x00, 0x00, 0x02],
+            extranonce2_size: 4,
+        });
+        assert_eq!(submit.await.unwrap(), Err(StratumError::Rejected {
+            code: 23,
+            message: "Low difficulty share".to_string(),
+        }));
+        
+        let notification = client.receive().await.unwrap();
+        assert_eq!(notification.method.as_deref(), Some("mining.set_difficulty"));
+        
+        let timed_out = client.authorize("worker1", "x").await;
+        assert!(matches!(timed_out, Err(StratumError::Timeout { .. })));
+    }
+
+    #[test]
+    fn test_error_decoding() {
+        assert_eq!(
+            StratumError::from_value(&json!({"code": 21, "message": "Job not found"})),
+            StratumError::Rejected { code: 21, message: "Job not found".to_string() }
+        );
+    }
+}
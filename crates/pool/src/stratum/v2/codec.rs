@@ -0,0 +1,313 @@
+//! Stratum V2 binary framing: the 6-byte frame header, the little-endian
+//! field encodings, and encrypted frame I/O over a Noise session.
+
+use super::noise::{CipherState, MAC_SIZE, MAX_MESSAGE_SIZE};
+use anyhow::{bail, Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub const HEADER_SIZE: usize = 6;
+pub const ENCRYPTED_HEADER_SIZE: usize = HEADER_SIZE + MAC_SIZE;
+pub const MAX_PAYLOAD_SIZE: usize = 0xFF_FFFF;
+/// Bit of `extension_type` marking messages addressed to a channel.
+pub const CHANNEL_MSG_BIT: u16 = 0x8000;
+const MAX_CHUNK_SIZE: usize = MAX_MESSAGE_SIZE - MAC_SIZE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub extension_type: u16,
+    pub channel_msg: bool,
+    pub msg_type: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(msg_type: u8, channel_msg: bool, payload: Vec<u8>) -> Self {
+        Self {
+            extension_type: 0,
+            channel_msg,
+            msg_type,
+            payload,
+        }
+    }
+
+    pub fn header(&self) -> [u8; HEADER_SIZE] {
+        let extension_type = self.extension_type | if self.channel_msg { CHANNEL_MSG_BIT } else { 0 };
+        let length = (self.payload.len() as u32).to_le_bytes();
+        let ext = extension_type.to_le_bytes();
+        [ext[0], ext[1], self.msg_type, length[0], length[1], length[2]]
+    }
+
+    /// Splits a header into the frame skeleton and its payload length.
+    pub fn from_header(header: &[u8]) -> Result<(Self, usize)> {
+        if header.len() != HEADER_SIZE {
+            bail!("Frame header has {} bytes, expected {}", header.len(), HEADER_SIZE);
+        }
+        let extension_type = u16::from_le_bytes([header[0], header[1]]);
+        let length = u32::from_le_bytes([header[3], header[4], header[5], 0]) as usize;
+        Ok((
+            Self {
+                extension_type: extension_type & !CHANNEL_MSG_BIT,
+                channel_msg: extension_type & CHANNEL_MSG_BIT != 0,
+                msg_type: header[2],
+                payload: vec![],
+            },
+            length,
+        ))
+    }
+}
+
+/// Size on the wire of a payload once split into MAC'd Noise messages.
+pub fn encrypted_payload_size(length: usize) -> usize {
+    length + length.div_ceil(MAX_CHUNK_SIZE) * MAC_SIZE
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, cipher: &mut CipherState, frame: &Frame) -> Result<()> {
+    if frame.payload.len() > MAX_PAYLOAD_SIZE {
+        bail!("Frame payload of {} bytes exceeds {}", frame.payload.len(), MAX_PAYLOAD_SIZE);
+    }
+    let mut buffer = cipher.encrypt(&[], &frame.header())?;
+    for chunk in frame.payload.chunks(MAX_CHUNK_SIZE) {
+        buffer.extend_from_slice(&cipher.encrypt(&[], chunk)?);
+    }
+    writer.write_all(&buffer).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, cipher: &mut CipherState) -> Result<Frame> {
+    let mut header = [0u8; ENCRYPTED_HEADER_SIZE];
+    reader.read_exact(&mut header).await.context("Failed to read frame header")?;
+    let (mut frame, length) = Frame::from_header(&cipher.decrypt(&[], &header)?)?;
+
+    let mut encrypted = vec![0u8; encrypted_payload_size(length)];
+    reader.read_exact(&mut encrypted).await.context("Failed to read frame payload")?;
+    frame.payload.reserve(length);
+    for chunk in encrypted.chunks(MAX_MESSAGE_SIZE) {
+        frame.payload.extend_from_slice(&cipher.decrypt(&[], chunk)?);
+    }
+    Ok(frame)
+}
+
+/// Serializes message fields in Stratum V2 wire format.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    pub fn u8(mut self, value: u8) -> Self {
+        self.buffer.push(value);
+        self
+    }
+
+    pub fn bool(self, value: bool) -> Self {
+        self.u8(value as u8)
+    }
+
+    pub fn u16(mut self, value: u16) -> Self {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u32(mut self, value: u32) -> Self {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn u64(mut self, value: u64) -> Self {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    pub fn f32(mut self, value: f32) -> Self {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// `U256`, already in wire (little-endian) byte order.
+    pub fn u256(mut self, value: &[u8; 32]) -> Self {
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    /// `STR0_255`; longer strings are truncated.
+    pub fn str0_255(self, value: &str) -> Self {
+        let mut end = value.len().min(255);
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.b0_255(&value.as_bytes()[..end])
+    }
+
+    pub fn b0_32(self, value: &[u8]) -> Self {
+        debug_assert!(value.len() <= 32);
+        self.b0_255(&value[..value.len().min(32)])
+    }
+
+    pub fn b0_255(mut self, value: &[u8]) -> Self {
+        let value = &value[..value.len().min(255)];
+        self.buffer.push(value.len() as u8);
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    /// `OPTION[U32]`, encoded as a sequence of zero or one element.
+    pub fn option_u32(self, value: Option<u32>) -> Self {
+        match value {
+            Some(value) => self.u8(1).u32(value),
+            None => self.u8(0),
+        }
+    }
+}
+
+/// Reads message fields in Stratum V2 wire format.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            bail!("Message truncated: need {} bytes at offset {}, have {}", length, self.position, self.data.len());
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool> {
+        Ok(self.u8()? & 1 == 1)
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u256(&mut self) -> Result<[u8; 32]> {
+        Ok(self.take(32)?.try_into().unwrap())
+    }
+
+    pub fn str0_255(&mut self) -> Result<String> {
+        String::from_utf8(self.b0_255()?).context("String field is not UTF-8")
+    }
+
+    pub fn b0_32(&mut self) -> Result<Vec<u8>> {
+        let bytes = self.b0_255()?;
+        if bytes.len() > 32 {
+            bail!("B0_32 field has {} bytes", bytes.len());
+        }
+        Ok(bytes)
+    }
+
+    pub fn b0_255(&mut self) -> Result<Vec<u8>> {
+        let length = self.u8()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    pub fn option_u32(&mut self) -> Result<Option<u32>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.u32()?)),
+            count => bail!("OPTION field has {} elements", count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher_pair() -> (CipherState, CipherState) {
+        use super::super::noise::{Initiator, Responder, SignatureNoiseMessage};
+        use secp256k1::{rand, Keypair, Secp256k1, SecretKey};
+
+        let secp = Secp256k1::new();
+        let authority = Keypair::new(&secp, &mut rand::thread_rng());
+        let static_key = SecretKey::new(&mut rand::thread_rng());
+        let certificate = SignatureNoiseMessage::sign(&authority, &static_key.x_only_public_key(&secp).0, 0, u32::MAX);
+        let mut initiator = Initiator::new(None);
+        let (reply, pool) = Responder::new(static_key, certificate).accept(&initiator.hello().unwrap()).unwrap();
+        let miner = initiator.finish(&reply, 0).unwrap();
+        (miner.encryptor, pool.decryptor)
+    }
+
+    #[test]
+    fn test_header_layout() {
+        let frame = Frame::new(0x15, true, vec![0; 0x010203]);
+        assert_eq!(frame.header(), [0x00, 0x80, 0x15, 0x03, 0x02, 0x01]);
+
+        let (parsed, length) = Frame::from_header(&frame.header()).unwrap();
+        assert_eq!(length, 0x010203);
+        assert!(parsed.channel_msg);
+        assert_eq!(parsed.extension_type, 0);
+        assert_eq!(parsed.msg_type, 0x15);
+    }
+
+    #[test]
+    fn test_fields_roundtrip() {
+        let bytes = Encoder::new()
+            .u8(7).bool(true).u16(0x0102).u32(0xdeadbeef).u64(u64::MAX - 1).f32(1.5)
+            .u256(&[0xab; 32]).str0_255("JxPoolMiner").b0_32(&[1, 2, 3]).option_u32(Some(9)).option_u32(None)
+            .finish();
+        assert_eq!(&bytes[1..4], &[0x01, 0x02, 0x01]);
+
+        let mut decoder = Decoder::new(&bytes);
+        assert_eq!(decoder.u8().unwrap(), 7);
+        assert!(decoder.bool().unwrap());
+        assert_eq!(decoder.u16().unwrap(), 0x0102);
+        assert_eq!(decoder.u32().unwrap(), 0xdeadbeef);
+        assert_eq!(decoder.u64().unwrap(), u64::MAX - 1);
+        assert_eq!(decoder.f32().unwrap(), 1.5);
+        assert_eq!(decoder.u256().unwrap(), [0xab; 32]);
+        assert_eq!(decoder.str0_255().unwrap(), "JxPoolMiner");
+        assert_eq!(decoder.b0_32().unwrap(), vec![1, 2, 3]);
+        assert_eq!(decoder.option_u32().unwrap(), Some(9));
+        assert_eq!(decoder.option_u32().unwrap(), None);
+        assert!(decoder.u8().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_frames_roundtrip_across_chunks() {
+        let (mut encryptor, mut decryptor) = cipher_pair();
+        let (mut client, mut server) = tokio::io::duplex(1 << 20);
+        let large = Frame::new(0x15, true, (0..150_000u32).map(|i| i as u8).collect());
+        let small = Frame::new(0x00, false, vec![1, 2, 3]);
+
+        write_frame(&mut client, &mut encryptor, &large).await.unwrap();
+        write_frame(&mut client, &mut encryptor, &small).await.unwrap();
+
+        assert_eq!(read_frame(&mut server, &mut decryptor).await.unwrap(), large);
+        assert_eq!(read_frame(&mut server, &mut decryptor).await.unwrap(), small);
+        assert_eq!(encrypted_payload_size(150_000), 150_000 + 3 * MAC_SIZE);
+    }
+}
@@ -0,0 +1,339 @@
+//! Stratum V2 common and mining-protocol messages used by a standard
+//! (header-only) channel.
+
+use super::codec::{Decoder, Encoder, Frame};
+use anyhow::Result;
+
+pub const SETUP_CONNECTION: u8 = 0x00;
+pub const SETUP_CONNECTION_SUCCESS: u8 = 0x01;
+pub const SETUP_CONNECTION_ERROR: u8 = 0x02;
+pub const OPEN_STANDARD_MINING_CHANNEL: u8 = 0x10;
+pub const OPEN_STANDARD_MINING_CHANNEL_SUCCESS: u8 = 0x11;
+pub const OPEN_MINING_CHANNEL_ERROR: u8 = 0x12;
+pub const NEW_MINING_JOB: u8 = 0x15;
+pub const SUBMIT_SHARES_STANDARD: u8 = 0x1a;
+pub const SUBMIT_SHARES_SUCCESS: u8 = 0x1c;
+pub const SUBMIT_SHARES_ERROR: u8 = 0x1d;
+pub const SET_NEW_PREV_HASH: u8 = 0x20;
+pub const SET_TARGET: u8 = 0x21;
+
+/// `SetupConnection.protocol` value for the mining protocol.
+pub const MINING_PROTOCOL: u8 = 0;
+/// Mining `SetupConnection` flag: the device only handles standard jobs.
+pub const REQUIRES_STANDARD_JOBS: u32 = 0x1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetupConnection {
+    pub protocol: u8,
+    pub min_version: u16,
+    pub max_version: u16,
+    pub flags: u32,
+    pub endpoint_host: String,
+    pub endpoint_port: u16,
+    pub vendor: String,
+    pub hardware_version: String,
+    pub firmware: String,
+    pub device_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetupConnectionSuccess {
+    pub used_version: u16,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetupConnectionError {
+    pub flags: u32,
+    pub error_code: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenStandardMiningChannel {
+    pub request_id: u32,
+    pub user_identity: String,
+    pub nominal_hash_rate: f32,
+    pub max_target: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenStandardMiningChannelSuccess {
+    pub request_id: u32,
+    pub channel_id: u32,
+    pub target: [u8; 32],
+    pub extranonce_prefix: Vec<u8>,
+    pub group_channel_id: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenMiningChannelError {
+    pub request_id: u32,
+    pub error_code: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewMiningJob {
+    pub channel_id: u32,
+    pub job_id: u32,
+    /// `None` marks a future job that waits for its `SetNewPrevHash`.
+    pub min_ntime: Option<u32>,
+    pub version: u32,
+    pub merkle_root: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetNewPrevHash {
+    pub channel_id: u32,
+    pub job_id: u32,
+    pub prev_hash: [u8; 32],
+    pub min_ntime: u32,
+    pub nbits: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetTarget {
+    pub channel_id: u32,
+    pub maximum_target: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubmitSharesStandard {
+    pub channel_id: u32,
+    pub sequence_number: u32,
+    pub job_id: u32,
+    pub nonce: u32,
+    pub ntime: u32,
+    pub version: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubmitSharesSuccess {
+    pub channel_id: u32,
+    pub last_sequence_number: u32,
+    pub new_submits_accepted_count: u32,
+    pub new_shares_sum: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubmitSharesError {
+    pub channel_id: u32,
+    pub sequence_number: u32,
+    pub error_code: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    SetupConnection(SetupConnection),
+    SetupConnectionSuccess(SetupConnectionSuccess),
+    SetupConnectionError(SetupConnectionError),
+    OpenStandardMiningChannel(OpenStandardMiningChannel),
+    OpenStandardMiningChannelSuccess(OpenStandardMiningChannelSuccess),
+    OpenMiningChannelError(OpenMiningChannelError),
+    NewMiningJob(NewMiningJob),
+    SetNewPrevHash(SetNewPrevHash),
+    SetTarget(SetTarget),
+    SubmitSharesStandard(SubmitSharesStandard),
+    SubmitSharesSuccess(SubmitSharesSuccess),
+    SubmitSharesError(SubmitSharesError),
+}
+
+impl Message {
+    pub fn to_frame(&self) -> Frame {
+        match self {
+            Message::SetupConnection(m) => Frame::new(SETUP_CONNECTION, false, Encoder::new()
+                .u8(m.protocol).u16(m.min_version).u16(m.max_version).u32(m.flags)
+                .str0_255(&m.endpoint_host).u16(m.endpoint_port).str0_255(&m.vendor)
+                .str0_255(&m.hardware_version).str0_255(&m.firmware).str0_255(&m.device_id)
+                .finish()),
+            Message::SetupConnectionSuccess(m) => Frame::new(SETUP_CONNECTION_SUCCESS, false, Encoder::new()
+                .u16(m.used_version).u32(m.flags)
+                .finish()),
+            Message::SetupConnectionError(m) => Frame::new(SETUP_CONNECTION_ERROR, false, Encoder::new()
+                .u32(m.flags).str0_255(&m.error_code)
+                .finish()),
+            Message::OpenStandardMiningChannel(m) => Frame::new(OPEN_STANDARD_MINING_CHANNEL, false, Encoder::new()
+                .u32(m.request_id).str0_255(&m.user_identity).f32(m.nominal_hash_rate).u256(&m.max_target)
+                .finish()),
+            Message::OpenStandardMiningChannelSuccess(m) => Frame::new(OPEN_STANDARD_MINING_CHANNEL_SUCCESS, false, Encoder::new()
+                .u32(m.request_id).u32(m.channel_id).u256(&m.target).b0_32(&m.extranonce_prefix).u32(m.group_channel_id)
+                .finish()),
+            Message::OpenMiningChannelError(m) => Frame::new(OPEN_MINING_CHANNEL_ERROR, false, Encoder::new()
+                .u32(m.request_id).str0_255(&m.error_code)
+                .finish()),
+            Message::NewMiningJob(m) => Frame::new(NEW_MINING_JOB, true, Encoder::new()
+                .u32(m.channel_id).u32(m.job_id).option_u32(m.min_ntime).u32(m.version).b0_32(&m.merkle_root)
+                .finish()),
+            Message::SetNewPrevHash(m) => Frame::new(SET_NEW_PREV_HASH, true, Encoder::new()
+                .u32(m.channel_id).u32(m.job_id).u256(&m.prev_hash).u32(m.min_ntime).u32(m.nbits)
+                .finish()),
+            Message::SetTarget(m) => Frame::new(SET_TARGET, true, Encoder::new()
+                .u32(m.channel_id).u256(&m.maximum_target)
+                .finish()),
+            Message::SubmitSharesStandard(m) => Frame::new(SUBMIT_SHARES_STANDARD, true, Encoder::new()
+                .u32(m.channel_id).u32(m.sequence_number).u32(m.job_id).u32(m.nonce).u32(m.ntime).u32(m.version)
+                .finish()),
+            Message::SubmitSharesSuccess(m) => Frame::new(SUBMIT_SHARES_SUCCESS, true, Encoder::new()
+                .u32(m.channel_id).u32(m.last_sequence_number).u32(m.new_submits_accepted_count).u64(m.new_shares_sum)
+                .finish()),
+            Message::SubmitSharesError(m) => Frame::new(SUBMIT_SHARES_ERROR, true, Encoder::new()
+                .u32(m.channel_id).u32(m.sequence_number).str0_255(&m.error_code)
+                .finish()),
+        }
+    }
+
+    /// Decodes a frame; `None` for message types this client does not handle.
+    pub fn from_frame(frame: &Frame) -> Result<Option<Self>> {
+        if frame.extension_type != 0 {
+            return Ok(None);
+        }
+        let mut d = Decoder::new(&frame.payload);
+        let message = match frame.msg_type {
+            SETUP_CONNECTION => Message::SetupConnection(SetupConnection {
+                protocol: d.u8()?,
+                min_version: d.u16()?,
+                max_version: d.u16()?,
+                flags: d.u32()?,
+                endpoint_host: d.str0_255()?,
+                endpoint_port: d.u16()?,
+                vendor: d.str0_255()?,
+                hardware_version: d.str0_255()?,
+                firmware: d.str0_255()?,
+                device_id: d.str0_255()?,
+            }),
+            SETUP_CONNECTION_SUCCESS => Message::SetupConnectionSuccess(SetupConnectionSuccess {
+                used_version: d.u16()?,
+                flags: d.u32()?,
+            }),
+            SETUP_CONNECTION_ERROR => Message::SetupConnectionError(SetupConnectionError {
+                flags: d.u32()?,
+                error_code: d.str0_255()?,
+            }),
+            OPEN_STANDARD_MINING_CHANNEL => Message::OpenStandardMiningChannel(OpenStandardMiningChannel {
+                request_id: d.u32()?,
+                user_identity: d.str0_255()?,
+                nominal_hash_rate: d.f32()?,
+                max_target: d.u256()?,
+            }),
+            OPEN_STANDARD_MINING_CHANNEL_SUCCESS => Message::OpenStandardMiningChannelSuccess(OpenStandardMiningChannelSuccess {
+                request_id: d.u32()?,
+                channel_id: d.u32()?,
+                target: d.u256()?,
+                extranonce_prefix: d.b0_32()?,
+                group_channel_id: d.u32()?,
+            }),
+            OPEN_MINING_CHANNEL_ERROR => Message::OpenMiningChannelError(OpenMiningChannelError {
+                request_id: d.u32()?,
+                error_code: d.str0_255()?,
+            }),
+            NEW_MINING_JOB => Message::NewMiningJob(NewMiningJob {
+                channel_id: d.u32()?,
+                job_id: d.u32()?,
+                min_ntime: d.option_u32()?,
+                version: d.u32()?,
+                merkle_root: d.b0_32()?,
+            }),
+            SET_NEW_PREV_HASH => Message::SetNewPrevHash(SetNewPrevHash {
+                channel_id: d.u32()?,
+                job_id: d.u32()?,
+                prev_hash: d.u256()?,
+                min_ntime: d.u32()?,
+                nbits: d.u32()?,
+            }),
+            SET_TARGET => Message::SetTarget(SetTarget {
+                channel_id: d.u32()?,
+                maximum_target: d.u256()?,
+            }),
+            SUBMIT_SHARES_STANDARD => Message::SubmitSharesStandard(SubmitSharesStandard {
+                channel_id: d.u32()?,
+                sequence_number: d.u32()?,
+                job_id: d.u32()?,
+                nonce: d.u32()?,
+                ntime: d.u32()?,
+                version: d.u32()?,
+            }),
+            SUBMIT_SHARES_SUCCESS => Message::SubmitSharesSuccess(SubmitSharesSuccess {
+                channel_id: d.u32()?,
+                last_sequence_number: d.u32()?,
+                new_submits_accepted_count: d.u32()?,
+                new_shares_sum: d.u64()?,
+            }),
+            SUBMIT_SHARES_ERROR => Message::SubmitSharesError(SubmitSharesError {
+                channel_id: d.u32()?,
+                sequence_number: d.u32()?,
+                error_code: d.str0_255()?,
+            }),
+            _ => return Ok(None),
+        };
+        Ok(Some(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_submit_shares_standard_layout() {
+        let frame = Message::SubmitSharesStandard(SubmitSharesStandard {
+            channel_id: 1,
+            sequence_number: 2,
+            job_id: 3,
+            nonce: 0x11223344,
+            ntime: 0x504e86b9,
+            version: 0x20000000,
+        })
+        .to_frame();
+        assert!(frame.channel_msg);
+        assert_eq!(frame.msg_type, SUBMIT_SHARES_STANDARD);
+        assert_eq!(
+            hex::encode(&frame.payload),
+            "01000000020000000300000044332211b9864e5000000020"
+        );
+    }
+
+    #[test]
+    fn test_messages_roundtrip() {
+        let messages = vec![
+            Message::SetupConnection(SetupConnection {
+                protocol: MINING_PROTOCOL,
+                min_version: 2,
+                max_version: 2,
+                flags: REQUIRES_STANDARD_JOBS,
+                endpoint_host: "pool.example".to_string(),
+                endpoint_port: 3336,
+                vendor: "JxPoolMiner".to_string(),
+                hardware_version: String::new(),
+                firmware: "1.0.0".to_string(),
+                device_id: String::new(),
+            }),
+            Message::OpenStandardMiningChannelSuccess(OpenStandardMiningChannelSuccess {
+                request_id: 1,
+                channel_id: 7,
+                target: [0x11; 32],
+                extranonce_prefix: vec![0, 0, 0, 1],
+                group_channel_id: 0,
+            }),
+            Message::NewMiningJob(NewMiningJob {
+                channel_id: 7,
+                job_id: 42,
+                min_ntime: None,
+                version: 0x20000000,
+                merkle_root: vec![0x22; 32],
+            }),
+            Message::SubmitSharesError(SubmitSharesError {
+                channel_id: 7,
+                sequence_number: 3,
+                error_code: "difficulty-too-low".to_string(),
+            }),
+        ];
+        for message in messages {
+            assert_eq!(Message::from_frame(&message.to_frame()).unwrap(), Some(message));
+        }
+    }
+
+    #[test]
+    fn test_unknown_and_truncated_frames() {
+        assert_eq!(Message::from_frame(&Frame::new(0x7f, false, vec![])).unwrap(), None);
+        assert!(Message::from_frame(&Frame::new(SET_TARGET, true, vec![0; 10])).is_err());
+    }
+}
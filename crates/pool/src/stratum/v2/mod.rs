@@ -0,0 +1,629 @@
+//! Stratum V2 client for a single standard (header-only) channel, selected
+//! by `stratum2+tcp://host:port[/authority_key]` pool URLs.
+
+pub mod codec;
+pub mod messages;
+pub mod noise;
+
+use self::codec::{read_frame, write_frame};
+use self::messages::*;
+use self::noise::{CipherState, Initiator, NoiseTransport, RESPONDER_MESSAGE_SIZE};
+use super::{pool_address, StratumError};
+use anyhow::{bail, Context, Result};
+use jxpoolminer_core::{target_to_difficulty, Algorithm, MiningJob, SHA256_DIFF1};
+use secp256k1::XOnlyPublicKey;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+use tokio::task::JoinHandle;
+
+pub const SCHEME: &str = "stratum2+tcp://";
+pub const PROTOCOL_VERSION: u16 = 2;
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+type PendingSubmits = Arc<std::sync::Mutex<HashMap<u32, oneshot::Sender<std::result::Result<bool, StratumError>>>>>;
+type JobVersions = Arc<std::sync::Mutex<HashMap<u32, u32>>>;
+
+pub fn is_v2_url(url: &str) -> bool {
+    url.starts_with(SCHEME)
+}
+
+/// Authority key from the URL path, if one is given.
+pub fn authority_key(url: &str) -> Result<Option<XOnlyPublicKey>> {
+    let rest = url.strip_prefix(SCHEME).unwrap_or(url);
+    match rest.split_once('/') {
+        Some((_, key)) if !key.is_empty() => decode_authority_key(key).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Accepts a 64-character hex x-only key or the base58check form pools
+/// publish (two version bytes `01 00` followed by the key).
+pub fn decode_authority_key(encoded: &str) -> Result<XOnlyPublicKey> {
+    let bytes = if encoded.len() == 64 && encoded.bytes().all(|b| b.is_ascii_hexdigit()) {
+        hex::decode(encoded)?
+    } else {
+        match base58check_decode(encoded)?.as_slice() {
+            [1, 0, key @ ..] => key.to_vec(),
+            _ => bail!("Unsupported authority key version in {}", encoded),
+        }
+    };
+    XOnlyPublicKey::from_slice(&bytes).context("Authority key is not a valid x-only public key")
+}
+
+fn base58check_decode(encoded: &str) -> Result<Vec<u8>> {
+    let mut number: Vec<u8> = Vec::new();
+    for c in encoded.bytes() {
+        let Some(digit) = BASE58_ALPHABET.iter().position(|&a| a == c) else {
+            bail!("Invalid base58 character {:?}", c as char);
+        };
+        let mut carry = digit as u32;
+        for byte in number.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            number.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let leading_zeros = encoded.bytes().take_while(|&c| c == b'1').count();
+    number.extend(std::iter::repeat_n(0, leading_zeros));
+    number.reverse();
+
+    if number.len() < 4 {
+        bail!("base58check string too short");
+    }
+    let (payload, checksum) = number.split_at(number.len() - 4);
+    let digest = Sha256::digest(Sha256::digest(payload));
+    if &digest[..4] != checksum {
+        bail!("base58check checksum mismatch");
+    }
+    Ok(payload.to_vec())
+}
+
+fn unix_now() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs().min(u32::MAX as u64) as u32)
+        .unwrap_or(0)
+}
+
+fn reversed(bytes: &[u8; 32]) -> [u8; 32] {
+    let mut out = *bytes;
+    out.reverse();
+    out
+}
+
+async fn read_message(stream: &mut TcpStream, cipher: &mut CipherState) -> Result<Message> {
+    loop {
+        let frame = read_frame(stream, cipher).await?;
+        match Message::from_frame(&frame)? {
+            Some(message) => return Ok(message),
+            None => tracing::debug!("Skipping unhandled Stratum V2 message type {:#04x}", frame.msg_type),
+        }
+    }
+}
+
+/// Jobs and target of the open channel, tracked by the reader task.
+struct ChannelState {
+    channel_id: u32,
+    /// Share target, big-endian.
+    target: [u8; 32],
+    prev_hash: Option<SetNewPrevHash>,
+    future_jobs: HashMap<u32, NewMiningJob>,
+    active_job: Option<NewMiningJob>,
+    job_versions: JobVersions,
+}
+
+impl ChannelState {
+    fn handle(&mut self, message: Message, jobs: &mpsc::UnboundedSender<MiningJob>, pending: &PendingSubmits) {
+        match message {
+            Message::NewMiningJob(job) if job.channel_id == self.channel_id => {
+                if job.min_ntime.is_none() {
+                    self.future_jobs.insert(job.job_id, job);
+                    return;
+                }
+                self.job_versions.lock().unwrap().insert(job.job_id, job.version);
+                self.activate(job, false, jobs);
+            }
+            Message::SetNewPrevHash(prev_hash) if prev_hash.channel_id == self.channel_id => {
+                let job = self.future_jobs.remove(&prev_hash.job_id);
+                // Everything built on the old block is stale now.
+                self.future_jobs.clear();
+                {
+                    let mut versions = self.job_versions.lock().unwrap();
+                    versions.clear();
+                    if let Some(job) = &job {
+                        versions.insert(job.job_id, job.version);
+                    }
+                }
+                self.prev_hash = Some(prev_hash);
+                match job {
+                    Some(job) => self.activate(job, true, jobs),
+                    None => self.active_job = None,
+                }
+            }
+            Message::SetTarget(update) if update.channel_id == self.channel_id => {
+                self.target = reversed(&update.maximum_target);
+                tracing::info!("Pool set channel target, difficulty {}", target_to_difficulty(&SHA256_DIFF1, &self.target));
+                // Unlike V1 difficulty the new target applies immediately.
+                if let Some(job) = self.active_job.take() {
+                    self.activate(job, false, jobs);
+                }
+            }
+            Message::SubmitSharesSuccess(success) => {
+                let mut pending = pending.lock().unwrap();
+                let acknowledged: Vec<u32> = pending.keys()
+                    .copied()
+                    .filter(|&sequence| sequence <= success.last_sequence_number)
+                    .collect();
+                for sequence in acknowledged {
+                    if let Some(waiter) = pending.remove(&sequence) {
+                        let _ = waiter.send(Ok(true));
+                    }
+                }
+            }
+            Message::SubmitSharesError(error) => {
+                if let Some(waiter) = pending.lock().unwrap().remove(&error.sequence_number) {
+                    let _ = waiter.send(Err(StratumError::Rejected {
+                        code: -1,
+                        message: error.error_code,
+                    }));
+                }
+            }
+            other => tracing::debug!("Ignoring Stratum V2 message: {:?}", other),
+        }
+    }
+
+    fn activate(&mut self, job: NewMiningJob, clean_jobs: bool, jobs: &mpsc::UnboundedSender<MiningJob>) {
+        if let Some(prev_hash) = &self.prev_hash {
+            let _ = jobs.send(mining_job(&job, prev_hash, &self.target, clean_jobs));
+        }
+        self.active_job = Some(job);
+    }
+}
+
+/// Header-only job: the pool already committed to the merkle root, so the
+/// 76-byte header prefix is complete and there is no coinbase to roll.
+fn mining_job(job: &NewMiningJob, prev_hash: &SetNewPrevHash, target: &[u8; 32], clean_jobs: bool) -> MiningJob {
+    let mut mining_job = MiningJob::new(job.job_id.to_string(), Algorithm::SHA256, target_to_difficulty(&SHA256_DIFF1, target));
+    mining_job.target = target.to_vec();
+    mining_job.prev_hash = prev_hash.prev_hash.to_vec();
+    mining_job.version = job.version;
+    mining_job.nbits = prev_hash.nbits;
+    mining_job.ntime = job.min_ntime.unwrap_or(prev_hash.min_ntime).max(prev_hash.min_ntime);
+    mining_job.clean_jobs = clean_jobs;
+
+    let mut header = Vec::with_capacity(76);
+    header.extend_from_slice(&mining_job.version.to_le_bytes());
+    header.extend_from_slice(&prev_hash.prev_hash);
+    header.extend_from_slice(&job.merkle_root);
+    header.extend_from_slice(&mining_job.ntime.to_le_bytes());
+    header.extend_from_slice(&mining_job.nbits.to_le_bytes());
+    mining_job.header = header;
+    mining_job
+}
+
+pub struct Sv2Client {
+    writer: Mutex<Option<(OwnedWriteHalf, CipherState)>>,
+    reader_task: std::sync::Mutex<Option<JoinHandle<()>>>,
+    alive: Arc<watch::Sender<bool>>,
+    request_timeout: Duration,
+    channel_id: u32,
+    extranonce_prefix: Vec<u8>,
+    sequence_number: AtomicU32,
+    pending: PendingSubmits,
+    job_versions: JobVersions,
+    jobs_rx: Mutex<mpsc::UnboundedReceiver<MiningJob>>,
+}
+
+impl Sv2Client {
+    /// Runs the Noise handshake, `SetupConnection` and opens a standard
+    /// channel for `user_identity`.
+    pub async fn connect(url: &str, user_identity: &str, request_timeout: Duration) -> Result<Self> {
+        let addr = pool_address(url);
+        let authority = authority_key(url)?;
+
+        tracing::info!("Connecting to Stratum V2 server: {}", addr);
+        let mut stream = TcpStream::connect(addr).await
+            .context("Failed to connect to pool")?;
+        let (transport, channel) = tokio::time::timeout(
+            request_timeout,
+            Self::setup(&mut stream, addr, authority, user_identity),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("Stratum V2 setup with {} timed out", addr))??;
+        tracing::debug!(
+            "Opened Stratum V2 channel {} on {} (extranonce prefix {})",
+            channel.channel_id, addr, hex::encode(&channel.extranonce_prefix)
+        );
+
+        let (reader, writer) = stream.into_split();
+        let (jobs_tx, jobs_rx) = mpsc::unbounded_channel();
+        let alive = Arc::new(watch::channel(true).0);
+        let pending: PendingSubmits = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let job_versions: JobVersions = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let state = ChannelState {
+            channel_id: channel.channel_id,
+            target: reversed(&channel.target),
+            prev_hash: None,
+            future_jobs: HashMap::new(),
+            active_job: None,
+            job_versions: job_versions.clone(),
+        };
+        let reader_task = Self::start_reader(reader, transport.decryptor, state, jobs_tx, pending.clone(), alive.clone());
+
+        Ok(Self {
+            writer: Mutex::new(Some((writer, transport.encryptor))),
+            reader_task: std::sync::Mutex::new(Some(reader_task)),
+            alive,
+            request_timeout,
+            channel_id: channel.channel_id,
+            extranonce_prefix: channel.extranonce_prefix,
+            sequence_number: AtomicU32::new(0),
+            pending,
+            job_versions,
+            jobs_rx: Mutex::new(jobs_rx),
+        })
+    }
+
+    async fn setup(
+        stream: &mut TcpStream,
+        addr: &str,
+        authority: Option<XOnlyPublicKey>,
+        user_identity: &str,
+    ) -> Result<(NoiseTransport, OpenStandardMiningChannelSuccess)> {
+        let mut initiator = Initiator::new(authority);
+        stream.write_all(&initiator.hello()?).await?;
+        let mut reply = [0u8; RESPONDER_MESSAGE_SIZE];
+        stream.read_exact(&mut reply).await
+            .context("Pool closed the connection during the Noise handshake")?;
+        let mut transport = initiator.finish(&reply, unix_now())?;
+
+        let (host, port) = addr.rsplit_once(':').unwrap_or((addr, ""));
+        let setup = Message::SetupConnection(SetupConnection {
+            protocol: MINING_PROTOCOL,
+            min_version: PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            flags: REQUIRES_STANDARD_JOBS,
+            endpoint_host: host.to_string(),
+            endpoint_port: port.parse().unwrap_or(0),
+            vendor: "JxPoolMiner".to_string(),
+            hardware_version: String::new(),
+            firmware: env!("CARGO_PKG_VERSION").to_string(),
+            device_id: String::new(),
+        });
+        write_frame(stream, &mut transport.encryptor, &setup.to_frame()).await?;
+        match read_message(stream, &mut transport.decryptor).await? {
+            Message::SetupConnectionSuccess(success) => {
+                tracing::debug!("SetupConnection accepted: version {}, flags {:#x}", success.used_version, success.flags);
+            }
+            Message::SetupConnectionError(error) => bail!("Pool refused SetupConnection: {}", error.error_code),
+            other => bail!("Unexpected reply to SetupConnection: {:?}", other),
+        }
+
+        let open = Message::OpenStandardMiningChannel(OpenStandardMiningChannel {
+            request_id: 1,
+            user_identity: user_identity.to_string(),
+            nominal_hash_rate: 0.0,
+            max_target: [0xff; 32],
+        });
+        write_frame(stream, &mut transport.encryptor, &open.to_frame()).await?;
+        match read_message(stream, &mut transport.decryptor).await? {
+            Message::OpenStandardMiningChannelSuccess(success) => Ok((transport, success)),
+            Message::OpenMiningChannelError(error) => bail!("Pool refused to open a mining channel: {}", error.error_code),
+            other => bail!("Unexpected reply to OpenStandardMiningChannel: {:?}", other),
+        }
+    }
+
+    fn start_reader(
+        mut reader: OwnedReadHalf,
+        mut cipher: CipherState,
+        mut state: ChannelState,
+        jobs: mpsc::UnboundedSender<MiningJob>,
+        pending: PendingSubmits,
+        alive: Arc<watch::Sender<bool>>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let frame = match read_frame(&mut reader, &mut cipher).await {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        tracing::warn!("Stratum V2 connection closed: {:#}", e);
+                        break;
+                    }
+                };
+                match Message::from_frame(&frame) {
+                    Ok(Some(message)) => state.handle(message, &jobs, &pending),
+                    Ok(None) => tracing::debug!("Skipping unhandled Stratum V2 message type {:#04x}", frame.msg_type),
+                    Err(e) => tracing::warn!("Ignoring malformed Stratum V2 message {:#04x}: {}", frame.msg_type, e),
+                }
+            }
+
+            alive.send_replace(false);
+            Self::fail_pending(&pending);
+        })
+    }
+
+    fn fail_pending(pending: &PendingSubmits) {
+        for (_, waiter) in pending.lock().unwrap().drain() {
+            let _ = waiter.send(Err(StratumError::Disconnected));
+        }
+    }
+
+    pub fn channel_id(&self) -> u32 {
+        self.channel_id
+    }
+
+    /// Prefix the pool reserved for this channel inside the coinbase.
+    pub fn extranonce_prefix(&self) -> &[u8] {
+        &self.extranonce_prefix
+    }
+
+    pub fn is_connected(&self) -> bool {
+        *self.alive.borrow()
+    }
+
+    /// Resolves once the connection has been lost or shut down.
+    pub async fn closed(&self) {
+        let mut alive = self.alive.subscribe();
+        let _ = alive.wait_for(|alive| !*alive).await;
+    }
+
+    pub async fn shutdown(&self) {
+        if let Some(handle) = self.reader_task.lock().unwrap().take() {
+            handle.abort();
+        }
+        if let Some((mut writer, _)) = self.writer.lock().await.take() {
+            let _ = writer.shutdown().await;
+        }
+        self.alive.send_replace(false);
+        Self::fail_pending(&self.pending);
+    }
+
+    /// Next job ready to mine, once both `NewMiningJob` and its
+    /// `SetNewPrevHash` have arrived. `None` after the connection closed.
+    pub async fn receive_job(&self) -> Option<MiningJob> {
+        self.jobs_rx.lock().await.recv().await
+    }
+
+    /// Sends `SubmitSharesStandard` and waits for the pool's verdict. A
+    /// rejection comes back as `StratumError::Rejected`.
+    pub async fn submit(&self, job_id: &str, nonce: u32, ntime: u32) -> std::result::Result<bool, StratumError> {
+        let version = job_id.parse::<u32>().ok()
+            .and_then(|id| self.job_versions.lock().unwrap().get(&id).map(|&version| (id, version)));
+        let Some((job_id, version)) = version else {
+            return Err(StratumError::Rejected {
+                code: -1,
+                message: format!("stale or unknown job {}", job_id),
+            });
+        };
+
+        let sequence_number = self.sequence_number.fetch_add(1, Ordering::Relaxed);
+        let (waiter_tx, waiter_rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(sequence_number, waiter_tx);
+        if !self.is_connected() {
+            self.pending.lock().unwrap().remove(&sequence_number);
+            return Err(StratumError::Disconnected);
+        }
+
+        let frame = Message::SubmitSharesStandard(SubmitSharesStandard {
+            channel_id: self.channel_id,
+            sequence_number,
+            job_id,
+            nonce,
+            ntime,
+            version,
+        })
+        .to_frame();
+        if let Err(e) = self.write(&frame).await {
+            self.pending.lock().unwrap().remove(&sequence_number);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(self.request_timeout, waiter_rx).await {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(_)) => Err(StratumError::Disconnected),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&sequence_number);
+                Err(StratumError::Timeout {
+                    method: "SubmitSharesStandard".to_string(),
+                    timeout_ms: self.request_timeout.as_millis(),
+                })
+            }
+        }
+    }
+
+    async fn write(&self, frame: &codec::Frame) -> std::result::Result<(), StratumError> {
+        let mut writer_guard = self.writer.lock().await;
+        let (writer, cipher) = writer_guard.as_mut().ok_or(StratumError::Disconnected)?;
+        if let Err(e) = write_frame(writer, cipher, frame).await {
+            writer_guard.take();
+            self.alive.send_replace(false);
+            return Err(StratumError::Io(e.to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Sv2Client {
+    fn drop(&mut self) {
+        if let Ok(mut task) = self.reader_task.lock() {
+            if let Some(handle) = task.take() {
+                handle.abort();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Client, PoolConfig};
+    use self::noise::{Responder, SignatureNoiseMessage};
+    use jxpoolminer_core::Share;
+    use secp256k1::{rand, Keypair, Secp256k1, SecretKey};
+    use tokio::net::TcpListener;
+
+    const PREV_HASH: [u8; 32] = [0x11; 32];
+    const MERKLE_ROOT: [u8; 32] = [0x22; 32];
+
+    /// In-process SV2 pool: opens channel 7, announces future job 1 plus its
+    /// prev hash, then accepts even nonces and rejects odd ones.
+    async fn spawn_pool(authority: &Keypair) -> String {
+        let secp = Secp256k1::new();
+        let static_key = SecretKey::new(&mut rand::thread_rng());
+        let now = unix_now();
+        let certificate = SignatureNoiseMessage::sign(authority, &static_key.x_only_public_key(&secp).0, now - 60, now + 3600);
+        let responder = Responder::new(static_key, certificate);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut hello = [0u8; noise::INITIATOR_MESSAGE_SIZE];
+            socket.read_exact(&mut hello).await.unwrap();
+            let (reply, mut transport) = responder.accept(&hello).unwrap();
+            socket.write_all(&reply).await.unwrap();
+
+            let mut accepted = 0;
+            loop {
+                let Ok(message) = read_message(&mut socket, &mut transport.decryptor).await else {
+                    return;
+                };
+                let replies = match message {
+                    Message::SetupConnection(setup) => {
+                        assert_eq!(setup.flags, REQUIRES_STANDARD_JOBS);
+                        vec![Message::SetupConnectionSuccess(SetupConnectionSuccess { used_version: 2, flags: 0 })]
+                    }
+                    Message::OpenStandardMiningChannel(open) => {
+                        assert_eq!(open.user_identity, "wallet:rig");
+                        let mut target = jxpoolminer_core::difficulty_to_target(&SHA256_DIFF1, 4.0);
+                        target.reverse();
+                        vec![
+                            Message::OpenStandardMiningChannelSuccess(OpenStandardMiningChannelSuccess {
+                                request_id: open.request_id,
+                                channel_id: 7,
+                                target,
+                                extranonce_prefix: vec![0, 0, 0, 1],
+                                group_channel_id: 0,
+                            }),
+                            Message::NewMiningJob(NewMiningJob {
+                                channel_id: 7,
+                                job_id: 1,
+                                min_ntime: None,
+                                version: 0x20000000,
+                                merkle_root: MERKLE_ROOT.to_vec(),
+                            }),
+                            Message::SetNewPrevHash(SetNewPrevHash {
+                                channel_id: 7,
+                                job_id: 1,
+                                prev_hash: PREV_HASH,
+                                min_ntime: 0x504e86b9,
+                                nbits: 0x1c2ac4af,
+                            }),
+                        ]
+                    }
+                    Message::SubmitSharesStandard(submit) => {
+                        assert_eq!((submit.channel_id, submit.job_id, submit.version), (7, 1, 0x20000000));
+                        if submit.nonce % 2 == 0 {
+                            accepted += 1;
+                            vec![Message::SubmitSharesSuccess(SubmitSharesSuccess {
+                                channel_id: 7,
+                                last_sequence_number: submit.sequence_number,
+                                new_submits_accepted_count: 1,
+                                new_shares_sum: accepted,
+                            })]
+                        } else {
+                            vec![Message::SubmitSharesError(SubmitSharesError {
+                                channel_id: 7,
+                                sequence_number: submit.sequence_number,
+                                error_code: "invalid-nonce".to_string(),
+                            })]
+                        }
+                    }
+                    other => panic!("unexpected message {:?}", other),
+                };
+                for reply in replies {
+                    write_frame(&mut socket, &mut transport.encryptor, &reply.to_frame()).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    fn config(url: String) -> PoolConfig {
+        PoolConfig {
+            primary: url,
+            fallback: None,
+            wallet_address: "wallet".to_string(),
+            worker_name: "rig".to_string(),
+            request_timeout: Duration::from_secs(5),
+            ..PoolConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_decode_authority_key() {
+        // x coordinate of the secp256k1 generator, in hex and base58check form.
+        let generator = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let url = format!("stratum2+tcp://pool.example:3336/{}", generator);
+        let key = authority_key(&url).unwrap().unwrap();
+        assert_eq!(hex::encode(key.serialize()), generator);
+        assert_eq!(authority_key("stratum2+tcp://pool.example:3336").unwrap(), None);
+
+        let key = decode_authority_key("9bZBweHhn6px2Quf1hADVUTrWxX65vtRBweWHP66kfkKGFTQRHs").unwrap();
+        assert_eq!(hex::encode(key.serialize()), generator);
+        assert!(decode_authority_key("9bZBweHhn6px2Quf1hADVUTrWxX65vtRBweWHP66kfkKGFTQRHt").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_mines_over_stratum_v2() {
+        let authority = Keypair::new(&Secp256k1::new(), &mut rand::thread_rng());
+        let addr = spawn_pool(&authority).await;
+        let url = format!("stratum2+tcp://{}/{}", addr, hex::encode(authority.x_only_public_key().0.serialize()));
+
+        let client = Client::connect(&config(url)).await.unwrap();
+        let job = tokio::time::timeout(Duration::from_secs(5), client.receive_job()).await.unwrap().unwrap();
+
+        assert_eq!(job.id, "1");
+        assert!(job.clean_jobs);
+        assert!(!job.has_coinbase());
+        assert_eq!(job.header.len(), 76);
+        assert_eq!(&job.header[..4], &0x20000000u32.to_le_bytes());
+        assert_eq!(&job.header[4..36], &PREV_HASH);
+        assert_eq!(&job.header[36..68], &MERKLE_ROOT);
+        assert_eq!(&job.header[68..72], &0x504e86b9u32.to_le_bytes());
+        assert_eq!(&job.header[72..76], &0x1c2ac4afu32.to_le_bytes());
+        assert!((job.difficulty - 4.0).abs() < 1e-6);
+        assert!((client.difficulty().await - 4.0).abs() < 1e-6);
+
+        let share = |nonce: u64| {
+            let mut share = Share::new(job.id.clone(), nonce, vec![], "cpu-0".to_string(), job.difficulty);
+            share.ntime = job.ntime;
+            share
+        };
+        assert!(client.submit_share(share(2)).await.unwrap());
+        assert!(!client.submit_share(share(3)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_pool_signed_by_other_authority() {
+        let secp = Secp256k1::new();
+        let authority = Keypair::new(&secp, &mut rand::thread_rng());
+        let expected = Keypair::new(&secp, &mut rand::thread_rng());
+        let addr = spawn_pool(&authority).await;
+        let url = format!("stratum2+tcp://{}/{}", addr, hex::encode(expected.x_only_public_key().0.serialize()));
+
+        assert!(Client::connect(&config(url)).await.is_err());
+    }
+}
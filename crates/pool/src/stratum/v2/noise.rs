@@ -0,0 +1,392 @@
+//! Noise NX handshake and transport ciphers used by Stratum V2
+//! (`Noise_NX_Secp256k1+EllSwift_ChaChaPoly_SHA256`).
+
+use anyhow::{bail, Context, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hmac::{Hmac, Mac};
+use secp256k1::ellswift::{ElligatorSwift, ElligatorSwiftParty};
+use secp256k1::{rand, schnorr, Keypair, Message, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use sha2::{Digest, Sha256};
+
+pub const PROTOCOL_NAME: &[u8] = b"Noise_NX_Secp256k1+EllSwift_ChaChaPoly_SHA256";
+pub const ELLSWIFT_SIZE: usize = 64;
+pub const MAC_SIZE: usize = 16;
+pub const SIGNATURE_MESSAGE_SIZE: usize = 74;
+/// Initiator's `-> e`.
+pub const INITIATOR_MESSAGE_SIZE: usize = ELLSWIFT_SIZE;
+/// Responder's `<- e, ee, s, es` followed by the signed certificate.
+pub const RESPONDER_MESSAGE_SIZE: usize =
+    ELLSWIFT_SIZE + ELLSWIFT_SIZE + MAC_SIZE + SIGNATURE_MESSAGE_SIZE + MAC_SIZE;
+/// Largest Noise message, MAC included.
+pub const MAX_MESSAGE_SIZE: usize = 65535;
+
+fn hash(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+fn hmac_hash(key: &[u8; 32], data: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Noise `HKDF` with two outputs.
+fn hkdf(chaining_key: &[u8; 32], input_key_material: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let temp_key = hmac_hash(chaining_key, input_key_material);
+    let first = hmac_hash(&temp_key, &[0x01]);
+    let mut second_input = first.to_vec();
+    second_input.push(0x02);
+    (first, hmac_hash(&temp_key, &second_input))
+}
+
+/// ECDH over ElligatorSwift encodings with the BIP324 hash; `initiator` and
+/// `responder` are the two public encodings in protocol order.
+fn ecdh(initiator: ElligatorSwift, responder: ElligatorSwift, secret: SecretKey, party: ElligatorSwiftParty) -> [u8; 32] {
+    ElligatorSwift::shared_secret(initiator, responder, secret, party, None).to_secret_bytes()
+}
+
+fn ellswift_keypair<C: secp256k1::Verification>(secp: &Secp256k1<C>, secret: SecretKey) -> ElligatorSwift {
+    ElligatorSwift::from_seckey(secp, secret, Some(rand::random()))
+}
+
+/// ChaCha20-Poly1305 key with its message counter.
+pub struct CipherState {
+    key: [u8; 32],
+    nonce: u64,
+}
+
+impl CipherState {
+    fn new(key: [u8; 32]) -> Self {
+        Self { key, nonce: 0 }
+    }
+
+    fn next_nonce(&mut self) -> Result<Nonce> {
+        if self.nonce == u64::MAX {
+            bail!("Noise nonce exhausted");
+        }
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.nonce.to_le_bytes());
+        self.nonce += 1;
+        Ok(Nonce::from(nonce))
+    }
+
+    pub fn encrypt(&mut self, ad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce()?;
+        ChaCha20Poly1305::new(Key::from_slice(&self.key))
+            .encrypt(&nonce, Payload { msg: plaintext, aad: ad })
+            .map_err(|_| anyhow::anyhow!("Noise encryption failed"))
+    }
+
+    pub fn decrypt(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce()?;
+        ChaCha20Poly1305::new(Key::from_slice(&self.key))
+            .decrypt(&nonce, Payload { msg: ciphertext, aad: ad })
+            .map_err(|_| anyhow::anyhow!("Noise message failed authentication"))
+    }
+}
+
+/// Established session: one cipher per direction.
+pub struct NoiseTransport {
+    pub encryptor: CipherState,
+    pub decryptor: CipherState,
+}
+
+struct SymmetricState {
+    chaining_key: [u8; 32],
+    handshake_hash: [u8; 32],
+    cipher: Option<CipherState>,
+}
+
+impl SymmetricState {
+    fn new() -> Self {
+        let chaining_key = hash(PROTOCOL_NAME);
+        // Followed by MixHash of the empty prologue.
+        Self {
+            chaining_key,
+            handshake_hash: hash(&chaining_key),
+            cipher: None,
+        }
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let mut input = self.handshake_hash.to_vec();
+        input.extend_from_slice(data);
+        self.handshake_hash = hash(&input);
+    }
+
+    fn mix_key(&mut self, input_key_material: &[u8]) {
+        let (chaining_key, key) = hkdf(&self.chaining_key, input_key_material);
+        self.chaining_key = chaining_key;
+        self.cipher = Some(CipherState::new(key));
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let ciphertext = match self.cipher.as_mut() {
+            Some(cipher) => cipher.encrypt(&self.handshake_hash, plaintext)?,
+            None => plaintext.to_vec(),
+        };
+        self.mix_hash(&ciphertext);
+        Ok(ciphertext)
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = match self.cipher.as_mut() {
+            Some(cipher) => cipher.decrypt(&self.handshake_hash, ciphertext)?,
+            None => ciphertext.to_vec(),
+        };
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    /// Keys for (initiator → responder, responder → initiator).
+    fn split(&self) -> (CipherState, CipherState) {
+        let (first, second) = hkdf(&self.chaining_key, &[]);
+        (CipherState::new(first), CipherState::new(second))
+    }
+}
+
+/// The pool's static key signed by its authority for a validity window,
+/// sent encrypted at the end of the handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureNoiseMessage {
+    pub version: u16,
+    pub valid_from: u32,
+    pub not_valid_after: u32,
+    pub signature: [u8; 64],
+}
+
+impl SignatureNoiseMessage {
+    fn digest(version: u16, valid_from: u32, not_valid_after: u32, static_key: &XOnlyPublicKey) -> Message {
+        let mut data = Vec::with_capacity(42);
+        data.extend_from_slice(&version.to_le_bytes());
+        data.extend_from_slice(&valid_from.to_le_bytes());
+        data.extend_from_slice(&not_valid_after.to_le_bytes());
+        data.extend_from_slice(&static_key.serialize());
+        Message::from_digest(hash(&data))
+    }
+
+    pub fn sign(authority: &Keypair, static_key: &XOnlyPublicKey, valid_from: u32, not_valid_after: u32) -> Self {
+        let secp = Secp256k1::new();
+        let digest = Self::digest(0, valid_from, not_valid_after, static_key);
+        Self {
+            version: 0,
+            valid_from,
+            not_valid_after,
+            signature: *secp.sign_schnorr(&digest, authority).as_ref(),
+        }
+    }
+
+    /// Checks the signature and that `now` (unix seconds) is inside the
+    /// validity window.
+    pub fn verify(&self, static_key: &XOnlyPublicKey, authority: &XOnlyPublicKey, now: u32) -> Result<()> {
+        if now < self.valid_from || now > self.not_valid_after {
+            bail!(
+                "Pool certificate not valid at {} (valid {}..={})",
+                now, self.valid_from, self.not_valid_after
+            );
+        }
+        let signature = schnorr::Signature::from_slice(&self.signature)
+            .context("Malformed pool certificate signature")?;
+        let digest = Self::digest(self.version, self.valid_from, self.not_valid_after, static_key);
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &digest, authority)
+            .context("Pool certificate not signed by the configured authority")
+    }
+
+    pub fn to_bytes(&self) -> [u8; SIGNATURE_MESSAGE_SIZE] {
+        let mut bytes = [0u8; SIGNATURE_MESSAGE_SIZE];
+        bytes[0..2].copy_from_slice(&self.version.to_le_bytes());
+        bytes[2..6].copy_from_slice(&self.valid_from.to_le_bytes());
+        bytes[6..10].copy_from_slice(&self.not_valid_after.to_le_bytes());
+        bytes[10..].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != SIGNATURE_MESSAGE_SIZE {
+            bail!("Pool certificate has {} bytes, expected {}", bytes.len(), SIGNATURE_MESSAGE_SIZE);
+        }
+        let mut signature = [0u8; 64];
+        signature.copy_from_slice(&bytes[10..]);
+        Ok(Self {
+            version: u16::from_le_bytes([bytes[0], bytes[1]]),
+            valid_from: u32::from_le_bytes(bytes[2..6].try_into().unwrap()),
+            not_valid_after: u32::from_le_bytes(bytes[6..10].try_into().unwrap()),
+            signature,
+        })
+    }
+}
+
+/// Client side of the handshake.
+pub struct Initiator {
+    state: SymmetricState,
+    ephemeral: SecretKey,
+    ephemeral_public: ElligatorSwift,
+    authority: Option<XOnlyPublicKey>,
+}
+
+impl Initiator {
+    /// Without an `authority` the pool's certificate is not checked, which
+    /// leaves the session open to a man in the middle.
+    pub fn new(authority: Option<XOnlyPublicKey>) -> Self {
+        let secp = Secp256k1::new();
+        let ephemeral = SecretKey::new(&mut rand::thread_rng());
+        Self {
+            state: SymmetricState::new(),
+            ephemeral,
+            ephemeral_public: ellswift_keypair(&secp, ephemeral),
+            authority,
+        }
+    }
+
+    /// First handshake message: `-> e`.
+    pub fn hello(&mut self) -> Result<[u8; INITIATOR_MESSAGE_SIZE]> {
+        let ephemeral = self.ephemeral_public.to_array();
+        self.state.mix_hash(&ephemeral);
+        self.state.encrypt_and_hash(&[])?;
+        Ok(ephemeral)
+    }
+
+    /// Processes the responder's reply and derives the session keys.
+    /// `now` is the current unix time used to check the certificate.
+    pub fn finish(mut self, reply: &[u8], now: u32) -> Result<NoiseTransport> {
+        if reply.len() != RESPONDER_MESSAGE_SIZE {
+            bail!("Noise handshake reply has {} bytes, expected {}", reply.len(), RESPONDER_MESSAGE_SIZE);
+        }
+        let (remote_ephemeral, rest) = reply.split_at(ELLSWIFT_SIZE);
+        let (encrypted_static, encrypted_certificate) = rest.split_at(ELLSWIFT_SIZE + MAC_SIZE);
+
+        let remote_ephemeral = ElligatorSwift::from_array(remote_ephemeral.try_into().unwrap());
+        self.state.mix_hash(&remote_ephemeral.to_array());
+        self.state.mix_key(&ecdh(self.ephemeral_public, remote_ephemeral, self.ephemeral, ElligatorSwiftParty::A));
+
+        let remote_static = self.state.decrypt_and_hash(encrypted_static)?;
+        let remote_static = ElligatorSwift::from_array(remote_static.try_into().unwrap());
+        self.state.mix_key(&ecdh(self.ephemeral_public, remote_static, self.ephemeral, ElligatorSwiftParty::A));
+
+        let certificate = self.state.decrypt_and_hash(encrypted_certificate)?;
+        let certificate = SignatureNoiseMessage::from_bytes(&certificate)?;
+        match self.authority {
+            Some(authority) => {
+                let (static_key, _) = PublicKey::from_ellswift(remote_static).x_only_public_key();
+                certificate.verify(&static_key, &authority, now)?;
+            }
+            None => tracing::warn!("No pool authority key configured; Stratum V2 pool identity not verified"),
+        }
+
+        let (encryptor, decryptor) = self.state.split();
+        Ok(NoiseTransport { encryptor, decryptor })
+    }
+}
+
+/// Pool side of the handshake.
+pub struct Responder {
+    static_key: SecretKey,
+    certificate: SignatureNoiseMessage,
+}
+
+impl Responder {
+    pub fn new(static_key: SecretKey, certificate: SignatureNoiseMessage) -> Self {
+        Self { static_key, certificate }
+    }
+
+    /// Answers the initiator's `hello`, returning the reply to send and the
+    /// session keys.
+    pub fn accept(&self, hello: &[u8]) -> Result<([u8; RESPONDER_MESSAGE_SIZE], NoiseTransport)> {
+        if hello.len() != INITIATOR_MESSAGE_SIZE {
+            bail!("Noise handshake hello has {} bytes, expected {}", hello.len(), INITIATOR_MESSAGE_SIZE);
+        }
+        let secp = Secp256k1::new();
+        let mut state = SymmetricState::new();
+        let remote_ephemeral = ElligatorSwift::from_array(hello.try_into().unwrap());
+        state.mix_hash(hello);
+        state.decrypt_and_hash(&[])?;
+
+        let ephemeral = SecretKey::new(&mut rand::thread_rng());
+        let ephemeral_public = ellswift_keypair(&secp, ephemeral);
+        let static_public = ellswift_keypair(&secp, self.static_key);
+
+        let mut reply = [0u8; RESPONDER_MESSAGE_SIZE];
+        reply[..ELLSWIFT_SIZE].copy_from_slice(&ephemeral_public.to_array());
+        state.mix_hash(&ephemeral_public.to_array());
+        state.mix_key(&ecdh(remote_ephemeral, ephemeral_public, ephemeral, ElligatorSwiftParty::B));
+
+        let encrypted_static = state.encrypt_and_hash(&static_public.to_array())?;
+        reply[ELLSWIFT_SIZE..2 * ELLSWIFT_SIZE + MAC_SIZE].copy_from_slice(&encrypted_static);
+        state.mix_key(&ecdh(remote_ephemeral, static_public, self.static_key, ElligatorSwiftParty::B));
+
+        let encrypted_certificate = state.encrypt_and_hash(&self.certificate.to_bytes())?;
+        reply[2 * ELLSWIFT_SIZE + MAC_SIZE..].copy_from_slice(&encrypted_certificate);
+
+        let (decryptor, encryptor) = state.split();
+        Ok((reply, NoiseTransport { encryptor, decryptor }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u32 = 1_700_000_000;
+
+    fn responder(authority: &Keypair, valid_from: u32, not_valid_after: u32) -> Responder {
+        let static_key = SecretKey::new(&mut rand::thread_rng());
+        let (static_public, _) = static_key.x_only_public_key(&Secp256k1::new());
+        let certificate = SignatureNoiseMessage::sign(authority, &static_public, valid_from, not_valid_after);
+        Responder::new(static_key, certificate)
+    }
+
+    fn handshake(authority: Option<XOnlyPublicKey>, responder: &Responder) -> Result<(NoiseTransport, NoiseTransport)> {
+        let mut initiator = Initiator::new(authority);
+        let hello = initiator.hello()?;
+        let (reply, pool) = responder.accept(&hello)?;
+        Ok((initiator.finish(&reply, NOW)?, pool))
+    }
+
+    #[test]
+    fn test_handshake_derives_matching_keys() {
+        let authority = Keypair::new(&Secp256k1::new(), &mut rand::thread_rng());
+        let responder = responder(&authority, NOW - 60, NOW + 60);
+        let (mut miner, mut pool) = handshake(Some(authority.x_only_public_key().0), &responder).unwrap();
+
+        let ciphertext = miner.encryptor.encrypt(&[], b"SetupConnection").unwrap();
+        assert_eq!(ciphertext.len(), 15 + MAC_SIZE);
+        assert_eq!(pool.decryptor.decrypt(&[], &ciphertext).unwrap(), b"SetupConnection");
+
+        let ciphertext = pool.encryptor.encrypt(&[], b"Success").unwrap();
+        assert_eq!(miner.decryptor.decrypt(&[], &ciphertext).unwrap(), b"Success");
+
+        // Counters advance, so a replayed message no longer authenticates.
+        assert!(miner.decryptor.decrypt(&[], &ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_rejects_certificate_from_other_authority() {
+        let secp = Secp256k1::new();
+        let authority = Keypair::new(&secp, &mut rand::thread_rng());
+        let impostor = Keypair::new(&secp, &mut rand::thread_rng());
+        let responder = responder(&impostor, NOW - 60, NOW + 60);
+
+        assert!(handshake(Some(authority.x_only_public_key().0), &responder).is_err());
+        assert!(handshake(None, &responder).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_expired_certificate() {
+        let authority = Keypair::new(&Secp256k1::new(), &mut rand::thread_rng());
+        let responder = responder(&authority, NOW - 120, NOW - 60);
+        let error = handshake(Some(authority.x_only_public_key().0), &responder).err().unwrap();
+        assert!(error.to_string().contains("not valid"));
+    }
+
+    #[test]
+    fn test_tampered_reply_fails() {
+        let authority = Keypair::new(&Secp256k1::new(), &mut rand::thread_rng());
+        let responder = responder(&authority, NOW - 60, NOW + 60);
+        let mut initiator = Initiator::new(Some(authority.x_only_public_key().0));
+        let (mut reply, _) = responder.accept(&initiator.hello().unwrap()).unwrap();
+        reply[100] ^= 0x01;
+        assert!(initiator.finish(&reply, NOW).is_err());
+    }
+}
@@ -0,0 +1,512 @@
+//! Keeps a pool session alive: detects socket loss, reconnects with
+//! exponential backoff, fails over to the fallback pool and fails back to
+//! the primary once it is reachable again.
+
+use crate::{Client, PoolConfig, stratum::{self, StratumClient, StratumResponse, SubscribeResult, v2::{self, Sv2Client}}};
+use jxpoolminer_core::MiningJob;
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{watch, Notify, RwLock};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Dialing a pool and running subscribe/authorize.
+    Connecting,
+    /// Session established with the primary pool.
+    Connected,
+    /// Session established with the fallback pool.
+    FailedOver,
+    /// No session; waiting out the backoff before the next attempt.
+    Disconnected,
+}
+
+impl ConnectionState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionState::Connected | ConnectionState::FailedOver)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConnectionState::Connecting => "Connecting",
+            ConnectionState::Connected => "Connected",
+            ConnectionState::FailedOver => "Connected (fallback)",
+            ConnectionState::Disconnected => "Disconnected",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay after the first failed attempt; doubled on every further failure.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Consecutive failures against one pool before switching to the other.
+    pub failover_after: u32,
+    /// How often the primary is probed while mining on the fallback.
+    pub failback_interval: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            failover_after: 3,
+            failback_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Backoff to wait after `failures` consecutive failed attempts.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(16);
+        self.initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff)
+    }
+}
+
+/// Protocol-specific connection behind a session.
+#[derive(Clone)]
+pub(crate) enum Transport {
+    V1(Arc<StratumClient>),
+    V2(Arc<Sv2Client>),
+}
+
+pub(crate) enum PoolEvent {
+    /// V1 notification, still to be interpreted.
+    Message(StratumResponse),
+    /// V2 job, complete with its target.
+    Job(MiningJob),
+}
+
+impl Transport {
+    async fn receive(&self) -> Option<PoolEvent> {
+        match self {
+            Transport::V1(stratum) => stratum.receive().await.map(PoolEvent::Message),
+            Transport::V2(sv2) => sv2.receive_job().await.map(PoolEvent::Job),
+        }
+    }
+
+    async fn closed(&self) {
+        match self {
+            Transport::V1(stratum) => stratum.closed().await,
+            Transport::V2(sv2) => sv2.closed().await,
+        }
+    }
+
+    async fn shutdown(&self) {
+        match self {
+            Transport::V1(stratum) => stratum.shutdown().await,
+            Transport::V2(sv2) => sv2.shutdown().await,
+        }
+    }
+}
+
+pub(crate) struct Session {
+    pub transport: Transport,
+    pub url: String,
+    pub on_fallback: bool,
+    pub subscription: SubscribeResult,
+}
+
+/// Dials `url` and runs the subscribe/authorize handshake, or for
+/// `stratum2+tcp://` URLs the V2 setup and channel opening.
+pub(crate) async fn open_session(config: &PoolConfig, url: &str, on_fallback: bool) -> Result<Session> {
+    if v2::is_v2_url(url) {
+        let sv2 = Sv2Client::connect(url, &config.username(), config.request_timeout).await?;
+        return Ok(Session {
+            transport: Transport::V2(Arc::new(sv2)),
+            url: url.to_string(),
+            on_fallback,
+            subscription: SubscribeResult::default(),
+        });
+    }
+    
+    let mut stratum = StratumClient::with_request_timeout(config.request_timeout);
+    if let Some(tls) = config.tls_for(url) {
+        stratum = stratum.with_tls(tls);
+    }
+    let stratum = Arc::new(stratum);
+    stratum.connect(url).await?;
+    
+    let worker = config.username();
+    let ethash = !stratum::job::has_bitcoin_header(&config.algorithm);
+    let subscription = if ethash {
+        stratum.subscribe_ethash("JxPoolMiner/1.0.0").await?
+    } else {
+        stratum.subscribe("JxPoolMiner/1.0.0").await?
+    };
+    tracing::debug!(
+        "Subscribed to {}: extranonce1={}, extranonce2_size={}",
+        url, hex::encode(&subscription.extranonce1), subscription.extranonce2_size
+    );
+    if !stratum.authorize(&worker, "x").await? {
+        anyhow::bail!("Pool rejected authorization for worker {}", worker);
+    }
+    if config.extranonce_subscribe && !ethash {
+        if let Err(e) = stratum.extranonce_subscribe().await {
+            tracing::debug!("Pool does not support mining.extranonce.subscribe: {}", e);
+        }
+    }
+    
+    Ok(Session {
+        transport: Transport::V1(stratum),
+        url: url.to_string(),
+        on_fallback,
+        subscription,
+    })
+}
+
+async fn probe_pool(url: &str) -> bool {
+    matches!(
+        tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(stratum::pool_address(url))).await,
+        Ok(Ok(_))
+    )
+}
+
+pub(crate) struct Supervisor {
+    pub config: PoolConfig,
+    pub state: watch::Sender<ConnectionState>,
+    pub session: Arc<RwLock<Option<Transport>>>,
+    pub active_url: Arc<RwLock<Option<String>>>,
+    pub difficulty: Arc<RwLock<f64>>,
+    pub extranonce: Arc<RwLock<SubscribeResult>>,
+    pub current_job: Arc<watch::Sender<Option<MiningJob>>>,
+    pub reconnect: Arc<Notify>,
+}
+
+impl Supervisor {
+    pub async fn run(self, mut session: Session) {
+        loop {
+            self.install(&session).await;
+            self.serve(&session).await;
+            
+            *self.session.write().await = None;
+            *self.active_url.write().await = None;
+            // Jobs are tied to the session's extranonce1, so none carry over.
+            self.current_job.send_replace(None);
+            session.transport.shutdown().await;
+            
+            session = self.reestablish().await;
+        }
+    }
+    
+    async fn install(&self, session: &Session) {
+        *self.session.write().await = Some(session.transport.clone());
+        *self.active_url.write().await = Some(session.url.clone());
+        // Difficulty is per session; pools resend it after subscribing.
+        *self.difficulty.write().await = 1.0;
+        *self.extranonce.write().await = session.subscription.clone();
+        self.state.send_replace(if session.on_fallback {
+            ConnectionState::FailedOver
+        } else {
+            ConnectionState::Connected
+        });
+        tracing::info!("Pool session established: {}", session.url);
+    }
+    
+    /// Pumps pool messages until the session has to be replaced.
+    async fn serve(&self, session: &Session) {
+        let interval = self.config.reconnect.failback_interval;
+        let mut probe = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        
+        loop {
+            tokio::select! {
+                biased;
+                event = session.transport.receive() => match event {
+                    Some(PoolEvent::Message(message)) => {
+                        Client::handle_message(message, &self.config.algorithm, self.config.algorithm_version, &self.extranonce, &self.difficulty, &self.current_job).await
+                    }
+                    Some(PoolEvent::Job(job)) => {
+                        tracing::info!("Received new mining job: {} (clean={})", job.id, job.clean_jobs);
+                        *self.difficulty.write().await = job.difficulty;
+                        self.current_job.send_replace(Some(job));
+                    }
+                    None => {
+                        tracing::warn!("Lost connection to pool: {}", session.url);
+                        return;
+                    }
+                },
+                _ = session.transport.closed() => {
+                    tracing::warn!("Lost connection to pool: {}", session.url);
+                    return;
+                }
+                _ = self.reconnect.notified() => {
+                    tracing::info!("Reconnect requested for pool: {}", session.url);
+                    return;
+                }
+                _ = probe.tick(), if session.on_fallback => {
+                    if probe_pool(&self.config.primary).await {
+                        tracing::info!("Primary pool reachable again, failing back: {}", self.config.primary);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Retries until a new session is up. Each pool gets `failover_after`
+    /// attempts before switching to the other one, starting with the primary.
+    async fn reestablish(&self) -> Session {
+        let policy = &self.config.reconnect;
+        let mut failures = 0u32;
+        
+        loop {
+            let round = failures / policy.failover_after.max(1);
+            let (url, on_fallback) = match &self.config.fallback {
+                Some(fallback) if round % 2 == 1 => (fallback.as_str(), true),
+                _ => (self.config.primary.as_str(), false),
+            };
+            
+            self.state.send_replace(ConnectionState::Connecting);
+            match open_session(&self.config, url, on_fallback).await {
+                Ok(session) => return session,
+                Err(e) => {
+                    failures += 1;
+                    let delay = policy.backoff(failures);
+                    tracing::warn!(
+                        "Pool connection to {} failed ({}), attempt {}; retrying in {:?}",
+                        url, e, failures, delay
+                    );
+                    self.state.send_replace(ConnectionState::Disconnected);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use jxpoolminer_core::{Algorithm, Share};
+    use tokio::net::TcpListener;
+
+    fn test_policy() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(40),
+            failover_after: 2,
+            failback_interval: Duration::from_millis(50),
+        }
+    }
+
+    /// Answers every request with `true`; drops each connection after the
+    /// handshake when `drop_after_handshake` is set.
+    async fn spawn_pool(listener: TcpListener, drop_after_handshake: bool) -> Arc<AtomicUsize> {
+        let subscribes = Arc::new(AtomicUsize::new(0));
+        let counter = subscribes.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = socket.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                        let result = if request["method"] == "mining.subscribe" {
+                            serde_json::json!([[], "00000000", 4])
+                        } else {
+                            serde_json::json!(true)
+                        };
+                        let reply = format!("{}\n", serde_json::json!({"id": request["id"], "result": result, "error": null}));
+                        writer.write_all(reply.as_bytes()).await.unwrap();
+                        if request["method"] == "mining.subscribe" {
+                            counter.fetch_add(1, Ordering::SeqCst);
+                        }
+                        if drop_after_handshake && request["method"] == "mining.authorize" {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        subscribes
+    }
+
+    async fn closed_port_url() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        format!("stratum+tcp://{}", listener.local_addr().unwrap())
+    }
+
+    async fn wait_for_state(client: &Client, wanted: ConnectionState) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while client.state() != wanted {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("state never became {:?}", wanted));
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let policy = test_policy();
+        assert_eq!(policy.backoff(1), Duration::from_millis(10));
+        assert_eq!(policy.backoff(2), Duration::from_millis(20));
+        assert_eq!(policy.backoff(3), Duration::from_millis(40));
+        assert_eq!(policy.backoff(30), Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn test_job_is_cleared_when_session_drops() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("stratum+tcp://{}", listener.local_addr().unwrap());
+        // Serves one session, sending a job after the handshake, then goes away.
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            drop(listener);
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                let result = if request["method"] == "mining.subscribe" {
+                    serde_json::json!([[], "00000000", 4])
+                } else {
+                    serde_json::json!(true)
+                };
+                let reply = format!("{}\n", serde_json::json!({"id": request["id"], "result": result, "error": null}));
+                writer.write_all(reply.as_bytes()).await.unwrap();
+                if request["method"] == "mining.extranonce.subscribe" {
+                    let notify = serde_json::json!({
+                        "id": null,
+                        "method": "mining.notify",
+                        "params": ["a1", "00".repeat(32), "01", "02", [], "00000002", "1c2ac4af", "504e86b9", true],
+                    });
+                    writer.write_all(format!("{}\n", notify).as_bytes()).await.unwrap();
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    return;
+                }
+            }
+        });
+
+        let config = PoolConfig {
+            primary: url,
+            fallback: None,
+            reconnect: test_policy(),
+            ..PoolConfig::default()
+        };
+        let client = Client::connect(&config).await.unwrap();
+        let job = tokio::time::timeout(Duration::from_secs(5), client.receive_job()).await.expect("no job").unwrap();
+        assert_eq!(job.id, "a1");
+
+        let mut jobs = client.jobs();
+        tokio::time::timeout(Duration::from_secs(5), jobs.wait_for(Option::is_none))
+            .await
+            .expect("job was not cleared")
+            .unwrap();
+        assert!(!client.is_connected().await);
+    }
+
+    #[tokio::test]
+    async fn test_ethash_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("stratum+tcp://{}", listener.local_addr().unwrap());
+        let (submits_tx, mut submits) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = socket.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                assert_ne!(request["method"], "mining.extranonce.subscribe");
+                if request["method"] == "mining.submit" {
+                    submits_tx.send(request["params"].clone()).unwrap();
+                }
+                let reply = format!("{}\n", serde_json::json!({"id": request["id"], "result": true, "error": null}));
+                writer.write_all(reply.as_bytes()).await.unwrap();
+                if request["method"] == "mining.authorize" {
+                    let notify = serde_json::json!({
+                        "id": null,
+                        "method": "mining.notify",
+                        "params": ["e1", format!("0x{}", "11".repeat(32)), format!("0x{}", "22".repeat(32)), "0x0000ffff"],
+                    });
+                    writer.write_all(format!("{}\n", notify).as_bytes()).await.unwrap();
+                }
+            }
+        });
+
+        let config = PoolConfig {
+            primary: url,
+            fallback: None,
+            reconnect: test_policy(),
+            algorithm: Algorithm::ETHASH,
+            ..PoolConfig::default()
+        };
+        let client = Client::connect(&config).await.unwrap();
+        let job = tokio::time::timeout(Duration::from_secs(5), client.receive_job()).await.expect("no job").unwrap();
+        assert_eq!(job.header, vec![0x11; 32]);
+        assert_eq!(job.seed_hash, vec![0x22; 32]);
+        assert_eq!(job.target[28..], [0, 0, 0xff, 0xff]);
+        assert_eq!(job.extranonce2_size, 4);
+
+        let mut share = Share::new(job.id.clone(), 0x0000_0001_0000_0002, vec![], "cpu-0".to_string(), job.difficulty);
+        share.header_hash = Some(job.header.clone());
+        share.mix_digest = Some(vec![0x33; 32]);
+        assert!(client.submit_share(share.clone()).await.unwrap());
+        let expected = serde_json::json!([
+            config.username(), "e1", "0x0000000100000002", format!("0x{}", "11".repeat(32)), format!("0x{}", "33".repeat(32)),
+        ]);
+        assert_eq!(submits.recv().await.unwrap(), expected);
+
+        share.mix_digest = None;
+        assert!(client.submit_share(share).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resubscribes_after_connection_loss() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("stratum+tcp://{}", listener.local_addr().unwrap());
+        let subscribes = spawn_pool(listener, true).await;
+        
+        let config = PoolConfig {
+            primary: url,
+            fallback: None,
+            reconnect: test_policy(),
+            ..PoolConfig::default()
+        };
+        let client = Client::connect(&config).await.unwrap();
+        
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while subscribes.load(Ordering::SeqCst) < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("client did not reconnect");
+        drop(client);
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_and_back() {
+        let primary = closed_port_url().await;
+        let fallback_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let fallback = format!("stratum+tcp://{}", fallback_listener.local_addr().unwrap());
+        spawn_pool(fallback_listener, false).await;
+        
+        let config = PoolConfig {
+            primary: primary.clone(),
+            fallback: Some(fallback.clone()),
+            reconnect: test_policy(),
+            ..PoolConfig::default()
+        };
+        let client = Client::connect(&config).await.unwrap();
+        assert_eq!(client.state(), ConnectionState::FailedOver);
+        assert!(client.is_connected().await);
+        assert_eq!(client.active_pool().await, Some(fallback));
+        
+        let primary_listener = TcpListener::bind(stratum::pool_address(&primary)).await.unwrap();
+        spawn_pool(primary_listener, false).await;
+        
+        wait_for_state(&client, ConnectionState::Connected).await;
+        assert_eq!(client.active_pool().await, Some(primary));
+    }
+}
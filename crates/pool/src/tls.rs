@@ -0,0 +1,186 @@
+//! TLS transport for `stratum+ssl://` / `stratum+tls://` pools, or any pool
+//! when `PoolConfig::use_tls` is set.
+
+use anyhow::{bail, Context, Result};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+pub const SCHEMES: [&str; 2] = ["stratum+ssl://", "stratum+tls://"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsConfig {
+    /// PEM bundle of CAs to trust instead of the built-in web roots.
+    pub ca_file: Option<PathBuf>,
+    /// SHA-256 fingerprint of the pool's certificate (hex, colons allowed).
+    /// A matching certificate is accepted even when no CA vouches for it.
+    pub pinned_sha256: Option<String>,
+    /// Accept any certificate. Only meant for self-signed local pools.
+    pub insecure: bool,
+}
+
+pub fn is_tls_url(url: &str) -> bool {
+    SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+}
+
+/// Lowercase hex SHA-256 of a DER certificate.
+pub fn fingerprint(certificate: &[u8]) -> String {
+    hex::encode(Sha256::digest(certificate))
+}
+
+fn parse_fingerprint(fingerprint: &str) -> Result<[u8; 32]> {
+    let digits: String = fingerprint.chars().filter(|c| *c != ':').collect();
+    hex::decode(&digits)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .with_context(|| format!("Invalid SHA-256 certificate fingerprint: {}", fingerprint))
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn load_roots(tls: &TlsConfig) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    match &tls.ca_file {
+        Some(path) => {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read CA bundle {}", path.display()))?;
+            for certificate in rustls_pemfile::certs(&mut pem.as_slice()) {
+                roots.add(certificate.context("Malformed certificate in CA bundle")?)
+                    .with_context(|| format!("Unusable certificate in CA bundle {}", path.display()))?;
+            }
+            if roots.is_empty() {
+                bail!("CA bundle {} contains no certificates", path.display());
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    Ok(roots)
+}
+
+/// Verifier for pinned or insecure mode: the chain is not checked, only the
+/// pin (if any) and the handshake signatures.
+#[derive(Debug)]
+struct PinnedVerifier {
+    pin: Option<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        match self.pin {
+            Some(pin) if Sha256::digest(end_entity.as_ref()).as_slice() != pin => {
+                tracing::error!(
+                    "Pool certificate fingerprint {} does not match the pinned one",
+                    fingerprint(end_entity.as_ref())
+                );
+                Err(rustls::Error::General("certificate fingerprint mismatch".to_string()))
+            }
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, certificate, signature, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        certificate: &CertificateDer<'_>,
+        signature: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, certificate, signature, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+pub fn client_config(tls: &TlsConfig) -> Result<Arc<ClientConfig>> {
+    let provider = provider();
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .context("No usable TLS protocol versions")?;
+
+    let config = if tls.insecure || tls.pinned_sha256.is_some() {
+        let pin = tls.pinned_sha256.as_deref().map(parse_fingerprint).transpose()?;
+        if pin.is_none() {
+            tracing::warn!("TLS certificate verification disabled for pool connection");
+        }
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedVerifier { pin, provider }))
+            .with_no_client_auth()
+    } else {
+        builder.with_root_certificates(load_roots(tls)?).with_no_client_auth()
+    };
+    Ok(Arc::new(config))
+}
+
+/// Runs the TLS handshake over `stream`; `address` is the `host:port` that
+/// was dialed and supplies the server name.
+pub async fn connect(stream: TcpStream, address: &str, tls: &TlsConfig) -> Result<TlsStream<TcpStream>> {
+    let host = address.rsplit_once(':').map_or(address, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let server_name = ServerName::try_from(host.to_string())
+        .with_context(|| format!("Invalid TLS server name: {}", host))?;
+
+    TlsConnector::from(client_config(tls)?)
+        .connect(server_name, stream)
+        .await
+        .with_context(|| format!("TLS handshake with {} failed", address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let hex = "ab".repeat(32);
+        let colons = vec!["AB"; 32].join(":");
+        assert_eq!(parse_fingerprint(&hex).unwrap(), [0xab; 32]);
+        assert_eq!(parse_fingerprint(&colons).unwrap(), [0xab; 32]);
+        assert!(parse_fingerprint("abcd").is_err());
+        assert!(parse_fingerprint(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_tls_schemes() {
+        assert!(is_tls_url("stratum+ssl://pool.example:443"));
+        assert!(is_tls_url("stratum+tls://pool.example:443"));
+        assert!(!is_tls_url("stratum+tcp://pool.example:3333"));
+        assert_eq!(crate::stratum::pool_address("stratum+ssl://pool.example:443"), "pool.example:443");
+    }
+
+    #[test]
+    fn test_missing_ca_bundle_is_an_error() {
+        let tls = TlsConfig {
+            ca_file: Some(PathBuf::from("/nonexistent/ca.pem")),
+            ..TlsConfig::default()
+        };
+        assert!(client_config(&tls).is_err());
+    }
+}
@@ -8,8 +8,17 @@ pub struct DeviceStats {
     pub hashrate: f64,
     pub shares_accepted: u64,
     pub shares_rejected: u64,
+    /// Shares the miner found but discarded after they failed verification.
+    pub hardware_errors: u64,
+    /// Hashrate implied by the difficulty of accepted shares.
+    pub effective_hashrate: f64,
     pub temperature: f32,
     pub power_usage: f32,
+    /// Fan speed in RPM.
+    pub fan_speed: u32,
+    /// Core and memory clocks in MHz.
+    pub core_clock: f32,
+    pub memory_clock: f32,
     pub uptime: u64,
 }
 
@@ -19,8 +28,13 @@ impl Default for DeviceStats {
             hashrate: 0.0,
             shares_accepted: 0,
             shares_rejected: 0,
+            hardware_errors: 0,
+            effective_hashrate: 0.0,
             temperature: 0.0,
             power_usage: 0.0,
+            fan_speed: 0,
+            core_clock: 0.0,
+            memory_clock: 0.0,
             uptime: 0,
         }
     }
@@ -32,10 +46,31 @@ pub struct HashratePoint {
     pub hashrate: f64,
 }
 
+/// Something that happened to a device, such as a thermal throttle.
+#[derive(Debug, Clone)]
+pub struct DeviceEvent {
+    pub timestamp: DateTime<Utc>,
+    pub device_id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+struct WorkPoint {
+    timestamp: DateTime<Utc>,
+    work: f64,
+}
+
+/// Window over which accepted share work is averaged.
+const EFFECTIVE_HASHRATE_WINDOW_SECS: i64 = 600;
+/// Device events kept, newest last.
+const MAX_EVENTS: usize = 100;
+
 pub struct Collector {
     device_hashrate_history: Arc<RwLock<HashMap<String, VecDeque<HashratePoint>>>>,
+    accepted_work: Arc<RwLock<HashMap<String, VecDeque<WorkPoint>>>>,
     device_stats: Arc<RwLock<HashMap<String, DeviceStats>>>,
     global_stats: Arc<RwLock<GlobalStats>>,
+    events: Arc<RwLock<VecDeque<DeviceEvent>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -59,12 +94,20 @@ impl Default for GlobalStats {
     }
 }
 
+impl Default for Collector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Collector {
     pub fn new() -> Self {
         Self {
             device_hashrate_history: Arc::new(RwLock::new(HashMap::new())),
+            accepted_work: Arc::new(RwLock::new(HashMap::new())),
             device_stats: Arc::new(RwLock::new(HashMap::new())),
             global_stats: Arc::new(RwLock::new(GlobalStats::default())),
+            events: Arc::new(RwLock::new(VecDeque::new())),
         }
     }
     
@@ -90,7 +133,15 @@ impl Collector {
             .hashrate = hashrate;
     }
     
-    pub async fn record_share(&self, device_id: &str, accepted: bool) {
+    /// Records a pool verdict. `work` is the expected number of hashes behind
+    /// the share, i.e. `MiningAlgorithm::work_for_difficulty(share.difficulty)`.
+    pub async fn record_share(&self, device_id: &str, accepted: bool, work: f64) {
+        let effective_hashrate = if accepted {
+            Some(self.add_accepted_work(device_id, work).await)
+        } else {
+            None
+        };
+        
         let mut stats = self.device_stats.write().await;
         let device_stats = stats.entry(device_id.to_string())
             .or_insert_with(DeviceStats::default);
@@ -100,6 +151,9 @@ impl Collector {
         } else {
             device_stats.shares_rejected += 1;
         }
+        if let Some(effective_hashrate) = effective_hashrate {
+            device_stats.effective_hashrate = effective_hashrate;
+        }
         
         let mut global = self.global_stats.write().await;
         global.total_shares += 1;
@@ -110,6 +164,28 @@ impl Collector {
         }
     }
     
+    /// Adds accepted work and returns the average hashrate it implies over
+    /// the window (or since start-up, if that is shorter).
+    async fn add_accepted_work(&self, device_id: &str, work: f64) -> f64 {
+        let now = Utc::now();
+        let window_start = now - chrono::Duration::seconds(EFFECTIVE_HASHRATE_WINDOW_SECS);
+        
+        let mut all_work = self.accepted_work.write().await;
+        let device_work = all_work.entry(device_id.to_string())
+            .or_insert_with(VecDeque::new);
+        device_work.push_back(WorkPoint {
+            timestamp: now,
+            work,
+        });
+        while device_work.front().is_some_and(|point| point.timestamp < window_start) {
+            device_work.pop_front();
+        }
+        
+        let start_time = self.global_stats.read().await.start_time;
+        let elapsed = (now - start_time.max(window_start)).num_milliseconds().max(1000) as f64 / 1000.0;
+        device_work.iter().map(|point| point.work).sum::<f64>() / elapsed
+    }
+    
     pub async fn update_temperature(&self, device_id: &str, temperature: f32) {
         let mut stats = self.device_stats.write().await;
         stats.entry(device_id.to_string())
@@ -117,6 +193,13 @@ impl Collector {
             .temperature = temperature;
     }
     
+    pub async fn update_hardware_errors(&self, device_id: &str, hardware_errors: u64) {
+        let mut stats = self.device_stats.write().await;
+        stats.entry(device_id.to_string())
+            .or_insert_with(DeviceStats::default)
+            .hardware_errors = hardware_errors;
+    }
+    
     pub async fn update_power(&self, device_id: &str, power: f32) {
         let mut stats = self.device_stats.write().await;
         stats.entry(device_id.to_string())
@@ -124,11 +207,47 @@ impl Collector {
             .power_usage = power;
     }
     
+    pub async fn update_fan_speed(&self, device_id: &str, rpm: u32) {
+        let mut stats = self.device_stats.write().await;
+        stats.entry(device_id.to_string())
+            .or_insert_with(DeviceStats::default)
+            .fan_speed = rpm;
+    }
+    
+    pub async fn update_clocks(&self, device_id: &str, core_mhz: f32, memory_mhz: f32) {
+        let mut stats = self.device_stats.write().await;
+        let device = stats.entry(device_id.to_string()).or_insert_with(DeviceStats::default);
+        device.core_clock = core_mhz;
+        device.memory_clock = memory_mhz;
+    }
+    
+    pub async fn record_event(&self, device_id: &str, message: impl Into<String>) {
+        let mut events = self.events.write().await;
+        events.push_back(DeviceEvent {
+            timestamp: Utc::now(),
+            device_id: device_id.to_string(),
+            message: message.into(),
+        });
+        while events.len() > MAX_EVENTS {
+            events.pop_front();
+        }
+    }
+    
+    /// Recent device events, oldest first.
+    pub async fn events(&self) -> Vec<DeviceEvent> {
+        self.events.read().await.iter().cloned().collect()
+    }
+    
     pub async fn total_hashrate(&self) -> f64 {
         let stats = self.device_stats.read().await;
         stats.values().map(|s| s.hashrate).sum()
     }
     
+    pub async fn total_effective_hashrate(&self) -> f64 {
+        let stats = self.device_stats.read().await;
+        stats.values().map(|s| s.effective_hashrate).sum()
+    }
+    
     pub async fn device_stats(&self, device_id: &str) -> Option<DeviceStats> {
         let stats = self.device_stats.read().await;
         stats.get(device_id).cloned()
@@ -8,8 +8,109 @@
 //! - Auto-update system
 
 use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::info;
-use tracing_subscriber;
+
+const USAGE: &str = "Usage: jxpoolminer [benchmark [--duration SECS]]";
+
+/// Seconds each device and algorithm is benchmarked for by default.
+const BENCHMARK_SECS: f64 = 30.0;
+
+enum Command {
+    Run,
+    Benchmark { duration: Duration },
+}
+
+fn parse_args() -> Result<Command> {
+    let mut args = std::env::args().skip(1);
+    let command = match args.next().as_deref() {
+        None => return Ok(Command::Run),
+        Some("benchmark") => {
+            let mut duration = Duration::from_secs_f64(BENCHMARK_SECS);
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--duration" => {
+                        let value = args.next().ok_or_else(|| anyhow::anyhow!("--duration needs a value\n{}", USAGE))?;
+                        duration = Duration::try_from_secs_f64(value.parse()?)
+                            .ok()
+                            .filter(|d| !d.is_zero())
+                            .ok_or_else(|| anyhow::anyhow!("--duration must be a positive number of seconds, not {}", value))?;
+                    }
+                    other => anyhow::bail!("Unknown argument {}\n{}", other, USAGE),
+                }
+            }
+            Command::Benchmark { duration }
+        }
+        Some("--help" | "-h") => {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+        Some(other) => anyhow::bail!("Unknown command {}\n{}", other, USAGE),
+    };
+    Ok(command)
+}
+
+/// Benchmarks every supported device on each of its algorithms, prints the
+/// results and stores them for later runs.
+async fn benchmark(devices: &[jxpoolminer_core::Device], duration: Duration) -> Result<()> {
+    let mut stored = jxpoolminer_config::load_benchmarks()?;
+    // Power comes from the same sensors the collector is fed from
+    let telemetry = std::sync::Mutex::new(jxpoolminer_devices::Telemetry::new(devices));
+    let power: jxpoolminer_mining::PowerReader = Arc::new(move |device: &jxpoolminer_core::Device| {
+        let readings = telemetry.lock().unwrap().read();
+        readings.get(&device.id).and_then(|r| r.power).map(f64::from)
+    });
+    let results = jxpoolminer_mining::Benchmark::new(duration)
+        .with_power_reader(power)
+        .run_all(devices)
+        .await;
+    
+    println!("{:<12} {:<10} {:>16} {:>10} {:>14}", "Device", "Algorithm", "Hashrate (H/s)", "Variation", "H/s per watt");
+    for result in results {
+        let per_watt = result.hashes_per_watt().map_or("-".to_string(), |h| format!("{:.0}", h));
+        println!(
+            "{:<12} {:<10} {:>16.0} {:>9.1}% {:>14}",
+            result.device_id, jxpoolminer_mining::algorithms::registry().name(&result.algorithm), result.hashrate, result.variation() * 100.0, per_watt,
+        );
+        stored.record(result);
+    }
+    for device in devices.iter().filter(|device| !jxpoolminer_mining::Benchmark::supports(device)) {
+        println!("{:<12} unsupported: only CPUs can be benchmarked", device.id);
+    }
+    
+    jxpoolminer_config::save_benchmarks(&stored)?;
+    info!("💾 Benchmark results saved to {}", jxpoolminer_config::benchmarks_path().display());
+    Ok(())
+}
+
+/// Searches `range` for ASICs, logging progress as it goes.
+async fn scan_for_asics(range: &str) -> Result<Vec<jxpoolminer_devices::scan::Discovered>> {
+    info!("📡 Scanning {} for ASIC miners...", range);
+    let scan = jxpoolminer_devices::scan::Scanner::new().start(range.parse()?);
+    let mut progress = scan.progress();
+    let report = tokio::spawn(async move {
+        while progress.changed().await.is_ok() {
+            let p = *progress.borrow_and_update();
+            if p.scanned == p.total || p.scanned.is_multiple_of(64) {
+                info!("  {}/{} hosts scanned, {} miner(s) found", p.scanned, p.total, p.found);
+            }
+        }
+    });
+    let found = scan.finish().await?;
+    report.abort();
+    Ok(found)
+}
+
+/// Pool worker name of a network ASIC, from its stable `asic-<host>` device
+/// ID so its pool stats survive restarts and changes to the ASIC list.
+/// Characters pools may treat as separators become `-`.
+fn asic_worker_name(worker_name: &str, device_id: &str) -> String {
+    let suffix: String = device_id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    format!("{}-{}", worker_name, suffix)
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -19,6 +120,7 @@ async fn main() -> Result<()> {
         .with_target(false)
         .init();
 
+    let command = parse_args()?;
     info!("🚀 JxPoolMiner v{} starting...", env!("CARGO_PKG_VERSION"));
     
     // Load configuration
@@ -27,7 +129,7 @@ async fn main() -> Result<()> {
     
     // Detect devices
     info!("🔍 Detecting mining devices...");
-    let devices = jxpoolminer_devices::detect_all().await?;
+    let mut devices = jxpoolminer_devices::detect_all().await?;
     
     if devices.is_empty() {
         anyhow::bail!("❌ No mining devices detected. Please check your hardware and drivers.");
@@ -39,9 +141,25 @@ async fn main() -> Result<()> {
         info!("  - {} ({:?})", device.name, device.device_type);
     }
     
+    if let Command::Benchmark { duration } = command {
+        info!("⏱️  Benchmarking devices for {:?} per algorithm...", duration);
+        return benchmark(&devices, duration).await;
+    }
+    
+    // Measured hashrates replace the detection estimates
+    match jxpoolminer_config::load_benchmarks() {
+        Ok(benchmarks) => benchmarks.apply(&mut devices),
+        Err(e) => tracing::warn!("Ignoring stored benchmark results: {}", e),
+    }
+    
     // Initialize mining engine
     info!("⚙️  Initializing mining engine...");
-    let mining_engine = jxpoolminer_mining::Engine::new(devices.clone())?;
+    let mut mining_engine = jxpoolminer_mining::Engine::new(devices.clone())?;
+    if let Some(threads) = config.mining.cpu_threads {
+        mining_engine = mining_engine.with_cpu_threads(threads);
+    }
+    mining_engine = mining_engine.with_device_settings(config.mining.devices.clone());
+    let mining_engine = Arc::new(mining_engine);
     
     // Connect to pool
     info!("🌐 Connecting to pool: {}", config.pool.primary);
@@ -51,23 +169,83 @@ async fn main() -> Result<()> {
         wallet_address: config.pool.wallet_address.clone(),
         worker_name: config.pool.worker_name.clone(),
         use_tls: config.pool.use_tls,
+        tls: jxpoolminer_pool::TlsConfig {
+            ca_file: config.pool.tls_ca_file.clone(),
+            pinned_sha256: config.pool.tls_pinned_sha256.clone(),
+            insecure: config.pool.tls_insecure,
+        },
+        reconnect: jxpoolminer_pool::ReconnectPolicy::default(),
+        request_timeout: std::time::Duration::from_secs(30),
+        extranonce_subscribe: true,
+        algorithm: config.pool.algorithm.clone(),
+        algorithm_version: config.pool.algorithm_version,
     };
-    let pool_client = jxpoolminer_pool::Client::connect(&pool_config).await?;
+    let pool_client = Arc::new(jxpoolminer_pool::Client::connect(&pool_config).await?);
+    
+    // Network ASICs mine on their own; they are pointed at the pool and
+    // watched, not driven by the engine
+    let mut asic_addresses = config.mining.asics.clone();
+    let mut web_only_asics = Vec::new();
+    if let Some(range) = &config.mining.asic_scan {
+        for discovered in scan_for_asics(range).await? {
+            match discovered.api_address {
+                Some(address) => asic_addresses.push(address),
+                None => web_only_asics.push(discovered.device),
+            }
+        }
+    }
+    let mut asics = jxpoolminer_devices::asic::detect_network(&asic_addresses).await;
+    let mut seen = std::collections::HashSet::new();
+    asics.retain(|asic| seen.insert(asic.device.id.clone()));
+    for asic in &asics {
+        let worker = jxpoolminer_pool::PoolConfig {
+            worker_name: asic_worker_name(&config.pool.worker_name, &asic.device.id),
+            ..pool_config.clone()
+        };
+        match asic.client().use_pool(&config.pool.primary, &worker.username(), "x").await {
+            Ok(_) => info!("⛏️  {} now mining on {}", asic.device.name, config.pool.primary),
+            Err(e) => tracing::warn!("Cannot point {} at the pool: {}", asic.device.name, e),
+        }
+    }
     
     // Initialize statistics collector
     info!("📊 Starting statistics collector...");
-    let stats_collector = jxpoolminer_stats::Collector::new();
+    let stats_collector = Arc::new(jxpoolminer_stats::Collector::new());
+    
+    // Wire pool jobs, the engine and share submission together
+    let coordinator = Arc::new(jxpoolminer_mining::Coordinator::new(
+        mining_engine.clone(),
+        pool_client.clone(),
+        stats_collector.clone(),
+    ));
+    
+    // Sensors feed the collector and, through it, thermal protection
+    let telemetry = jxpoolminer_devices::Telemetry::new(&devices);
+    let thermal = jxpoolminer_mining::ThermalController::new(mining_engine, stats_collector.clone())
+        .spawn(telemetry.subscribe());
+    let telemetry = telemetry.spawn(stats_collector.clone());
+    devices.extend(asics.iter().map(|asic| asic.device.clone()));
+    devices.extend(web_only_asics.into_iter().filter(|d| seen.insert(d.id.clone())));
+    let asic_monitor = jxpoolminer_devices::asic::monitor(asics, stats_collector.clone());
+    if config.app.auto_start {
+        info!("⛏️  Auto-starting mining on all devices...");
+        coordinator.start_all().await?;
+    }
     
     // Start GUI
     info!("🖥️  Launching GUI...");
     jxpoolminer_gui::run(
         config,
         devices,
-        mining_engine,
+        coordinator.clone(),
         pool_client,
         stats_collector,
     ).await?;
     
+    coordinator.shutdown().await;
+    telemetry.abort();
+    thermal.abort();
+    asic_monitor.abort();
     info!("👋 JxPoolMiner shutting down...");
     Ok(())
 }
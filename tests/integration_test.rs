@@ -1,7 +1,5 @@
-use jxpoolminer_devices;
-use jxpoolminer_mining;
-use jxpoolminer_pool;
-use jxpoolminer_config;
+use std::time::Duration;
+
 
 #[tokio::test]
 async fn test_device_detection() {
@@ -13,24 +11,26 @@ async fn test_device_detection() {
 #[tokio::test]
 async fn test_mining_engine() {
     let devices = jxpoolminer_devices::detect_all().await.unwrap();
-    let engine = jxpoolminer_mining::Engine::new(devices).unwrap();
+    let _engine = jxpoolminer_mining::Engine::new(devices).unwrap();
     println!("✅ Mining engine created");
 }
 
 #[tokio::test]
 async fn test_pool_connection() {
-    let config = jxpoolminer_pool::PoolConfig::default();
-    // Pool connection will fail if pool doesn't exist (expected in test environment)
-    match jxpoolminer_pool::Client::connect(&config).await {
-        Ok(client) => {
-            assert!(client.is_connected().await);
-            println!("✅ Pool connection established");
-        }
-        Err(_) => {
-            // Expected to fail in test environment without real pool
-            println!("✅ Pool connection test skipped (no real pool available)");
-        }
-    }
+    use jxpoolminer_mockpool::{MockPool, MockPoolConfig};
+
+    let pool = MockPool::start(MockPoolConfig::default()).await.unwrap();
+    let config = mock_pool_config(&pool);
+    let client = jxpoolminer_pool::Client::connect(&config).await.unwrap();
+    assert!(client.is_connected().await);
+    
+    let job = tokio::time::timeout(Duration::from_secs(5), client.receive_job()).await.unwrap().unwrap();
+    assert!(job.clean_jobs);
+    assert_eq!(job.extranonce1, vec![0, 0, 0, 1]);
+    
+    let stats = pool.stats();
+    assert_eq!((stats.connections, stats.subscribes, stats.authorizes, stats.notifies), (1, 1, 1, 1));
+    println!("✅ Pool connection established");
 }
 
 #[tokio::test]
@@ -39,3 +39,375 @@ async fn test_config_loading() {
     assert_eq!(config.app.theme, "dark");
     println!("✅ Configuration loaded");
 }
+
+#[test]
+fn test_notify_to_header_known_answers() {
+    use jxpoolminer_mining::header;
+    use jxpoolminer_pool::stratum::job::parse_notify;
+
+    // Block 125552: decode the header fields from their Stratum encoding and
+    // pair them with the block's merkle root.
+    let params = serde_json::json!([
+        "1e8d",
+        "ab02cd818b9e567ee21793cddef299feb29ad444a41b85b8000008a300000000",
+        "", "", [],
+        "00000001", "1a44b9f2", "4dd7f5c7", true
+    ]);
+    let job = parse_notify(params.as_array().unwrap(), jxpoolminer_core::Algorithm::SHA256).unwrap();
+    let mut root: [u8; 32] = hex::decode("2b12fcf1b09288fcaff797d71e950e71ae42b91e8bdb2304758dfcffc2b620e3")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    root.reverse();
+    let block = header::build_header(&job, &root, 2504433986);
+    assert_eq!(
+        header::display_hash(&header::sha256d(&block)),
+        "00000000000000001e8d6829a8a21adc5d38d0a473b144b6765798e61f98bd1d"
+    );
+
+    // Genesis block as a notify, the coinbase message split around the extranonces.
+    let params = serde_json::json!([
+        "0",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d010445",
+        "732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000",
+        [],
+        "00000001", "1d00ffff", "495fab29", true
+    ]);
+    let mut job = parse_notify(params.as_array().unwrap(), jxpoolminer_core::Algorithm::SHA256).unwrap();
+    job.extranonce1 = hex::decode("54686520").unwrap();
+    job.extranonce2_size = 4;
+    let mut block = header::header_for(&job, &hex::decode("54696d65").unwrap());
+    block[header::NONCE_OFFSET..].copy_from_slice(&2083236893u32.to_le_bytes());
+    assert_eq!(
+        header::display_hash(&header::sha256d(&block)),
+        "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+    );
+}
+
+mod tls_pool {
+    use jxpoolminer_pool::{Client, ConnectionState, PoolConfig, TlsConfig};
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    struct Certificates {
+        ca_pem: String,
+        leaf_der: CertificateDer<'static>,
+        leaf_key: PrivateKeyDer<'static>,
+    }
+
+    fn certificates() -> Certificates {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(vec![]).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let leaf_key = KeyPair::generate().unwrap();
+        let leaf = CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()])
+            .unwrap()
+            .signed_by(&leaf_key, &ca, &ca_key)
+            .unwrap();
+
+        Certificates {
+            ca_pem: ca.pem(),
+            leaf_der: leaf.der().clone(),
+            leaf_key: PrivatePkcs8KeyDer::from(leaf_key.serialize_der()).into(),
+        }
+    }
+
+    /// TLS Stratum stand-in answering subscribe/authorize on every connection.
+    async fn spawn_tls_pool(certificates: &Certificates) -> u16 {
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![certificates.leaf_der.clone()], certificates.leaf_key.clone_key())
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(stream) = acceptor.accept(socket).await else {
+                        return;
+                    };
+                    let (reader, mut writer) = tokio::io::split(stream);
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                        let result = if request["method"] == "mining.subscribe" {
+                            serde_json::json!([[], "00000000", 4])
+                        } else {
+                            serde_json::json!(true)
+                        };
+                        let reply = serde_json::json!({"id": request["id"], "result": result, "error": null});
+                        if writer.write_all(format!("{}\n", reply).as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    fn config(primary: String, use_tls: bool, tls: TlsConfig) -> PoolConfig {
+        PoolConfig {
+            primary,
+            fallback: None,
+            use_tls,
+            tls,
+            request_timeout: std::time::Duration::from_secs(5),
+            ..PoolConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tls_with_custom_ca_bundle() {
+        let certificates = certificates();
+        let port = spawn_tls_pool(&certificates).await;
+        let ca_file = std::env::temp_dir().join(format!("jxpoolminer-test-ca-{}-{}.pem", std::process::id(), port));
+        std::fs::write(&ca_file, &certificates.ca_pem).unwrap();
+
+        let tls = TlsConfig {
+            ca_file: Some(ca_file.clone()),
+            ..TlsConfig::default()
+        };
+        let client = Client::connect(&config(format!("stratum+ssl://localhost:{}", port), false, tls)).await;
+        let _ = std::fs::remove_file(&ca_file);
+
+        assert_eq!(client.unwrap().state(), ConnectionState::Connected);
+    }
+
+    #[tokio::test]
+    async fn test_tls_rejects_untrusted_certificate() {
+        let certificates = certificates();
+        let port = spawn_tls_pool(&certificates).await;
+
+        let url = format!("stratum+tcp://localhost:{}", port);
+        assert!(Client::connect(&config(url.clone(), true, TlsConfig::default())).await.is_err());
+
+        let insecure = TlsConfig {
+            insecure: true,
+            ..TlsConfig::default()
+        };
+        assert!(Client::connect(&config(url, true, insecure)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tls_certificate_pinning() {
+        let certificates = certificates();
+        let port = spawn_tls_pool(&certificates).await;
+        let url = format!("stratum+tls://127.0.0.1:{}", port);
+
+        let pinned = TlsConfig {
+            pinned_sha256: Some(jxpoolminer_pool::tls::fingerprint(&certificates.leaf_der)),
+            ..TlsConfig::default()
+        };
+        assert!(Client::connect(&config(url.clone(), false, pinned)).await.is_ok());
+
+        let wrong_pin = TlsConfig {
+            pinned_sha256: Some("00".repeat(32)),
+            ..TlsConfig::default()
+        };
+        assert!(Client::connect(&config(url, false, wrong_pin)).await.is_err());
+    }
+}
+
+fn mock_pool_config(pool: &jxpoolminer_mockpool::MockPool) -> jxpoolminer_pool::PoolConfig {
+    jxpoolminer_pool::PoolConfig {
+        primary: pool.url(),
+        fallback: None,
+        request_timeout: Duration::from_secs(5),
+        reconnect: jxpoolminer_pool::ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+            ..jxpoolminer_pool::ReconnectPolicy::default()
+        },
+        ..jxpoolminer_pool::PoolConfig::default()
+    }
+}
+
+/// Brute-forces a share for `job` the way the pool will check it. Client
+/// jobs carry no target; the engine derives it the same way.
+fn find_share(job: &jxpoolminer_core::MiningJob, extranonce2: &[u8]) -> u32 {
+    use jxpoolminer_mining::header::{self, NONCE_OFFSET};
+
+    let target = jxpoolminer_mining::algorithms::registry().resolve(&job.algorithm).unwrap().target_for_difficulty(job.difficulty);
+    let mut block = header::header_for(job, extranonce2);
+    (0..u32::MAX)
+        .find(|nonce| {
+            block[NONCE_OFFSET..].copy_from_slice(&nonce.to_le_bytes());
+            let mut value = header::sha256d(&block);
+            value.reverse();
+            jxpoolminer_core::meets_target(&value, &target)
+        })
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_mock_pool_validates_client_shares() {
+    use jxpoolminer_core::Share;
+    use jxpoolminer_mockpool::{MockPool, MockPoolConfig};
+
+    let pool = MockPool::start(MockPoolConfig::with_difficulty(1.0 / 1_048_576.0)).await.unwrap();
+    let client = jxpoolminer_pool::Client::connect(&mock_pool_config(&pool)).await.unwrap();
+    let job = tokio::time::timeout(Duration::from_secs(5), client.receive_job()).await.unwrap().unwrap();
+    
+    let extranonce2 = vec![0, 0, 0, 9];
+    let nonce = find_share(&job, &extranonce2);
+    let mut share = Share::new(job.id.clone(), nonce as u64, vec![], "cpu-0".to_string(), job.difficulty);
+    share.extranonce2 = extranonce2;
+    share.ntime = job.ntime;
+    
+    assert!(client.submit_share(share.clone()).await.unwrap());
+    assert!(!client.submit_share(share.clone()).await.unwrap(), "duplicate accepted");
+    
+    pool.reject_next(1);
+    share.nonce += 1;
+    assert!(!client.submit_share(share).await.unwrap());
+    
+    let stats = pool.stats();
+    assert_eq!((stats.submits, stats.accepted, stats.duplicates, stats.injected_rejects), (3, 1, 1, 1));
+}
+
+#[tokio::test]
+async fn test_client_survives_malformed_lines_and_disconnects() {
+    use jxpoolminer_mockpool::{Action, MockPool, MockPoolConfig};
+    use jxpoolminer_pool::ConnectionState;
+
+    let pool = MockPool::start(MockPoolConfig::default()).await.unwrap();
+    let client = jxpoolminer_pool::Client::connect(&mock_pool_config(&pool)).await.unwrap();
+    
+    pool.broadcast(Action::Raw("{not json".to_string()));
+    pool.broadcast(Action::SetDifficulty(8.0));
+    pool.broadcast(Action::Notify { clean_jobs: true });
+    pool.wait_until(Duration::from_secs(5), |stats| stats.notifies == 2).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while client.difficulty().await != 8.0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }).await.expect("difficulty after malformed line not applied");
+    assert_eq!(client.state(), ConnectionState::Connected);
+    
+    pool.broadcast(Action::Disconnect);
+    pool.wait_until(Duration::from_secs(5), |stats| stats.subscribes == 2 && stats.notifies == 3).await.unwrap();
+    let mut state = client.state_changes();
+    tokio::time::timeout(Duration::from_secs(5), state.wait_for(|state| *state == ConnectionState::Connected))
+        .await
+        .expect("client did not reconnect")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_engine_shares_reach_pool_and_collector() {
+    use jxpoolminer_core::{Device, DeviceType};
+    use jxpoolminer_mockpool::{MockPool, MockPoolConfig};
+
+    let pool = MockPool::start(MockPoolConfig::with_difficulty(1.0 / 65536.0)).await.unwrap();
+    let client = jxpoolminer_pool::Client::connect(&mock_pool_config(&pool)).await.unwrap();
+    let collector = jxpoolminer_stats::Collector::new();
+    let device = Device::new("cpu-0".to_string(), "Test CPU".to_string(), DeviceType::CPU { cores: 1 });
+    let engine = jxpoolminer_mining::Engine::new(vec![device]).unwrap();
+    
+    let job = tokio::time::timeout(Duration::from_secs(5), client.receive_job()).await.unwrap().unwrap();
+    engine.start_mining("cpu-0", job).await.unwrap();
+    
+    let mut submitted = 0;
+    tokio::time::timeout(Duration::from_secs(60), async {
+        while submitted < 3 {
+            match engine.get_share("cpu-0").await {
+                Some(share) => {
+                    let work = share.difficulty;
+                    let accepted = client.submit_share(share).await.unwrap();
+                    collector.record_share("cpu-0", accepted, work).await;
+                    submitted += 1;
+                }
+                None => tokio::time::sleep(Duration::from_millis(5)).await,
+            }
+        }
+    }).await.expect("engine found no shares");
+    engine.stop_mining("cpu-0").await.unwrap();
+    
+    let pool_stats = pool.stats();
+    let global = collector.global_stats().await;
+    assert_eq!(pool_stats.submits, 3);
+    assert_eq!(global.total_shares, pool_stats.submits);
+    assert_eq!(global.accepted_shares, pool_stats.accepted);
+    assert_eq!(global.rejected_shares, pool_stats.rejected);
+}
+
+#[tokio::test]
+async fn test_coordinator_mines_pool_jobs_on_all_devices() {
+    use jxpoolminer_core::{Device, DeviceStatus, DeviceType};
+    use jxpoolminer_mining::{Coordinator, Engine};
+    use jxpoolminer_mockpool::{MockPool, MockPoolConfig};
+    use std::sync::Arc;
+
+    let pool = MockPool::start(MockPoolConfig::with_difficulty(1.0 / 65536.0)).await.unwrap();
+    let client = Arc::new(jxpoolminer_pool::Client::connect(&mock_pool_config(&pool)).await.unwrap());
+    let collector = Arc::new(jxpoolminer_stats::Collector::new());
+    let devices = (0..2)
+        .map(|i| Device::new(format!("cpu-{}", i), "Test CPU".to_string(), DeviceType::CPU { cores: 1 }))
+        .collect();
+    let engine = Arc::new(Engine::new(devices).unwrap());
+    let coordinator = Coordinator::new(engine.clone(), client.clone(), collector.clone());
+    
+    assert!(coordinator.start_device("gpu-9").await.is_err());
+    coordinator.start_all().await.unwrap();
+    
+    tokio::time::timeout(Duration::from_secs(60), async {
+        loop {
+            let stats = collector.all_device_stats().await;
+            let both_submitted = ["cpu-0", "cpu-1"].iter().all(|id| {
+                stats.get(*id).is_some_and(|s| s.shares_accepted + s.shares_rejected > 0)
+            });
+            if both_submitted {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("coordinator did not submit shares for every device");
+    assert!(engine.devices().await.iter().all(|d| d.status == DeviceStatus::Mining));
+    
+    // A new job reaches every device without any further calls.
+    let mut jobs = client.jobs();
+    jobs.borrow_and_update();
+    pool.broadcast(jxpoolminer_mockpool::Action::Notify { clean_jobs: true });
+    tokio::time::timeout(Duration::from_secs(5), jobs.changed()).await.unwrap().unwrap();
+    let new_job = jobs.borrow().clone().unwrap().id;
+    let received_at = std::time::Instant::now();
+    tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            let mut switched = true;
+            for id in ["cpu-0", "cpu-1"] {
+                switched &= engine.current_job(id).await.as_deref() == Some(new_job.as_str());
+            }
+            if switched {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }).await.unwrap_or_else(|_| panic!("devices not on job {} after {:?}", new_job, received_at.elapsed()));
+    let before = pool.stats().submits;
+    pool.wait_until(Duration::from_secs(60), |stats| stats.submits > before).await.unwrap();
+    
+    coordinator.shutdown().await;
+    coordinator.shutdown().await;
+    assert!(coordinator.start_device("cpu-0").await.is_err());
+    assert!(engine.devices().await.iter().all(|d| d.status == DeviceStatus::Idle));
+    assert!(engine.hashes_done("cpu-0").await > 0);
+    assert!(engine.hashes_done("cpu-1").await > 0);
+    
+    let pool_stats = pool.stats();
+    let global = collector.global_stats().await;
+    assert_eq!(global.total_shares, pool_stats.submits);
+    assert_eq!(global.accepted_shares, pool_stats.accepted);
+    assert_eq!(global.rejected_shares, pool_stats.rejected);
+}